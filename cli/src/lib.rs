@@ -5,7 +5,7 @@ use std::{
 
 use common::{
     cards::{Card, Suit},
-    game::{CardFromDeck, CardSelector, GamePrinter, GameState},
+    game::{CardFromDeck, CardSelector, GameOutcome, GamePrinter, GameState},
 };
 use crossterm::{
     cursor,
@@ -72,13 +72,26 @@ impl GamePrinter for CliPrinter {
         })()
         .unwrap();
     }
+
+    fn print_outcome(&mut self, outcome: &GameOutcome) {
+        let mut stdout = io::stdout();
+        stdout
+            .queue(cursor::MoveTo(20, 21))
+            .unwrap()
+            .queue(style::PrintStyledContent(outcome.to_string().cyan()))
+            .unwrap();
+        stdout.queue(cursor::MoveTo(0, HEIGHT)).unwrap();
+        stdout.flush().unwrap();
+    }
 }
 
 fn print_cards(cards: &Vec<Card>) -> io::Result<()> {
     let mut stdout = io::stdout();
     for card in cards {
         let card_str = card.to_string() + " ";
-        if card.suit == Suit::Hearts || card.suit == Suit::Diamonds {
+        if card.suit == Suit::Stars {
+            stdout.queue(style::PrintStyledContent(card_str.yellow()))?;
+        } else if card.suit.is_red() {
             stdout.queue(style::PrintStyledContent(card_str.red()))?;
         } else {
             stdout.queue(style::PrintStyledContent(card_str.white()))?;
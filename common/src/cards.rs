@@ -1,15 +1,19 @@
 use std::{error::Error, fmt::Display};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum Suit {
     Spades = 0,
     Hearts,
     Diamonds,
     Clubs,
+    /// Fifth suit of five-suit decks.
+    Stars,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum Rank {
     Two = 2,
@@ -25,14 +29,84 @@ pub enum Rank {
     Queen,
     King,
     Ace,
+    Joker,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// Suit of a joker only gives its colour, black joker is a spade and red joker a heart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Card {
     pub rank: Rank,
     pub suit: Suit,
 }
 
+/*
+ * Cards are identified by codes, which are shared with `crypto::deck::DeckSpec`.
+ * Codes 0..52 are the standard deck ordered by suits and ranks, followed by
+ * the black and the red joker and by the 13 cards of the fifth suit.
+ * Copies of a card in multi-deck games have the same code.
+ */
+
+/// Number of cards in the standard deck.
+pub const STANDARD_CARDS: usize = 52;
+pub const BLACK_JOKER: usize = 52;
+pub const RED_JOKER: usize = 53;
+/// Code of the two of stars, the fifth suit follows the jokers.
+pub const STARS_START: usize = 54;
+/// Number of valid card codes.
+pub const CARD_CODES: usize = STARS_START + RANKS.len();
+
+const SUITS: [Suit; 4] = [Suit::Spades, Suit::Hearts, Suit::Diamonds, Suit::Clubs];
+const RANKS: [Rank; 13] = [
+    Rank::Two,
+    Rank::Three,
+    Rank::Four,
+    Rank::Five,
+    Rank::Six,
+    Rank::Seven,
+    Rank::Eight,
+    Rank::Nine,
+    Rank::Ten,
+    Rank::Jack,
+    Rank::Queen,
+    Rank::King,
+    Rank::Ace,
+];
+
+impl Suit {
+    pub fn is_red(self) -> bool {
+        matches!(self, Suit::Hearts | Suit::Diamonds)
+    }
+}
+
+impl Card {
+    pub fn joker(red: bool) -> Self {
+        Card {
+            rank: Rank::Joker,
+            suit: if red { Suit::Hearts } else { Suit::Spades },
+        }
+    }
+
+    pub fn is_joker(&self) -> bool {
+        self.rank == Rank::Joker
+    }
+
+    /// Code of the card, inverse of `Card::try_from`.
+    pub fn code(&self) -> usize {
+        if self.is_joker() {
+            return if self.suit.is_red() {
+                RED_JOKER
+            } else {
+                BLACK_JOKER
+            };
+        }
+        let rank = RANKS.iter().position(|r| *r == self.rank).unwrap();
+        match SUITS.iter().position(|s| *s == self.suit) {
+            Some(suit) => suit * RANKS.len() + rank,
+            None => STARS_START + rank,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum PlayingCard {
     Hidden,
@@ -47,6 +121,7 @@ impl Display for Rank {
             Rank::Queen => "Q".fmt(f),
             Rank::King => "K".fmt(f),
             Rank::Ace => "A".fmt(f),
+            Rank::Joker => "🃏".fmt(f),
             _ => unreachable!(),
         }
     }
@@ -59,6 +134,7 @@ impl Display for Suit {
             Suit::Hearts => "♥",
             Suit::Diamonds => "♦",
             Suit::Clubs => "♣",
+            Suit::Stars => "★",
         }
         .fmt(f)
     }
@@ -67,6 +143,10 @@ impl Display for Suit {
 impl Display for Card {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let Card { rank, suit } = self;
+        // black and red jokers have their own code points
+        if self.is_joker() {
+            return if suit.is_red() { "🂿" } else { "🃏" }.fmt(f);
+        }
         write!(f, "{}{}", rank, suit)
     }
 }
@@ -96,13 +176,22 @@ impl Error for ParseCardError {}
 impl TryFrom<usize> for Card {
     type Error = ParseCardError;
     fn try_from(value: usize) -> Result<Self, Self::Error> {
-        if value >= 52 {
-            return Err(ParseCardError { index: value });
+        match value {
+            BLACK_JOKER => Ok(Card::joker(false)),
+            RED_JOKER => Ok(Card::joker(true)),
+            _ if value < STANDARD_CARDS => Ok(Card {
+                suit: *SUITS.get(value / RANKS.len()).unwrap(),
+                rank: *RANKS.get(value % RANKS.len()).unwrap(),
+            }),
+            _ => value
+                .checked_sub(STARS_START)
+                .and_then(|rank| RANKS.get(rank))
+                .map(|&rank| Card {
+                    rank,
+                    suit: Suit::Stars,
+                })
+                .ok_or(ParseCardError { index: value }),
         }
-        Ok(Card {
-            suit: unsafe { ::std::mem::transmute::<u8, Suit>((value / 13) as u8) },
-            rank: unsafe { ::std::mem::transmute::<u8, Rank>((value % 13 + 2) as u8) },
-        })
     }
 }
 
@@ -131,7 +220,29 @@ mod test {
     #[test]
     #[should_panic]
     fn casting_fail() {
-        Card::try_from(52).unwrap();
+        Card::try_from(CARD_CODES).unwrap();
+    }
+
+    #[test]
+    fn special_cards() {
+        assert_eq!(Card::try_from(BLACK_JOKER).unwrap(), Card::joker(false));
+        assert_eq!(Card::try_from(RED_JOKER).unwrap(), Card::joker(true));
+        assert_eq!(
+            Card::try_from(CARD_CODES - 1).unwrap(),
+            Card {
+                rank: Rank::Ace,
+                suit: Suit::Stars
+            }
+        );
+        assert_eq!(format!("{}", Card::joker(false)), "🃏");
+        assert_eq!(format!("{}", Card::joker(true)), "🂿");
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        for code in 0..CARD_CODES {
+            assert_eq!(Card::try_from(code).unwrap().code(), code);
+        }
     }
 
     #[test]
@@ -1,3 +1,7 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
 use crate::cards::Card;
 pub trait ShowHandDeck {
     fn show_hand(&self) -> Vec<CardFromDeck>;
@@ -15,11 +19,47 @@ pub struct PlayerBasic {
     pub cards: Vec<Card>,
 }
 
+/// How the game ended for the player.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameOutcome {
+    /// Score of the player and scores of other players ordered by ids.
+    Finished { score: usize, scores: Vec<usize> },
+    /// Other player `player` cheated or left and lost the game.
+    OpponentForfeited { player: usize, reason: String },
+    /// Other players stopped the game, accusing the player of cheating.
+    Forfeited { reason: String },
+    /// Player `player` stopped the game or went silent, nobody is shown to be at fault.
+    Aborted { player: usize, reason: String },
+}
+
+impl Display for GameOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameOutcome::Finished { score, scores } => {
+                write!(
+                    f,
+                    "Game finished, your score {}, others {:?}",
+                    score, scores
+                )
+            }
+            GameOutcome::OpponentForfeited { player, reason } => {
+                write!(f, "Player {} forfeited: {}", player, reason)
+            }
+            GameOutcome::Forfeited { reason } => write!(f, "You forfeited: {}", reason),
+            GameOutcome::Aborted { player, reason } => {
+                write!(f, "Player {} stopped the game: {}", player, reason)
+            }
+        }
+    }
+}
+
 pub trait GamePrinter {
     fn print_game(&mut self, game_state: &GameState);
+    /// Shown once the game ended.
+    fn print_outcome(&mut self, _outcome: &GameOutcome) {}
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct CardFromDeck {
     pub card: Card,
     pub ind: usize,
@@ -28,3 +68,27 @@ pub struct CardFromDeck {
 pub trait CardSelector {
     fn select_card(&mut self, hand: &[CardFromDeck]) -> CardFromDeck;
 }
+
+/// Betting decision of a player, `Raise` is the amount added on top of the current bet.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BetAction {
+    Fold,
+    Check,
+    Call,
+    Raise(u64),
+}
+
+/// Everything the player sees when it has to bet.
+#[derive(Debug, Clone)]
+pub struct BettingState {
+    pub hole_cards: Vec<Card>,
+    pub community_cards: Vec<Card>,
+    pub pot: u64,
+    pub to_call: u64,
+    pub chips: u64,
+    pub max_raise: u64,
+}
+
+pub trait ActionSelector {
+    fn select_action(&mut self, state: &BettingState) -> BetAction;
+}
@@ -0,0 +1,352 @@
+use crate::cards::{Card, Rank, Suit};
+
+/// Order of ranks used when comparing cards in tricks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RankOrder {
+    /// Two is the lowest, ace is the highest.
+    #[default]
+    AceHigh,
+    /// Ace is the lowest, king is the highest.
+    AceLow,
+    /// Ace, ten, king, queen, jack as in Schnapsen, other ranks are below the jack.
+    Schnapsen,
+}
+
+impl RankOrder {
+    /// Strength of `rank`, higher beats lower.
+    /// Jokers have no rank, no card is weaker than a joker.
+    pub fn strength(self, rank: Rank) -> u8 {
+        match (self, rank) {
+            (_, Rank::Joker) => 0,
+            (RankOrder::AceLow, Rank::Ace) => 1,
+            (RankOrder::Schnapsen, Rank::Ten) => Rank::Ace as u8 - 1,
+            (RankOrder::Schnapsen, Rank::Jack | Rank::Queen | Rank::King) => rank as u8 - 2,
+            (RankOrder::Schnapsen, _) if rank < Rank::Ten => rank as u8 - 2,
+            _ => rank as u8,
+        }
+    }
+}
+
+/// Whether `card` beats `best`, the best card of the trick so far.
+/// Only cards of the same suit or trumps can beat it.
+pub fn beats(card: Card, best: Card, trump: Option<Suit>, order: RankOrder) -> bool {
+    if card.suit == best.suit {
+        order.strength(card.rank) > order.strength(best.rank)
+    } else {
+        Some(card.suit) == trump
+    }
+}
+
+/// Position of the card winning `trick`, cards are ordered starting with the led one.
+pub fn trick_winner(trick: &[Card], trump: Option<Suit>, order: RankOrder) -> usize {
+    let mut winner = 0;
+    for (position, &card) in trick.iter().enumerate().skip(1) {
+        if beats(card, *trick.get(winner).unwrap(), trump, order) {
+            winner = position;
+        }
+    }
+    winner
+}
+
+/// Poker hand categories, ordered from the weakest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HandCategory {
+    HighCard,
+    OnePair,
+    TwoPair,
+    ThreeOfAKind,
+    Straight,
+    Flush,
+    FullHouse,
+    FourOfAKind,
+    StraightFlush,
+}
+
+/// Value of the best five-card poker hand, stronger hands compare greater.
+/// `ranks` break ties within a category, most significant first.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HandValue {
+    pub category: HandCategory,
+    pub ranks: Vec<Rank>,
+}
+
+// highest rank of a straight made by distinct `ranks` sorted descending, ace can be low
+fn straight_high(ranks: &[Rank]) -> Option<Rank> {
+    let values = ranks.iter().map(|&r| r as u8).collect::<Vec<_>>();
+    if values
+        .windows(2)
+        .all(|w| w.first().unwrap() - 1 == *w.last().unwrap())
+    {
+        return ranks.first().copied();
+    }
+    if values == [Rank::Ace as u8, 5, 4, 3, 2] {
+        return Some(Rank::Five);
+    }
+    None
+}
+
+fn evaluate_five(cards: &[Card]) -> HandValue {
+    let mut groups: Vec<(usize, Rank)> = vec![];
+    for card in cards {
+        match groups.iter_mut().find(|(_, rank)| *rank == card.rank) {
+            Some((count, _)) => *count += 1,
+            None => groups.push((1, card.rank)),
+        }
+    }
+    groups.sort_by(|a, b| b.cmp(a));
+    let ranks = groups.iter().map(|&(_, rank)| rank).collect::<Vec<_>>();
+    let counts = groups.iter().map(|&(count, _)| count).collect::<Vec<_>>();
+    let flush = cards.iter().all(|c| c.suit == cards.first().unwrap().suit);
+    let straight = if counts.len() == 5 {
+        straight_high(&ranks)
+    } else {
+        None
+    };
+    let category = match (counts.as_slice(), flush, straight) {
+        (_, true, Some(_)) => HandCategory::StraightFlush,
+        ([4, 1], _, _) => HandCategory::FourOfAKind,
+        ([3, 2], _, _) => HandCategory::FullHouse,
+        (_, true, None) => HandCategory::Flush,
+        (_, false, Some(_)) => HandCategory::Straight,
+        ([3, 1, 1], _, _) => HandCategory::ThreeOfAKind,
+        ([2, 2, 1], _, _) => HandCategory::TwoPair,
+        ([2, 1, 1, 1], _, _) => HandCategory::OnePair,
+        _ => HandCategory::HighCard,
+    };
+    HandValue {
+        category,
+        ranks: straight.map_or(ranks, |high| vec![high]),
+    }
+}
+
+/// Best five-card hand which can be made from `cards`.
+/// Jokers are not wild and are left out, they have no rank to make a hand with.
+/// `None` without five cards left or with duplicate cards, which no poker hand has.
+pub fn evaluate_hand(cards: &[Card]) -> Option<HandValue> {
+    let cards = cards
+        .iter()
+        .copied()
+        .filter(|card| !card.is_joker())
+        .collect::<Vec<_>>();
+    let duplicate = cards
+        .iter()
+        .enumerate()
+        .any(|(i, card)| cards.iter().skip(i + 1).any(|other| other == card));
+    if cards.len() < 5 || duplicate {
+        return None;
+    }
+    (0u32..1 << cards.len())
+        .filter(|mask| mask.count_ones() == 5)
+        .map(|mask| {
+            let hand = cards
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, &card)| card)
+                .collect::<Vec<_>>();
+            evaluate_five(&hand)
+        })
+        .max()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hand(cards: &[(Rank, Suit)]) -> Vec<Card> {
+        cards
+            .iter()
+            .map(|&(rank, suit)| Card { rank, suit })
+            .collect()
+    }
+
+    #[test]
+    fn hand_categories() {
+        use Rank::*;
+        use Suit::*;
+        let value = evaluate_hand(&hand(&[
+            (Ace, Spades),
+            (Two, Hearts),
+            (Three, Clubs),
+            (Four, Spades),
+            (Five, Diamonds),
+            (King, Hearts),
+            (King, Clubs),
+        ]))
+        .unwrap();
+        assert_eq!(value.category, HandCategory::Straight);
+        assert_eq!(value.ranks, vec![Five]);
+
+        let value = evaluate_hand(&hand(&[
+            (Ace, Spades),
+            (Ace, Hearts),
+            (King, Clubs),
+            (King, Spades),
+            (King, Diamonds),
+            (Two, Hearts),
+            (Three, Hearts),
+        ]))
+        .unwrap();
+        assert_eq!(value.category, HandCategory::FullHouse);
+        assert_eq!(value.ranks, vec![King, Ace]);
+
+        let value = evaluate_hand(&hand(&[
+            (Nine, Hearts),
+            (Ten, Hearts),
+            (Jack, Hearts),
+            (Queen, Hearts),
+            (King, Hearts),
+            (Ace, Spades),
+            (Two, Hearts),
+        ]))
+        .unwrap();
+        assert_eq!(value.category, HandCategory::StraightFlush);
+        assert_eq!(value.ranks, vec![King]);
+    }
+
+    #[test]
+    fn hand_comparison() {
+        use Rank::*;
+        use Suit::*;
+        let pair_of_kings = evaluate_hand(&hand(&[
+            (King, Spades),
+            (King, Hearts),
+            (Two, Clubs),
+            (Seven, Spades),
+            (Nine, Diamonds),
+        ]))
+        .unwrap();
+        let pair_of_kings_ace = evaluate_hand(&hand(&[
+            (King, Clubs),
+            (King, Diamonds),
+            (Ace, Clubs),
+            (Seven, Hearts),
+            (Nine, Hearts),
+        ]))
+        .unwrap();
+        let two_pair = evaluate_hand(&hand(&[
+            (Two, Spades),
+            (Two, Hearts),
+            (Three, Clubs),
+            (Three, Spades),
+            (Nine, Diamonds),
+        ]))
+        .unwrap();
+        assert!(pair_of_kings < pair_of_kings_ace);
+        assert!(pair_of_kings_ace < two_pair);
+    }
+
+    #[test]
+    fn five_card_hand_counts() {
+        let deck = (0..52)
+            .map(|i| Card::try_from(i).unwrap())
+            .collect::<Vec<_>>();
+        let mut counts = [0usize; 9];
+        let mut hand = Vec::with_capacity(5);
+        for a in 0..52 {
+            for b in a + 1..52 {
+                for c in b + 1..52 {
+                    for d in c + 1..52 {
+                        for e in d + 1..52 {
+                            hand.clear();
+                            hand.extend([a, b, c, d, e].iter().map(|&i| *deck.get(i).unwrap()));
+                            *counts
+                                .get_mut(evaluate_hand(&hand).unwrap().category as usize)
+                                .unwrap() += 1;
+                        }
+                    }
+                }
+            }
+        }
+        assert_eq!(
+            counts,
+            [1302540, 1098240, 123552, 54912, 10200, 5108, 3744, 624, 40]
+        );
+    }
+
+    #[test]
+    fn seven_card_best_hand() {
+        use Rank::*;
+        use Suit::*;
+        let value = evaluate_hand(&hand(&[
+            (Two, Spades),
+            (Two, Hearts),
+            (Two, Clubs),
+            (Seven, Spades),
+            (Seven, Diamonds),
+            (Seven, Hearts),
+            (Ace, Hearts),
+        ]))
+        .unwrap();
+        assert_eq!(value.category, HandCategory::FullHouse);
+        assert_eq!(value.ranks, vec![Seven, Two]);
+    }
+
+    #[test]
+    fn duplicate_cards_are_no_hand() {
+        use Rank::*;
+        use Suit::*;
+        let cards = hand(&[
+            (Ace, Spades),
+            (Ace, Spades),
+            (Ace, Spades),
+            (Ace, Spades),
+            (Ace, Spades),
+            (King, Hearts),
+            (Two, Clubs),
+        ]);
+        assert_eq!(evaluate_hand(&cards), None);
+    }
+
+    #[test]
+    fn trump_tricks() {
+        use Rank::*;
+        use Suit::*;
+        let trick = hand(&[(Ten, Hearts), (Ace, Spades), (Two, Clubs), (King, Hearts)]);
+        assert_eq!(trick_winner(&trick, None, RankOrder::AceHigh), 3);
+        assert_eq!(trick_winner(&trick, Some(Clubs), RankOrder::AceHigh), 2);
+        assert_eq!(trick_winner(&trick, Some(Hearts), RankOrder::AceHigh), 3);
+        assert_eq!(trick_winner(&trick, None, RankOrder::Schnapsen), 0);
+    }
+
+    #[test]
+    fn rank_orders() {
+        use Rank::*;
+        let schnapsen = [Jack, Queen, King, Ten, Ace].map(|r| RankOrder::Schnapsen.strength(r));
+        assert!(schnapsen.windows(2).all(|w| w.first() < w.last()));
+        assert!(RankOrder::Schnapsen.strength(Nine) < RankOrder::Schnapsen.strength(Jack));
+        assert!(RankOrder::AceLow.strength(Ace) < RankOrder::AceLow.strength(Two));
+        assert!(RankOrder::AceHigh.strength(King) < RankOrder::AceHigh.strength(Ace));
+        for order in [RankOrder::AceHigh, RankOrder::AceLow, RankOrder::Schnapsen] {
+            assert!(order.strength(Joker) <= order.strength(Two));
+        }
+    }
+
+    #[test]
+    fn jokers_are_left_out() {
+        use Rank::*;
+        use Suit::*;
+        let mut cards = hand(&[
+            (Ace, Spades),
+            (King, Hearts),
+            (Queen, Clubs),
+            (Jack, Diamonds),
+            (Nine, Spades),
+        ]);
+        cards.push(Card::joker(true));
+        let value = evaluate_hand(&cards).unwrap();
+        assert_eq!(value.category, HandCategory::HighCard);
+        assert_eq!(value.ranks, vec![Ace, King, Queen, Jack, Nine]);
+        cards.truncate(4);
+        cards.extend([Card::joker(true), Card::joker(false), Card::joker(true)]);
+        assert_eq!(evaluate_hand(&cards), None);
+        let trick = [
+            Card::joker(false),
+            Card {
+                rank: Two,
+                suit: Spades,
+            },
+        ];
+        assert_eq!(trick_winner(&trick, None, RankOrder::AceHigh), 1);
+    }
+}
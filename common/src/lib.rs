@@ -1,5 +1,6 @@
 pub mod cards;
 pub mod game;
+pub mod hands;
 
 pub fn add(left: usize, right: usize) -> usize {
     left + right
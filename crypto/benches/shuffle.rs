@@ -0,0 +1,40 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::seq::SliceRandom;
+use rand::{rngs::StdRng, SeedableRng};
+
+use crypto_scg::{
+    encryption::basic_deck,
+    shuffle_v2::{ShuffleProof, ShuffleWithProof},
+    shuffle_v3::ShuffleWithArgument,
+    types::KeyType,
+};
+
+const DECK_SIZES: [usize; 3] = [8, 16, 52];
+
+fn bench_shuffle<S: ShuffleProof>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group(name);
+    group.sample_size(10);
+    let mut rng = StdRng::seed_from_u64(0);
+    for n in DECK_SIZES {
+        let deck = basic_deck()[..n].to_vec();
+        let p_key = KeyType::rand(&mut rng);
+        let mut perm = (0..n).collect::<Vec<_>>();
+        perm.shuffle(&mut rng);
+        group.bench_with_input(BenchmarkId::new("generate", n), &deck, |b, deck| {
+            b.iter(|| S::generate(deck.clone(), &p_key, &perm, &mut rng))
+        });
+        let proof = S::generate(deck.clone(), &p_key, &perm, &mut rng);
+        group.bench_with_input(BenchmarkId::new("verify", n), &deck, |b, deck| {
+            b.iter(|| assert!(proof.verify_step(deck)))
+        });
+    }
+    group.finish();
+}
+
+fn shuffle_proofs(c: &mut Criterion) {
+    bench_shuffle::<ShuffleWithProof>(c, "one_in_n");
+    bench_shuffle::<ShuffleWithArgument>(c, "shuffle_argument");
+}
+
+criterion_group!(benches, shuffle_proofs);
+criterion_main!(benches);
@@ -0,0 +1,67 @@
+use ark_ec::{AffineRepr, CurveConfig};
+use ark_ff::{PrimeField, Zero};
+
+/*
+ * Curve backend is selected with cargo features, exactly one of
+ * `pallas` (default), `bls12_381` and `curve25519` has to be enabled.
+ * Curve25519 backend works in the prime order subgroup of Edwards25519,
+ * points are checked to be in the subgroup when deserialized.
+ */
+
+#[cfg(any(
+    all(feature = "pallas", feature = "bls12_381"),
+    all(feature = "pallas", feature = "curve25519"),
+    all(feature = "bls12_381", feature = "curve25519"),
+))]
+compile_error!("only one curve backend feature can be enabled");
+
+#[cfg(not(any(feature = "pallas", feature = "bls12_381", feature = "curve25519")))]
+compile_error!("one of curve backend features has to be enabled");
+
+#[cfg(feature = "pallas")]
+mod backend {
+    pub type Config = ark_pallas::PallasConfig;
+    pub type Affine = ark_ec::short_weierstrass::Affine<Config>;
+    pub type Projective = ark_ec::short_weierstrass::Projective<Config>;
+    pub const CURVE: &str = "pallas";
+}
+
+#[cfg(feature = "bls12_381")]
+mod backend {
+    pub type Config = ark_test_curves::bls12_381::g1::Config;
+    pub type Affine = ark_ec::short_weierstrass::Affine<Config>;
+    pub type Projective = ark_ec::short_weierstrass::Projective<Config>;
+    pub const CURVE: &str = "bls12_381";
+}
+
+#[cfg(feature = "curve25519")]
+mod backend {
+    pub type Config = ark_ed25519::EdwardsConfig;
+    pub type Affine = ark_ec::twisted_edwards::Affine<Config>;
+    pub type Projective = ark_ec::twisted_edwards::Projective<Config>;
+    pub const CURVE: &str = "curve25519";
+}
+
+/// Name of the curve the crate was built with, players have to agree on it.
+pub use backend::CURVE;
+
+pub(crate) type EncryptedValueType = backend::Projective;
+pub(crate) type KeyTypeType = <backend::Config as CurveConfig>::ScalarField;
+type BaseField = <backend::Config as CurveConfig>::BaseField;
+
+#[cfg(not(feature = "curve25519"))]
+fn point_from_coordinate(x: BaseField) -> Option<backend::Affine> {
+    backend::Affine::get_point_from_x_unchecked(x, false)
+}
+
+#[cfg(feature = "curve25519")]
+fn point_from_coordinate(y: BaseField) -> Option<backend::Affine> {
+    backend::Affine::get_point_from_y_unchecked(y, false)
+}
+
+/// Point in the prime order subgroup with `hash` as its coordinate, if there is one.
+pub(crate) fn point_from_hash(hash: &[u8]) -> Option<EncryptedValueType> {
+    point_from_coordinate(BaseField::from_be_bytes_mod_order(hash))
+        .map(|point| point.clear_cofactor().into())
+        .filter(|point: &EncryptedValueType| !point.is_zero())
+}
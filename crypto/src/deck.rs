@@ -0,0 +1,234 @@
+use ark_ec::Group;
+use common::cards::{BLACK_JOKER, CARD_CODES, RED_JOKER, STARS_START};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::encryption::Translator;
+use crate::types::{EncryptedValue, EncryptedValueType, KeyType};
+
+/*
+ * Deck is an ordered multiset of card codes, every player has to start with the same one.
+ * Codes of cards are defined by `common::cards`, the standard 52 card deck comes first,
+ * followed by jokers and cards of custom suits.
+ * k-th copy of code c is encoded as (k * COPY_STRIDE + c + 1) * g, so the first copy
+ * of every card has the same value as in `basic_deck`.
+ */
+
+/// Encodings of copies of a card are this far apart, room is left for new card codes.
+pub const COPY_STRIDE: usize = 128;
+const _: () = assert!(CARD_CODES <= COPY_STRIDE);
+
+const SUITS: usize = 4;
+const RANKS: usize = 13;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeckSpec {
+    cards: Vec<usize>,
+}
+
+impl DeckSpec {
+    pub fn new(cards: Vec<usize>) -> Self {
+        assert!(cards.iter().all(|&code| code < CARD_CODES));
+        DeckSpec { cards }
+    }
+
+    // all suits with ranks from `lowest` (0 is two) up to the ace
+    fn from_rank(lowest: usize) -> Self {
+        Self::new(
+            (0..SUITS)
+                .flat_map(|suit| (lowest..RANKS).map(move |rank| suit * RANKS + rank))
+                .collect(),
+        )
+    }
+
+    /// Standard 52 card deck.
+    pub fn standard() -> Self {
+        Self::from_rank(0)
+    }
+
+    /// 16 cards from jack to ace.
+    pub fn short() -> Self {
+        Self::from_rank(9)
+    }
+
+    /// 24 cards from nine to ace.
+    pub fn schnapsen() -> Self {
+        Self::from_rank(7)
+    }
+
+    /// 32 cards from seven to ace.
+    pub fn skat() -> Self {
+        Self::from_rank(5)
+    }
+
+    /// Two standard decks.
+    pub fn double() -> Self {
+        Self::standard().repeat(2)
+    }
+
+    /// Two standard decks with four jokers.
+    pub fn canasta() -> Self {
+        Self::double().with_jokers(4)
+    }
+
+    /// Standard deck with the fifth suit of stars, 65 cards.
+    pub fn five_suits() -> Self {
+        Self::new(
+            (0..SUITS * RANKS)
+                .chain(STARS_START..STARS_START + RANKS)
+                .collect(),
+        )
+    }
+
+    /// Deck with `count` more jokers, alternately black and red.
+    pub fn with_jokers(&self, count: usize) -> Self {
+        let jokers = [BLACK_JOKER, RED_JOKER].into_iter().cycle().take(count);
+        Self::new(self.cards.iter().copied().chain(jokers).collect())
+    }
+
+    /// Every card of the deck `count` times.
+    pub fn repeat(&self, count: usize) -> Self {
+        Self::new(self.cards.repeat(count))
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "standard" => Some(Self::standard()),
+            "short" => Some(Self::short()),
+            "schnapsen" => Some(Self::schnapsen()),
+            "skat" => Some(Self::skat()),
+            "double" => Some(Self::double()),
+            "canasta" => Some(Self::canasta()),
+            "five-suits" => Some(Self::five_suits()),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cards.is_empty()
+    }
+
+    pub fn cards(&self) -> &[usize] {
+        &self.cards
+    }
+
+    /// Whether every card is from the standard deck and none of them repeats.
+    pub fn is_standard_subset(&self) -> bool {
+        self.cards.iter().all(|&code| code < SUITS * RANKS)
+            && self.copies().iter().all(|&copy| copy == 0)
+    }
+
+    // number of the copy for every card in the deck
+    fn copies(&self) -> Vec<usize> {
+        let mut seen = [0; CARD_CODES];
+        self.cards
+            .iter()
+            .map(|&code| {
+                let count = seen.get_mut(code).unwrap();
+                *count += 1;
+                *count - 1
+            })
+            .collect()
+    }
+
+    /// Encoded deck, the starting deck of the preparation.
+    pub fn deck(&self) -> Vec<EncryptedValue> {
+        let g = EncryptedValueType::generator();
+        self.cards
+            .iter()
+            .zip(self.copies())
+            .map(|(code, copy)| {
+                let ind = copy * COPY_STRIDE + code + 1;
+                EncryptedValue::new(g * KeyType::from(ind as i64).val)
+            })
+            .collect()
+    }
+
+    /// Translator from decrypted values of the deck to card codes.
+    pub fn translator(&self) -> Translator {
+        Translator::with_codes(self.deck(), self.cards.clone())
+    }
+
+    /// Hash identifying the deck, compared during the connection handshake.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"deck spec");
+        hasher.update((self.cards.len() as u64).to_le_bytes());
+        for &code in self.cards.iter() {
+            hasher.update((code as u64).to_le_bytes());
+        }
+        hasher.finalize().into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use common::cards::{Card, Suit};
+
+    use super::DeckSpec;
+    use crate::encryption::{basic_deck, short_deck};
+
+    #[test]
+    fn deck_sizes() {
+        assert_eq!(DeckSpec::standard().len(), 52);
+        assert_eq!(DeckSpec::short().len(), 16);
+        assert_eq!(DeckSpec::schnapsen().len(), 24);
+        assert_eq!(DeckSpec::skat().len(), 32);
+        assert_eq!(DeckSpec::double().len(), 104);
+        assert_eq!(DeckSpec::canasta().len(), 108);
+        assert_eq!(DeckSpec::five_suits().len(), 65);
+        assert_eq!(DeckSpec::standard().with_jokers(2).len(), 54);
+    }
+
+    #[test]
+    fn standard_subsets() {
+        assert!(DeckSpec::standard().is_standard_subset());
+        assert!(DeckSpec::short().is_standard_subset());
+        assert!(!DeckSpec::double().is_standard_subset());
+        assert!(!DeckSpec::standard().with_jokers(1).is_standard_subset());
+        assert!(!DeckSpec::five_suits().is_standard_subset());
+    }
+
+    #[test]
+    fn compatible_with_fixed_decks() {
+        assert_eq!(DeckSpec::standard().deck(), basic_deck().to_vec());
+        assert_eq!(DeckSpec::short().deck(), short_deck().to_vec());
+    }
+
+    #[test]
+    fn translate_copies() {
+        let spec = DeckSpec::canasta();
+        let deck = spec.deck();
+        let translator = spec.translator();
+        for (value, code) in deck.iter().zip(spec.cards()) {
+            assert_eq!(translator.translate(*value), Some(*code));
+        }
+        let mut unique = deck.clone();
+        unique.sort_by_key(|v| format!("{:?}", v));
+        unique.dedup();
+        assert_eq!(unique.len(), deck.len());
+    }
+
+    #[test]
+    fn five_suits_have_stars() {
+        let stars = DeckSpec::five_suits()
+            .cards()
+            .iter()
+            .filter(|&&code| Card::try_from(code).unwrap().suit == Suit::Stars)
+            .count();
+        assert_eq!(stars, 13);
+    }
+
+    #[test]
+    fn different_hashes() {
+        assert_eq!(DeckSpec::skat().hash(), DeckSpec::skat().hash());
+        assert_ne!(DeckSpec::skat().hash(), DeckSpec::schnapsen().hash());
+        assert_ne!(DeckSpec::standard().hash(), DeckSpec::double().hash());
+        let reversed = DeckSpec::new(DeckSpec::short().cards().iter().rev().copied().collect());
+        assert_ne!(DeckSpec::short().hash(), reversed.hash());
+    }
+}
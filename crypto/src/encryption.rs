@@ -1,13 +1,17 @@
+use std::collections::HashMap;
+
 use ark_ec::Group;
 use ark_ff::Field;
-use ark_std::iterable::Iterable;
-
-use rand::thread_rng;
+use rand::{CryptoRng, RngCore};
 
 use crate::types::{EncryptedValue, EncryptedValueType, KeyType};
 
+/// Code of a card, as defined by `common::cards`.
+pub type CardId = usize;
+
+/// Maps decrypted values back to card codes, values are keyed by their compressed encoding.
 pub struct Translator {
-    cards: [EncryptedValue; 52],
+    cards: HashMap<Vec<u8>, CardId>,
 }
 
 pub fn basic_deck() -> [EncryptedValue; 52] {
@@ -34,11 +38,24 @@ pub fn short_deck() -> [EncryptedValue; 16] {
 }
 
 impl Translator {
-    pub fn new(deck: &[EncryptedValue; 52]) -> Self {
-        Translator { cards: *deck }
+    /// `deck[i]` encodes card with code `i`.
+    pub fn new(deck: &[EncryptedValue]) -> Self {
+        Self::with_codes(deck.to_vec(), (0..deck.len()).collect())
+    }
+    /// `cards[i]` encodes card with code `codes[i]`.
+    pub fn with_codes(cards: Vec<EncryptedValue>, codes: Vec<CardId>) -> Self {
+        assert_eq!(cards.len(), codes.len());
+        Translator {
+            cards: cards
+                .iter()
+                .map(EncryptedValue::to_bytes)
+                .zip(codes)
+                .collect(),
+        }
     }
-    pub fn translate(&self, value: EncryptedValue) -> Option<usize> {
-        self.cards.iter().position(|v| v == value)
+    /// Code of the card encoded by `value`, `None` if it is not in the deck.
+    pub fn translate(&self, value: EncryptedValue) -> Option<CardId> {
+        self.cards.get(&value.to_bytes()).copied()
     }
 }
 
@@ -50,8 +67,8 @@ pub fn decrypt(message: &EncryptedValue, p_key: &KeyType) -> EncryptedValue {
     EncryptedValue::new(message.val * p_key.val.inverse().unwrap())
 }
 
-pub fn rand_key() -> KeyType {
-    KeyType::rand(&mut thread_rng())
+pub fn rand_key<R: RngCore + CryptoRng>(rng: &mut R) -> KeyType {
+    KeyType::rand(rng)
 }
 
 pub fn mul_key(a: &KeyType, b: &KeyType) -> KeyType {
@@ -61,15 +78,15 @@ pub fn mul_key(a: &KeyType, b: &KeyType) -> KeyType {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ark_std::test_rng;
     use ark_std::UniformRand;
+    use rand::{rngs::StdRng, SeedableRng};
 
     #[test]
     fn starting_deck() {
-        let deck = Translator::new(&basic_deck()).cards;
+        let deck = basic_deck();
         let g = EncryptedValue::new(EncryptedValueType::generator());
         for (i, v) in deck.iter().enumerate() {
-            assert_eq!(v, encrypt(&g, &KeyType::from((i + 1) as i64)));
+            assert_eq!(v, &encrypt(&g, &KeyType::from((i + 1) as i64)));
         }
     }
 
@@ -96,9 +113,16 @@ mod tests {
         translator.translate(elem).unwrap();
     }
 
+    #[test]
+    fn translation_outside_deck() {
+        let translator = Translator::with_codes(short_deck().to_vec(), (0..16).collect());
+        assert_eq!(translator.translate(*short_deck().get(5).unwrap()), Some(5));
+        assert_eq!(translator.translate(*basic_deck().first().unwrap()), None);
+    }
+
     #[test]
     fn test_encrypt_decrypt() {
-        let mut rng = test_rng();
+        let mut rng = StdRng::seed_from_u64(0);
         let plaintext = EncryptedValue::new(EncryptedValueType::rand(&mut rng));
         let p_key = KeyType::rand(&mut rng);
         let ciphertext = encrypt(&plaintext, &p_key);
@@ -109,7 +133,7 @@ mod tests {
     #[test]
     #[should_panic]
     fn test_encrypt_decrypt_fail() {
-        let mut rng = test_rng();
+        let mut rng = StdRng::seed_from_u64(0);
         let plaintext = EncryptedValue::new(EncryptedValueType::rand(&mut rng));
         let p_key = KeyType::rand(&mut rng);
         let rand_key = KeyType::rand(&mut rng);
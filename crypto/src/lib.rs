@@ -1,4 +1,8 @@
+pub mod curve;
+pub mod deck;
 pub mod encryption;
 pub mod shuffle;
 pub mod shuffle_v2;
+pub mod shuffle_v3;
+pub mod signature;
 pub mod types;
@@ -1,6 +1,6 @@
-use ark_std::test_rng;
 use crypto_scg::shuffle_v2::ShuffleWithProof;
 use rand::seq::SliceRandom;
+use rand::{rngs::StdRng, SeedableRng};
 
 use crypto_scg::{
     encryption::{basic_deck, decrypt, Translator},
@@ -8,7 +8,7 @@ use crypto_scg::{
 };
 
 fn main() {
-    let mut rng = test_rng();
+    let mut rng = StdRng::seed_from_u64(0);
     let deck = basic_deck().to_vec();
     let p_key = KeyType::rand(&mut rng);
     let mut perm = vec![0usize; deck.len()];
@@ -1,6 +1,6 @@
 use crate::encryption::{decrypt, encrypt, rand_key};
 use crate::types::{EncryptedValue, KeyType, PartyState};
-use rand::{seq::SliceRandom, thread_rng};
+use rand::{seq::SliceRandom, CryptoRng, RngCore};
 
 /*
  * Shuffling proceeds as follows:
@@ -37,22 +37,30 @@ impl PartyBasic {
             deck: vec![],
         }
     }
-    fn encrypt_and_shuffle(&mut self, deck: &mut [EncryptedValue]) {
+    fn encrypt_and_shuffle<R: RngCore + CryptoRng>(
+        &mut self,
+        deck: &mut [EncryptedValue],
+        rng: &mut R,
+    ) {
         assert_eq!(self.state, PartyState::WaitForShuffle, "Illegal state");
-        let key = rand_key();
+        let key = rand_key(rng);
         self.keys.push(key);
         for card in deck.iter_mut() {
             *card = encrypt(card, &key);
         }
-        deck.shuffle(&mut thread_rng());
+        deck.shuffle(rng);
         self.state = PartyState::WaitForEncryption;
     }
-    fn decrypt_encrypt(&mut self, deck: &mut [EncryptedValue]) {
+    fn decrypt_encrypt<R: RngCore + CryptoRng>(
+        &mut self,
+        deck: &mut [EncryptedValue],
+        rng: &mut R,
+    ) {
         assert_eq!(self.state, PartyState::WaitForEncryption, "Illegal state");
         let key = *self.keys.first().unwrap();
         self.keys.pop();
         for card in deck.iter_mut() {
-            let new_key = rand_key();
+            let new_key = rand_key(rng);
             self.keys.push(new_key);
             *card = encrypt(&decrypt(card, &key), &new_key);
         }
@@ -66,10 +74,10 @@ impl PartyBasic {
         };
         self.state = PartyState::Done;
     }
-    pub fn make_turn(&mut self, deck: &mut [EncryptedValue]) {
+    pub fn make_turn<R: RngCore + CryptoRng>(&mut self, deck: &mut [EncryptedValue], rng: &mut R) {
         match self.state {
-            PartyState::WaitForShuffle => self.encrypt_and_shuffle(deck),
-            PartyState::WaitForEncryption => self.decrypt_encrypt(deck),
+            PartyState::WaitForShuffle => self.encrypt_and_shuffle(deck, rng),
+            PartyState::WaitForEncryption => self.decrypt_encrypt(deck, rng),
             PartyState::WaitForDeck => self.pass_deck(deck),
             PartyState::Done => (),
         };
@@ -92,6 +100,8 @@ impl Default for PartyBasic {
 #[cfg(test)]
 mod test {
     use crate::encryption::{basic_deck, Translator};
+    use rand::thread_rng;
+    use rand::{rngs::StdRng, SeedableRng};
 
     use super::*;
 
@@ -107,12 +117,12 @@ mod test {
         }
     }
 
-    fn run_protocol(players: &mut [PartyBasic]) {
+    fn run_protocol<R: RngCore + CryptoRng>(players: &mut [PartyBasic], rng: &mut R) {
         let mut deck = basic_deck().to_vec();
         loop {
             let mut done = true;
             for player in players.iter_mut() {
-                player.make_turn(&mut deck);
+                player.make_turn(&mut deck, rng);
                 done &= player.state == PartyState::Done;
             }
             if done {
@@ -124,7 +134,7 @@ mod test {
     #[test]
     fn check_decks() {
         let mut players = prepare_players();
-        run_protocol(&mut players);
+        run_protocol(&mut players, &mut thread_rng());
         let (decks, keys): (Vec<_>, Vec<_>) =
             players.into_iter().map(|p| p.retrieve_deck()).unzip();
         assert!(decks.windows(2).all(|w| w[0] == w[1]));
@@ -136,7 +146,7 @@ mod test {
     fn decryption() {
         use std::iter::zip;
         let mut players = prepare_players();
-        run_protocol(&mut players);
+        run_protocol(&mut players, &mut thread_rng());
         let mut deck = players.first().unwrap().deck.clone();
         for player in players.iter() {
             for (value, key) in zip(deck.iter_mut(), &player.keys) {
@@ -152,4 +162,14 @@ mod test {
         perm.sort();
         assert_eq!(perm, { 0..52 }.collect::<Vec<usize>>());
     }
+
+    #[test]
+    fn seeded_runs_are_equal() {
+        let run = || {
+            let mut players = prepare_players();
+            run_protocol(&mut players, &mut StdRng::seed_from_u64(0));
+            players.remove(0).retrieve_deck()
+        };
+        assert_eq!(run(), run());
+    }
 }
@@ -1,20 +1,20 @@
 use ark_ec::Group;
 // use ark_ff::field_hashers::{DefaultFieldHasher, HashToField};
 use ark_std::iterable::Iterable;
-use rand::Rng;
-use serde::{Deserialize, Serialize};
+use rand::{CryptoRng, RngCore};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::iter::zip;
 
 use crate::{
-    encryption::encrypt,
+    encryption::{decrypt, encrypt},
     types::{EncryptedValue, EncryptedValueType, KeyType, KeyTypeType},
 };
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub struct ChallProof {
-    challenge: KeyType,
-    proof: KeyType,
+    pub(crate) challenge: KeyType,
+    pub(crate) proof: KeyType,
 }
 
 /*
@@ -22,7 +22,7 @@ pub struct ChallProof {
  * augmented with zero-knowledge step verification.
  */
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ShuffleWithProof {
     pub values_prev: Vec<EncryptedValue>,
     pub values_aftr: Vec<EncryptedValue>,
@@ -45,7 +45,7 @@ fn hash_to_field(hash: &[u8; 32]) -> KeyType {
     KeyType::new(res)
 }
 
-fn calc_hash(mut hasher: Sha256, points: &[EncryptedValue]) -> Sha256 {
+pub(crate) fn calc_hash(mut hasher: Sha256, points: &[EncryptedValue]) -> Sha256 {
     for point in points {
         hasher.update(point.to_string());
     }
@@ -75,10 +75,8 @@ fn one_in_n<R>(
     rng: &mut R,
 ) -> Vec<ChallProof>
 where
-    R: Rng,
+    R: RngCore + CryptoRng,
 {
-    dbg!("one in n");
-    dbg!(&ind);
     let n = points.len();
     let mut res = vec![None; n];
     let p = encrypt(points.get(ind).unwrap(), p_key);
@@ -139,11 +137,11 @@ impl ShuffleWithProof {
     pub fn generate<R>(
         values_prev: Vec<EncryptedValue>,
         p_key: &KeyType,
-        perm: &Vec<usize>,
+        perm: &[usize],
         rng: &mut R,
     ) -> Self
     where
-        R: Rng,
+        R: RngCore + CryptoRng,
     {
         assert_eq!(perm.len(), values_prev.len());
         let g = EncryptedValue::new(EncryptedValueType::generator());
@@ -164,8 +162,8 @@ impl ShuffleWithProof {
         }
     }
 
-    pub fn verify(&self, prev: &Vec<EncryptedValue>) -> bool {
-        if self.values_prev != *prev {
+    pub fn verify(&self, prev: &[EncryptedValue]) -> bool {
+        if self.values_prev != prev {
             return false;
         }
         let g = EncryptedValue::new(EncryptedValueType::generator());
@@ -195,13 +193,65 @@ impl ShuffleWithProof {
     }
 }
 
-fn mask_proof<R>(p: &EncryptedValue, k: &KeyType, pp: &EncryptedValue, rng: &mut R) -> ChallProof
+/// Single verifiable step of the deck preparation.
+pub trait ProofStep {
+    fn verify_step(&self, prev: &[EncryptedValue]) -> bool;
+    fn result(&self) -> &[EncryptedValue];
+}
+
+/// Proof of a shuffle combined with encryption under a single key,
+/// so different proof systems can be used interchangeably.
+pub trait ShuffleProof: ProofStep + Clone + Serialize + DeserializeOwned {
+    fn generate<R: RngCore + CryptoRng>(
+        values_prev: Vec<EncryptedValue>,
+        p_key: &KeyType,
+        perm: &[usize],
+        rng: &mut R,
+    ) -> Self;
+    fn public_key(&self) -> &EncryptedValue;
+}
+
+impl ProofStep for ShuffleWithProof {
+    fn verify_step(&self, prev: &[EncryptedValue]) -> bool {
+        self.verify(prev)
+    }
+    fn result(&self) -> &[EncryptedValue] {
+        &self.values_aftr
+    }
+}
+
+impl ShuffleProof for ShuffleWithProof {
+    fn generate<R: RngCore + CryptoRng>(
+        values_prev: Vec<EncryptedValue>,
+        p_key: &KeyType,
+        perm: &[usize],
+        rng: &mut R,
+    ) -> Self {
+        ShuffleWithProof::generate(values_prev, p_key, perm, rng)
+    }
+    fn public_key(&self) -> &EncryptedValue {
+        &self.public_key
+    }
+}
+
+/*
+ * Chaum-Pedersen proof that log_g(h) = log_p(pp) = k.
+ */
+fn dleq_proof<R>(
+    g: &EncryptedValue,
+    h: &EncryptedValue,
+    p: &EncryptedValue,
+    pp: &EncryptedValue,
+    k: &KeyType,
+    rng: &mut R,
+) -> ChallProof
 where
-    R: Rng,
+    R: RngCore + CryptoRng,
 {
     let r = KeyType::rand(rng);
+    let r_g = encrypt(g, &r);
     let r_p = encrypt(p, &r);
-    let e: KeyType = calc_hash(Sha256::new(), &[*p, *pp, r_p]).into();
+    let e: KeyType = calc_hash(Sha256::new(), &[*g, *h, *p, *pp, r_g, r_p]).into();
     let s = KeyType::new(r.val - (e.val * k.val));
     ChallProof {
         challenge: e,
@@ -209,72 +259,134 @@ where
     }
 }
 
+fn verify_dleq(
+    g: &EncryptedValue,
+    h: &EncryptedValue,
+    p: &EncryptedValue,
+    pp: &EncryptedValue,
+    proof: &ChallProof,
+) -> bool {
+    let ChallProof {
+        challenge: e,
+        proof: s,
+    } = proof;
+    let r_g = linear_combination(e, h, s, g);
+    let r_p = linear_combination(e, pp, s, p);
+    let e_v: KeyType = calc_hash(Sha256::new(), &[*g, *h, *p, *pp, r_g, r_p]).into();
+    *e == e_v
+}
+
 /*
  * Second part of the protocol from shuffle.rs,
  * augmented with zero-knowledge step verification.
+ * Every card key k_i is committed as k_i * public_key, where public_key = p_key * g
+ * comes from the shuffle step, so the commitment is (k_i * p_key) * g.
  */
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EncryptWithProof {
     pub values_prev: Vec<EncryptedValue>,
     pub values_aftr: Vec<EncryptedValue>,
+    pub public_key: EncryptedValue,
+    pub key_commitments: Vec<EncryptedValue>,
     pub proofs: Vec<ChallProof>,
 }
 
 impl EncryptWithProof {
-    pub fn generate<R>(values_prev: Vec<EncryptedValue>, keys: &Vec<KeyType>, rng: &mut R) -> Self
+    pub fn generate<R>(
+        values_prev: Vec<EncryptedValue>,
+        keys: &Vec<KeyType>,
+        public_key: &EncryptedValue,
+        rng: &mut R,
+    ) -> Self
     where
-        R: Rng,
+        R: RngCore + CryptoRng,
     {
-        let (proofs, values_aftr) = zip(values_prev.iter(), keys.iter())
+        let (proofs, (values_aftr, key_commitments)) = zip(values_prev.iter(), keys.iter())
             .map(|(p, k)| {
                 let pp = encrypt(p, k);
-                (mask_proof(p, k, &pp, rng), pp)
+                let h = encrypt(public_key, k);
+                (dleq_proof(public_key, &h, p, &pp, k, rng), (pp, h))
             })
             .unzip();
         EncryptWithProof {
             values_prev,
             values_aftr,
+            public_key: *public_key,
+            key_commitments,
             proofs,
         }
     }
-    pub fn verify(&self, prev: &Vec<EncryptedValue>) -> bool {
-        if self.values_prev != *prev {
+    pub fn verify(&self, prev: &[EncryptedValue]) -> bool {
+        if self.values_prev != prev
+            || self.values_aftr.len() != prev.len()
+            || self.key_commitments.len() != prev.len()
+            || self.proofs.len() != prev.len()
+        {
             return false;
         }
-        for (p, (pp, proof)) in self
-            .values_prev
+        self.values_prev
             .iter()
-            .zip(zip(self.values_aftr.iter(), self.proofs.iter()))
-        {
-            let ChallProof {
-                challenge: e,
-                proof: s,
-            } = proof;
-            let r_p = linear_combination(e, pp, s, p);
-            let e_v = calc_hash(Sha256::new(), &[*p, *pp, r_p]).into();
-            if *e != e_v {
-                return false;
-            }
-        }
-        true
+            .zip(self.values_aftr.iter())
+            .zip(zip(self.key_commitments.iter(), self.proofs.iter()))
+            .all(|((p, pp), (h, proof))| verify_dleq(&self.public_key, h, p, pp, proof))
+    }
+}
+
+impl ProofStep for EncryptWithProof {
+    fn verify_step(&self, prev: &[EncryptedValue]) -> bool {
+        self.verify(prev)
+    }
+    fn result(&self) -> &[EncryptedValue] {
+        &self.values_aftr
+    }
+}
+
+/// Commitment to the key of a single card, published during `EncryptWithProof`.
+pub fn key_commitment(key: &KeyType) -> EncryptedValue {
+    encrypt(&EncryptedValue::new(EncryptedValueType::generator()), key)
+}
+
+/*
+ * Instead of revealing the key of a card, player removes its key from the card
+ * and proves that it used the key matching its commitment.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct DecryptionShare {
+    pub value: EncryptedValue,
+    pub proof: ChallProof,
+}
+
+impl DecryptionShare {
+    pub fn generate<R>(card: &EncryptedValue, key: &KeyType, rng: &mut R) -> Self
+    where
+        R: RngCore + CryptoRng,
+    {
+        let g = EncryptedValue::new(EncryptedValueType::generator());
+        let value = decrypt(card, key);
+        let proof = dleq_proof(&g, &key_commitment(key), &value, card, key, rng);
+        DecryptionShare { value, proof }
+    }
+    pub fn verify(&self, card: &EncryptedValue, commitment: &EncryptedValue) -> bool {
+        let g = EncryptedValue::new(EncryptedValueType::generator());
+        verify_dleq(&g, commitment, &self.value, card, &self.proof)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use ark_std::test_rng;
     use rand::seq::SliceRandom;
+    use rand::{rngs::StdRng, SeedableRng};
 
     use crate::{
-        encryption::{basic_deck, decrypt, short_deck, Translator},
+        encryption::{basic_deck, decrypt, encrypt, short_deck, Translator},
         types::KeyType,
     };
 
-    use super::{EncryptWithProof, ShuffleWithProof};
+    use super::{key_commitment, DecryptionShare, EncryptWithProof, ShuffleWithProof};
 
     #[test]
     fn shuffle_with_proof() {
-        let mut rng = test_rng();
+        let mut rng = StdRng::seed_from_u64(0);
         let deck = basic_deck().to_vec();
         let p_key = KeyType::rand(&mut rng);
         let mut perm = vec![0usize; deck.len()];
@@ -298,14 +410,15 @@ mod test {
 
     #[test]
     fn encrypt_with_proof() {
-        let mut rng = test_rng();
+        let mut rng = StdRng::seed_from_u64(0);
         let deck = basic_deck().to_vec();
         let keys = vec![0; deck.len()]
             .iter()
             .map(|_| KeyType::rand(&mut rng))
             .collect();
+        let public_key = key_commitment(&KeyType::rand(&mut rng));
         dbg!("Start generating proof");
-        let proof = EncryptWithProof::generate(deck.clone(), &keys, &mut rng);
+        let proof = EncryptWithProof::generate(deck.clone(), &keys, &public_key, &mut rng);
         dbg!("Done generating proof");
         let trans = Translator::new(&basic_deck());
         for (v, k) in proof.values_aftr.iter().zip(keys.iter()) {
@@ -319,7 +432,7 @@ mod test {
 
     #[test]
     fn shuffle_with_proof_short() {
-        let mut rng = test_rng();
+        let mut rng = StdRng::seed_from_u64(0);
         let deck = short_deck().to_vec();
         let p_key = KeyType::rand(&mut rng);
         let mut perm = vec![0usize; deck.len()];
@@ -343,14 +456,15 @@ mod test {
 
     #[test]
     fn encrypt_with_proof_short() {
-        let mut rng = test_rng();
+        let mut rng = StdRng::seed_from_u64(0);
         let deck = short_deck().to_vec();
         let keys = vec![0; deck.len()]
             .iter()
             .map(|_| KeyType::rand(&mut rng))
             .collect();
+        let public_key = key_commitment(&KeyType::rand(&mut rng));
         dbg!("Start generating proof");
-        let proof = EncryptWithProof::generate(deck.clone(), &keys, &mut rng);
+        let proof = EncryptWithProof::generate(deck.clone(), &keys, &public_key, &mut rng);
         dbg!("Done generating proof");
         let trans = Translator::new(&basic_deck());
         for (v, k) in proof.values_aftr.iter().zip(keys.iter()) {
@@ -361,4 +475,39 @@ mod test {
         assert!(proof.verify(&deck));
         dbg!("Done verification");
     }
+
+    #[test]
+    fn encrypt_with_proof_wrong_commitment() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let deck = short_deck().to_vec();
+        let keys = (0..deck.len()).map(|_| KeyType::rand(&mut rng)).collect();
+        let public_key = key_commitment(&KeyType::rand(&mut rng));
+        let mut proof = EncryptWithProof::generate(deck.clone(), &keys, &public_key, &mut rng);
+        *proof.key_commitments.first_mut().unwrap() = key_commitment(&KeyType::from(5));
+        assert!(!proof.verify(&deck));
+    }
+
+    #[test]
+    fn decryption_share() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let card = *basic_deck().get(7).unwrap();
+        let key = KeyType::rand(&mut rng);
+        let encrypted = encrypt(&card, &key);
+        let share = DecryptionShare::generate(&encrypted, &key, &mut rng);
+        assert_eq!(share.value, card);
+        assert!(share.verify(&encrypted, &key_commitment(&key)));
+    }
+
+    #[test]
+    fn decryption_share_wrong_key() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let card = *basic_deck().get(7).unwrap();
+        let key = KeyType::rand(&mut rng);
+        let encrypted = encrypt(&card, &key);
+        // key which decrypts the card to the next one in the deck
+        let wrong_key = KeyType::new(key.val * KeyType::from(8).val / KeyType::from(9).val);
+        let share = DecryptionShare::generate(&encrypted, &wrong_key, &mut rng);
+        assert_eq!(share.value, *basic_deck().get(8).unwrap());
+        assert!(!share.verify(&encrypted, &key_commitment(&key)));
+    }
 }
@@ -0,0 +1,335 @@
+use ark_ec::{CurveGroup, Group, VariableBaseMSM};
+use ark_ff::{One, Zero};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::iter::zip;
+
+use crate::{
+    encryption::encrypt,
+    shuffle_v2::{calc_hash, ProofStep, ShuffleProof},
+    types::{EncryptedValue, EncryptedValueType, KeyType, KeyTypeType},
+};
+
+/*
+ * Shuffle argument of Terelius and Wikstrom, linear in the size of the deck
+ * instead of quadratic one_in_n proofs from shuffle_v2.rs.
+ * values_aftr[i] = p_key * values_prev[perm[i]] is proven by:
+ * 1. Pedersen commitment to the permutation matrix, c[perm[i]] = r[perm[i]] * g + h_i.
+ * 2. Random challenges u (Fiat-Shamir), u~[i] = u[perm[i]].
+ *    Sum of commitments opens to all ones and chain of commitments proves
+ *    that product of u~ equals product of u, so u~ is a permutation of u.
+ * 3. Sum of u~[i] * values_aftr[i] = p_key * (sum of u[j] * values_prev[j]),
+ *    with the same p_key as in public_key = p_key * g.
+ */
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ShuffleArgument {
+    commitments: Vec<EncryptedValue>,
+    chain: Vec<EncryptedValue>,
+    challenge: KeyType,
+    responses: [KeyType; 4],
+    chain_responses: Vec<KeyType>,
+    perm_responses: Vec<KeyType>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShuffleWithArgument {
+    pub values_prev: Vec<EncryptedValue>,
+    pub values_aftr: Vec<EncryptedValue>,
+    pub public_key: EncryptedValue,
+    argument: ShuffleArgument,
+}
+
+// independent generators h and h_0, ..., h_{n-1} with unknown discrete logarithms
+fn generators(n: usize) -> (EncryptedValueType, Vec<EncryptedValueType>) {
+    let h = EncryptedValue::hash_to_curve(b"shuffle argument h").val;
+    let hs = (0..n)
+        .map(|i| EncryptedValue::hash_to_curve(format!("shuffle argument h_{}", i).as_bytes()).val)
+        .collect();
+    (h, hs)
+}
+
+fn msm(points: &[EncryptedValueType], scalars: &[KeyTypeType]) -> EncryptedValueType {
+    EncryptedValueType::msm_unchecked(&EncryptedValueType::normalize_batch(points), scalars)
+}
+
+fn values(points: &[EncryptedValue]) -> Vec<EncryptedValueType> {
+    points.iter().map(|p| p.val).collect()
+}
+
+fn wrap(points: &[EncryptedValueType]) -> Vec<EncryptedValue> {
+    points.iter().map(|p| EncryptedValue::new(*p)).collect()
+}
+
+fn random_scalars<R: RngCore + CryptoRng>(n: usize, rng: &mut R) -> Vec<KeyTypeType> {
+    (0..n).map(|_| KeyType::rand(rng).val).collect()
+}
+
+fn statement_hash(
+    values_prev: &[EncryptedValue],
+    values_aftr: &[EncryptedValue],
+    public_key: &EncryptedValue,
+    commitments: &[EncryptedValue],
+) -> Sha256 {
+    let hasher = calc_hash(Sha256::new(), values_prev);
+    let hasher = calc_hash(hasher, values_aftr);
+    let hasher = calc_hash(hasher, &[*public_key]);
+    calc_hash(hasher, commitments)
+}
+
+fn challenges(statement: &Sha256, n: usize) -> Vec<KeyTypeType> {
+    (0..n)
+        .map(|i| KeyType::from(statement.clone().chain_update(i.to_be_bytes())).val)
+        .collect()
+}
+
+fn final_challenge(
+    statement: &Sha256,
+    chain: &[EncryptedValue],
+    t: &[EncryptedValueType],
+    t_chain: &[EncryptedValueType],
+) -> KeyType {
+    let hasher = calc_hash(statement.clone(), chain);
+    let hasher = calc_hash(hasher, &wrap(t));
+    calc_hash(hasher, &wrap(t_chain)).into()
+}
+
+// chain[i - 1] with chain[-1] = h
+fn chain_prev(chain: &[EncryptedValueType], h: EncryptedValueType, i: usize) -> EncryptedValueType {
+    if i == 0 {
+        h
+    } else {
+        *chain.get(i - 1).unwrap()
+    }
+}
+
+impl ShuffleProof for ShuffleWithArgument {
+    fn generate<R: RngCore + CryptoRng>(
+        values_prev: Vec<EncryptedValue>,
+        p_key: &KeyType,
+        perm: &[usize],
+        rng: &mut R,
+    ) -> Self {
+        assert_eq!(perm.len(), values_prev.len());
+        let n = values_prev.len();
+        let g = EncryptedValueType::generator();
+        let public_key = encrypt(&EncryptedValue::new(g), p_key);
+        let values_aftr = perm
+            .iter()
+            .map(|&ind| encrypt(values_prev.get(ind).unwrap(), p_key))
+            .collect::<Vec<_>>();
+        let (h, hs) = generators(n);
+
+        let r = random_scalars(n, rng);
+        let mut commitments = vec![EncryptedValue::new(g); n];
+        for (i, &j) in perm.iter().enumerate() {
+            *commitments.get_mut(j).unwrap() =
+                EncryptedValue::new(g * r.get(j).unwrap() + hs.get(i).unwrap());
+        }
+        let statement = statement_hash(&values_prev, &values_aftr, &public_key, &commitments);
+        let u = challenges(&statement, n);
+        let u_perm = perm.iter().map(|&j| *u.get(j).unwrap()).collect::<Vec<_>>();
+
+        let r_chain = random_scalars(n, rng);
+        let mut chain = Vec::with_capacity(n);
+        for i in 0..n {
+            let prev = chain_prev(&chain, h, i);
+            chain.push(g * r_chain.get(i).unwrap() + prev * u_perm.get(i).unwrap());
+        }
+
+        let w = random_scalars(4, rng);
+        let w_chain = random_scalars(n, rng);
+        let w_perm = random_scalars(n, rng);
+        let combined_prev = msm(&values(&values_prev), &u);
+        let t = [
+            g * w[0],
+            g * w[1],
+            g * w[2] + msm(&hs, &w_perm),
+            msm(&values(&values_aftr), &w_perm) - combined_prev * w[3],
+            g * w[3],
+        ];
+        let t_chain = (0..n)
+            .map(|i| {
+                g * w_chain.get(i).unwrap() + chain_prev(&chain, h, i) * w_perm.get(i).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let chain = wrap(&chain);
+        let e = final_challenge(&statement, &chain, &t, &t_chain).val;
+
+        let r_sum: KeyTypeType = r.iter().sum();
+        let mut r_chain_sum = KeyTypeType::zero();
+        let mut v = KeyTypeType::one();
+        for i in (0..n).rev() {
+            r_chain_sum += *r_chain.get(i).unwrap() * v;
+            v *= u_perm.get(i).unwrap();
+        }
+        let r_combined: KeyTypeType = zip(&r, &u).map(|(r, u)| *r * u).sum();
+        let responses = [
+            w[0] - e * r_sum,
+            w[1] - e * r_chain_sum,
+            w[2] - e * r_combined,
+            w[3] - e * p_key.val,
+        ]
+        .map(KeyType::new);
+        let chain_responses = zip(&w_chain, &r_chain)
+            .map(|(w, r)| KeyType::new(*w - e * r))
+            .collect();
+        let perm_responses = zip(&w_perm, &u_perm)
+            .map(|(w, u)| KeyType::new(*w - e * u))
+            .collect();
+
+        ShuffleWithArgument {
+            values_prev,
+            values_aftr,
+            public_key,
+            argument: ShuffleArgument {
+                commitments,
+                chain,
+                challenge: KeyType::new(e),
+                responses,
+                chain_responses,
+                perm_responses,
+            },
+        }
+    }
+
+    fn public_key(&self) -> &EncryptedValue {
+        &self.public_key
+    }
+}
+
+impl ProofStep for ShuffleWithArgument {
+    fn verify_step(&self, prev: &[EncryptedValue]) -> bool {
+        let ShuffleArgument {
+            commitments,
+            chain,
+            challenge,
+            responses,
+            chain_responses,
+            perm_responses,
+        } = &self.argument;
+        let n = prev.len();
+        if self.values_prev != prev
+            || [
+                self.values_aftr.len(),
+                commitments.len(),
+                chain.len(),
+                chain_responses.len(),
+                perm_responses.len(),
+            ]
+            .iter()
+            .any(|&len| len != n)
+        {
+            return false;
+        }
+        let g = EncryptedValueType::generator();
+        let (h, hs) = generators(n);
+        let statement = statement_hash(prev, &self.values_aftr, &self.public_key, commitments);
+        let u = challenges(&statement, n);
+        let e = challenge.val;
+        let [s_1, s_2, s_3, s_4] = responses.map(|s| s.val);
+        let s_perm = perm_responses.iter().map(|s| s.val).collect::<Vec<_>>();
+        let commitments = values(commitments);
+        let chain_values = values(chain);
+
+        let ones =
+            commitments.iter().sum::<EncryptedValueType>() - hs.iter().sum::<EncryptedValueType>();
+        let product: KeyTypeType = u.iter().product();
+        let chain_end = chain_values.last().copied().unwrap_or(h) - h * product;
+        let combined = msm(&commitments, &u);
+        let combined_prev = msm(&values(prev), &u);
+        let t = [
+            ones * e + g * s_1,
+            chain_end * e + g * s_2,
+            combined * e + g * s_3 + msm(&hs, &s_perm),
+            msm(&values(&self.values_aftr), &s_perm) - combined_prev * s_4,
+            self.public_key.val * e + g * s_4,
+        ];
+        let t_chain = (0..n)
+            .map(|i| {
+                *chain_values.get(i).unwrap() * e
+                    + g * chain_responses.get(i).unwrap().val
+                    + chain_prev(&chain_values, h, i) * s_perm.get(i).unwrap()
+            })
+            .collect::<Vec<_>>();
+        final_challenge(&statement, chain, &t, &t_chain) == *challenge
+    }
+
+    fn result(&self) -> &[EncryptedValue] {
+        &self.values_aftr
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::seq::SliceRandom;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{
+        encryption::{basic_deck, decrypt, short_deck, Translator},
+        shuffle_v2::{key_commitment, ProofStep, ShuffleProof},
+        types::{EncryptedValue, KeyType},
+    };
+
+    use super::ShuffleWithArgument;
+
+    fn shuffle(deck: &[EncryptedValue]) -> (ShuffleWithArgument, KeyType) {
+        let mut rng = StdRng::seed_from_u64(0);
+        let p_key = KeyType::rand(&mut rng);
+        let mut perm = (0..deck.len()).collect::<Vec<_>>();
+        perm.shuffle(&mut rng);
+        let proof = ShuffleWithArgument::generate(deck.to_vec(), &p_key, &perm, &mut rng);
+        (proof, p_key)
+    }
+
+    #[test]
+    fn shuffle_with_argument() {
+        let deck = basic_deck().to_vec();
+        let (proof, p_key) = shuffle(&deck);
+        assert!(proof.verify_step(&deck));
+        let trans = Translator::new(&basic_deck());
+        let mut cards = proof
+            .values_aftr
+            .iter()
+            .map(|v| trans.translate(decrypt(v, &p_key)).unwrap())
+            .collect::<Vec<_>>();
+        cards.sort();
+        assert_eq!(cards, (0..deck.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn shuffle_with_argument_serialization() {
+        let deck = short_deck().to_vec();
+        let (proof, _) = shuffle(&deck);
+        let serialized = serde_json::to_string(&proof).unwrap();
+        let deserialized: ShuffleWithArgument = serde_json::from_str(&serialized).unwrap();
+        assert!(deserialized.verify_step(&deck));
+    }
+
+    #[test]
+    fn shuffle_with_argument_duplicated_card() {
+        let deck = short_deck().to_vec();
+        let (mut proof, _) = shuffle(&deck);
+        let first = *proof.values_aftr.first().unwrap();
+        *proof.values_aftr.last_mut().unwrap() = first;
+        assert!(!proof.verify_step(&deck));
+    }
+
+    #[test]
+    fn shuffle_with_argument_wrong_public_key() {
+        let deck = short_deck().to_vec();
+        let (mut proof, _) = shuffle(&deck);
+        proof.public_key = key_commitment(&KeyType::from(7));
+        assert!(!proof.verify_step(&deck));
+    }
+
+    #[test]
+    fn shuffle_with_argument_wrong_input() {
+        let deck = short_deck().to_vec();
+        let (proof, _) = shuffle(&deck);
+        let mut other_deck = deck.clone();
+        other_deck.swap(0, 1);
+        assert!(!proof.verify_step(&other_deck));
+    }
+}
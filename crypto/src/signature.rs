@@ -0,0 +1,124 @@
+use std::fmt::Display;
+
+use ark_ec::Group;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    encryption::encrypt,
+    shuffle_v2::{calc_hash, key_commitment, ChallProof},
+    types::{EncryptedValue, EncryptedValueType, KeyType},
+};
+
+/*
+ * Schnorr signature over the curve used for cards.
+ * For secret key x with public key g^x, signer picks random r and computes
+ * e = H(g^x, g^r, message) and s = r - ex. Verifier recomputes g^r as g^s * (g^x)^e.
+ */
+
+#[derive(Clone)]
+pub struct SigningKey {
+    key: KeyType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyingKey(EncryptedValue);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Signature(ChallProof);
+
+fn challenge(public_key: &EncryptedValue, r: &EncryptedValue, message: &[u8]) -> KeyType {
+    calc_hash(Sha256::new().chain_update(b"signature"), &[*public_key, *r])
+        .chain_update(message)
+        .into()
+}
+
+impl SigningKey {
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        SigningKey {
+            key: KeyType::rand(rng),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.key.to_bytes()
+    }
+
+    /// `None` for bytes which are not a valid non-zero key.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        KeyType::from_bytes(bytes)
+            .filter(|key| *key != KeyType::from(0))
+            .map(|key| SigningKey { key })
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey(key_commitment(&self.key))
+    }
+
+    pub fn sign<R: RngCore + CryptoRng>(&self, message: &[u8], rng: &mut R) -> Signature {
+        let r = KeyType::rand(rng);
+        let e = challenge(&self.verifying_key().0, &key_commitment(&r), message);
+        Signature(ChallProof {
+            challenge: e,
+            proof: KeyType::new(r.val - e.val * self.key.val),
+        })
+    }
+}
+
+impl VerifyingKey {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        EncryptedValue::from_bytes(bytes).map(VerifyingKey)
+    }
+
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> bool {
+        let ChallProof {
+            challenge: e,
+            proof: s,
+        } = signature.0;
+        let g = EncryptedValue::new(EncryptedValueType::generator());
+        let r = EncryptedValue::new(encrypt(&g, &s).val + encrypt(&self.0, &e).val);
+        challenge(&self.0, &r, message) == e
+    }
+}
+
+impl Display for VerifyingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{SigningKey, VerifyingKey};
+
+    #[test]
+    fn sign_and_verify() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let key = SigningKey::generate(&mut rng);
+        let signature = key.sign(b"transcript", &mut rng);
+        assert!(key.verifying_key().verify(b"transcript", &signature));
+        assert!(!key.verifying_key().verify(b"transcripT", &signature));
+        let other = SigningKey::generate(&mut rng);
+        assert!(!other.verifying_key().verify(b"transcript", &signature));
+    }
+
+    #[test]
+    fn key_bytes() {
+        let key = SigningKey::generate(&mut StdRng::seed_from_u64(0));
+        let restored = SigningKey::from_bytes(&key.to_bytes()).unwrap();
+        assert_eq!(restored.verifying_key(), key.verifying_key());
+        let verifying = key.verifying_key();
+        assert_eq!(
+            VerifyingKey::from_bytes(&verifying.to_bytes()),
+            Some(verifying)
+        );
+        assert!(SigningKey::from_bytes(&[0; 32]).is_none());
+    }
+}
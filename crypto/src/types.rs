@@ -1,16 +1,14 @@
 use std::fmt::Display;
 
-use ark_ec::short_weierstrass::Projective;
-use ark_ff::BigInt;
-use ark_pallas::Fr as ScalarField;
-use ark_pallas::PallasConfig;
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_std::UniformRand;
 use rand::Rng;
+use serde::de::{SeqAccess, Visitor};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-pub(crate) type EncryptedValueType = Projective<PallasConfig>;
-pub(crate) type KeyTypeType = ScalarField;
+use crate::curve::point_from_hash;
+pub(crate) use crate::curve::{EncryptedValueType, KeyTypeType};
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub struct EncryptedValue {
@@ -21,6 +19,32 @@ impl EncryptedValue {
     pub fn new(val: EncryptedValueType) -> Self {
         EncryptedValue { val }
     }
+    /// Canonical compressed encoding of the point.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.val.serialize_compressed(&mut bytes).unwrap();
+        bytes
+    }
+    /// Point from its compressed encoding, `None` if it is not a valid point.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        EncryptedValueType::deserialize_compressed(bytes)
+            .ok()
+            .map(EncryptedValue::new)
+    }
+    /// Point with unknown discrete logarithm, found by hashing `seed` with a counter
+    /// until the hash is a coordinate of a point.
+    pub(crate) fn hash_to_curve(seed: &[u8]) -> Self {
+        (0u64..)
+            .find_map(|counter| {
+                let hash = Sha256::new()
+                    .chain_update(seed)
+                    .chain_update(counter.to_be_bytes())
+                    .finalize();
+                point_from_hash(&hash)
+            })
+            .map(EncryptedValue::new)
+            .unwrap()
+    }
 }
 
 impl Display for EncryptedValue {
@@ -29,14 +53,40 @@ impl Display for EncryptedValue {
     }
 }
 
+/*
+ * Points and scalars are written with `serialize_bytes`, binary codecs store them
+ * as byte strings. Text codecs write an array of numbers, which is accepted back too.
+ */
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("bytes")
+    }
+    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(v.to_vec())
+    }
+    fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(v)
+    }
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
 impl Serialize for EncryptedValue {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let mut bytes = Vec::new();
-        self.val.serialize_compressed(&mut bytes).unwrap(); // TODO convert this error to serde type (somehow)
-        bytes.serialize(serializer)
+        serializer.serialize_bytes(&self.to_bytes())
     }
 }
 
@@ -45,26 +95,37 @@ impl<'de> Deserialize<'de> for EncryptedValue {
     where
         D: serde::Deserializer<'de>,
     {
-        let bytes: Vec<u8> = Deserialize::deserialize(deserializer)?;
-        let val = Projective::deserialize_compressed(&*bytes).unwrap(); // TODO convert this error to serde type (somehow)
-        Ok(EncryptedValue::new(val))
+        let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+        EncryptedValue::from_bytes(&bytes).ok_or_else(|| serde::de::Error::custom("invalid point"))
     }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub struct KeyType {
-    pub(crate) val: ScalarField,
+    pub(crate) val: KeyTypeType,
 }
 
 impl KeyType {
-    pub fn new(val: ScalarField) -> Self {
+    pub fn new(val: KeyTypeType) -> Self {
         KeyType { val }
     }
     pub fn rand<R>(rng: &mut R) -> Self
     where
         R: Rng + ?Sized,
     {
-        KeyType::new(ScalarField::rand(rng))
+        KeyType::new(KeyTypeType::rand(rng))
+    }
+    /// Canonical compressed encoding of the scalar.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.val.serialize_compressed(&mut bytes).unwrap();
+        bytes
+    }
+    /// Scalar from its compressed encoding, `None` if it is out of range.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        KeyTypeType::deserialize_compressed(bytes)
+            .ok()
+            .map(KeyType::new)
     }
 }
 
@@ -76,7 +137,7 @@ impl Display for KeyType {
 
 impl From<i64> for KeyType {
     fn from(value: i64) -> Self {
-        KeyType::new(ScalarField::from(value))
+        KeyType::new(KeyTypeType::from(value))
     }
 }
 
@@ -85,9 +146,7 @@ impl Serialize for KeyType {
     where
         S: serde::Serializer,
     {
-        let mut bytes = Vec::new();
-        self.val.serialize_compressed(&mut bytes).unwrap(); // TODO convert this error to serde type (somehow)
-        bytes.serialize(serializer)
+        serializer.serialize_bytes(&self.to_bytes())
     }
 }
 
@@ -96,9 +155,8 @@ impl<'de> Deserialize<'de> for KeyType {
     where
         D: serde::Deserializer<'de>,
     {
-        let bytes: Vec<u8> = Deserialize::deserialize(deserializer)?;
-        let val = BigInt::<4>::deserialize_compressed(&*bytes).unwrap(); // TODO convert this error to serde type (somehow)
-        Ok(KeyType::new(ScalarField::from(val)))
+        let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+        KeyType::from_bytes(&bytes).ok_or_else(|| serde::de::Error::custom("scalar out of range"))
     }
 }
 
@@ -112,12 +170,12 @@ pub enum PartyState {
 
 #[cfg(test)]
 mod test {
-    use ark_std::test_rng;
+    use rand::{rngs::StdRng, SeedableRng};
 
     use crate::encryption::encrypt;
 
     use super::KeyType;
-    use super::ScalarField;
+    use super::KeyTypeType;
     use super::{EncryptedValue, EncryptedValueType};
     use ark_ec::Group;
     use ark_std::UniformRand;
@@ -125,7 +183,7 @@ mod test {
 
     #[test]
     fn test_eq() {
-        let mut rng = test_rng();
+        let mut rng = StdRng::seed_from_u64(0);
         let g = EncryptedValue::new(EncryptedValueType::rand(&mut rng));
         let s1 = KeyType::rand(&mut rng);
         let s2 = KeyType::rand(&mut rng);
@@ -160,7 +218,7 @@ mod test {
 
     #[test]
     fn group_serialize_deserialize_rand() {
-        let mut rng = test_rng();
+        let mut rng = StdRng::seed_from_u64(0);
         let g = EncryptedValue::new(EncryptedValueType::rand(&mut rng));
         let serialized = serde_json::to_string(&g).unwrap();
         let deserialized: EncryptedValue = serde_json::from_str(&serialized).unwrap();
@@ -185,10 +243,34 @@ mod test {
 
     #[test]
     fn scalar_serialize_deserialize_rand() {
-        let mut rng = test_rng();
-        let g = KeyType::new(ScalarField::rand(&mut rng));
+        let mut rng = StdRng::seed_from_u64(0);
+        let g = KeyType::new(KeyTypeType::rand(&mut rng));
         let serialized = serde_json::to_string(&g).unwrap();
         let deserialized: KeyType = serde_json::from_str(&serialized).unwrap();
         assert_eq!(deserialized, g);
     }
+
+    #[test]
+    fn cbor_byte_strings() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let g = EncryptedValue::new(EncryptedValueType::rand(&mut rng));
+        let k = KeyType::rand(&mut rng);
+        for (bytes, len) in [
+            (to_cbor(&g), g.to_bytes().len()),
+            (to_cbor(&k), k.to_bytes().len()),
+        ] {
+            // major type 2 with one byte of length, not an array of integers
+            assert_eq!(bytes.first(), Some(&0x58));
+            assert_eq!(bytes.len(), len + 2);
+        }
+        let point: EncryptedValue = ciborium::from_reader(&*to_cbor(&g)).unwrap();
+        let key: KeyType = ciborium::from_reader(&*to_cbor(&k)).unwrap();
+        assert_eq!((point, key), (g, k));
+    }
+
+    fn to_cbor<T: serde::Serialize>(value: &T) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes).unwrap();
+        bytes
+    }
 }
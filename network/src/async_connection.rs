@@ -0,0 +1,176 @@
+use std::future::Future;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::connection::{
+    decode_frame, encode_frame, out_of_sync, parse_header, Codec, HEADER_SIZE, MAX_FRAME_SIZE,
+};
+use crate::error::{Error, Result};
+use crate::secure::{Handshake, Identity, SecureChannel};
+
+/*
+ * Async connections send the same frames and run the same handshake as `TcpConnection`,
+ * so one side can block while the other runs on a runtime.
+ */
+
+pub trait AsyncConnection {
+    fn send<T: Serialize>(&mut self, message: &T) -> impl Future<Output = Result<()>> + Send;
+    fn receive<T: DeserializeOwned + Serialize>(
+        &mut self,
+    ) -> impl Future<Output = Result<T>> + Send;
+}
+
+pub struct AsyncTcpConnection {
+    stream: TcpStream,
+    codec: Codec,
+    max_frame_size: usize,
+    read_timeout: Option<Duration>,
+    channel: Option<SecureChannel>,
+    /// A frame was read only partly, see `TcpConnection`.
+    out_of_sync: bool,
+}
+
+impl AsyncTcpConnection {
+    pub fn new(stream: TcpStream) -> Self {
+        Self::with_codec(stream, Codec::default())
+    }
+    pub fn with_codec(stream: TcpStream, codec: Codec) -> Self {
+        AsyncTcpConnection {
+            stream,
+            codec,
+            max_frame_size: MAX_FRAME_SIZE,
+            read_timeout: None,
+            channel: None,
+            out_of_sync: false,
+        }
+    }
+    pub fn set_codec(&mut self, codec: Codec) {
+        self.codec = codec;
+    }
+    /// Frames with longer payload are rejected both when sending and receiving.
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size;
+    }
+    /// Receiving fails with `Error::Timeout` if nothing arrives within `timeout`.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+    /// Runs the handshake, all later messages are encrypted and authenticated.
+    /// The side which opened the connection has to be the `initiator`.
+    pub async fn secure(&mut self, identity: &Identity, initiator: bool) -> Result<()> {
+        let mut handshake = Handshake::new(identity, initiator)?;
+        let mut record = Vec::new();
+        while !handshake.is_finished() {
+            match handshake.write()? {
+                Some(record) => self.stream.write_all(&record).await?,
+                None => {
+                    let timeout = self.read_timeout;
+                    with_timeout(timeout, read_record(&mut self.stream, &mut record)).await?;
+                    handshake.read(&record)?;
+                }
+            }
+        }
+        self.channel = Some(handshake.finish()?);
+        Ok(())
+    }
+    /// Static key of the peer, if the connection is secured.
+    pub fn peer_identity(&self) -> Option<[u8; 32]> {
+        self.channel.as_ref().map(SecureChannel::peer)
+    }
+    pub(crate) fn handshake_hash(&self) -> Option<&[u8]> {
+        self.channel.as_ref().map(SecureChannel::handshake_hash)
+    }
+    async fn read_exact(&mut self, buffer: &mut [u8]) -> Result<()> {
+        let Some(channel) = self.channel.as_mut() else {
+            self.stream.read_exact(buffer).await?;
+            return Ok(());
+        };
+        let mut record = Vec::new();
+        let mut filled = 0;
+        while filled < buffer.len() {
+            if !channel.has_buffered() {
+                read_record(&mut self.stream, &mut record).await?;
+                channel.open(&record)?;
+            }
+            filled += channel.take(buffer.get_mut(filled..).unwrap());
+        }
+        Ok(())
+    }
+    // nothing is consumed, so timing out here leaves the stream intact
+    async fn wait_for_frame(&mut self) -> Result<()> {
+        if !self
+            .channel
+            .as_ref()
+            .is_some_and(SecureChannel::has_buffered)
+        {
+            self.stream.peek(&mut [0; 1]).await?;
+        }
+        Ok(())
+    }
+    async fn read_frame(&mut self) -> Result<(u8, Codec, Vec<u8>)> {
+        let mut header = [0; HEADER_SIZE];
+        self.read_exact(&mut header).await?;
+        let (message_type, codec, length) = parse_header(header, self.max_frame_size)?;
+        let mut buffer = vec![0_u8; length];
+        self.read_exact(&mut buffer).await?;
+        Ok((message_type, codec, buffer))
+    }
+}
+
+async fn read_record(stream: &mut TcpStream, record: &mut Vec<u8>) -> Result<()> {
+    let mut length = [0; 2];
+    stream.read_exact(&mut length).await?;
+    record.resize(u16::from_be_bytes(length) as usize, 0);
+    stream.read_exact(record).await?;
+    Ok(())
+}
+
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .unwrap_or(Err(Error::Timeout)),
+        None => future.await,
+    }
+}
+
+impl AsyncConnection for AsyncTcpConnection {
+    // the frame is encrypted before the future is created, so the message does not have to be `Sync`
+    fn send<T: Serialize>(&mut self, message: &T) -> impl Future<Output = Result<()>> + Send {
+        let frame = encode_frame(self.codec, self.max_frame_size, message).and_then(|frame| {
+            match self.channel.as_mut() {
+                Some(channel) => channel.seal(&frame),
+                None => Ok(frame),
+            }
+        });
+        async move {
+            self.stream.write_all(&frame?).await?;
+            Ok(())
+        }
+    }
+
+    async fn receive<T: DeserializeOwned + Serialize>(&mut self) -> Result<T> {
+        if self.out_of_sync {
+            return Err(out_of_sync());
+        }
+        let timeout = self.read_timeout;
+        with_timeout(timeout, self.wait_for_frame()).await?;
+        // cancelled read loses the part of the frame read so far
+        let frame = with_timeout(timeout, self.read_frame()).await;
+        let (message_type, codec, buffer) = frame.map_err(|err| {
+            self.out_of_sync = true;
+            match err {
+                Error::Timeout => out_of_sync(),
+                err => err,
+            }
+        })?;
+        decode_frame(message_type, codec, &buffer)
+    }
+}
@@ -0,0 +1,95 @@
+use std::io;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::connection::{Codec, Connection};
+use crate::error::{Error, Result};
+
+/*
+ * In-process connection for tests and local opponents. Messages are encoded
+ * with the codec like on the network, so everything which passes through a channel
+ * also passes through a socket. Like frames, messages carry their codec.
+ * Dropping one side closes the connection.
+ */
+
+pub struct ChannelConnection {
+    sender: Sender<(Codec, Vec<u8>)>,
+    receiver: Receiver<(Codec, Vec<u8>)>,
+    codec: Codec,
+    read_timeout: Option<Duration>,
+}
+
+impl ChannelConnection {
+    fn new(sender: Sender<(Codec, Vec<u8>)>, receiver: Receiver<(Codec, Vec<u8>)>) -> Self {
+        ChannelConnection {
+            sender,
+            receiver,
+            codec: Codec::default(),
+            read_timeout: None,
+        }
+    }
+
+    /// Two ends of one connection.
+    pub fn pair() -> (Self, Self) {
+        let (first_sender, second_receiver) = channel();
+        let (second_sender, first_receiver) = channel();
+        (
+            Self::new(first_sender, first_receiver),
+            Self::new(second_sender, second_receiver),
+        )
+    }
+
+    /// Full mesh of connections, `mesh[i]` are connections of player `i`
+    /// to other players ordered by their ids, like from `ConStartup::initialize_mesh`.
+    pub fn mesh(num_players: usize) -> Vec<Vec<Self>> {
+        let mut mesh: Vec<Vec<Self>> = (0..num_players).map(|_| vec![]).collect();
+        for first in 0..num_players {
+            for second in first + 1..num_players {
+                let (first_end, second_end) = Self::pair();
+                mesh.get_mut(first).unwrap().push(first_end);
+                mesh.get_mut(second).unwrap().push(second_end);
+            }
+        }
+        mesh
+    }
+
+    pub fn set_codec(&mut self, codec: Codec) {
+        self.codec = codec;
+    }
+
+    /// Receiving fails with `Error::Timeout` if nothing arrives within `timeout`.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+}
+
+fn closed() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "Other side of the channel is closed",
+    ))
+}
+
+impl Connection for ChannelConnection {
+    fn send<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        let bytes = self.codec.encode(message)?;
+        self.sender.send((self.codec, bytes)).map_err(|_| closed())
+    }
+
+    fn receive<T: DeserializeOwned + Serialize>(&mut self) -> Result<T> {
+        let (codec, bytes) = match self.read_timeout {
+            Some(timeout) => self
+                .receiver
+                .recv_timeout(timeout)
+                .map_err(|err| match err {
+                    RecvTimeoutError::Timeout => Error::Timeout,
+                    RecvTimeoutError::Disconnected => closed(),
+                })?,
+            None => self.receiver.recv().map_err(|_| closed())?,
+        };
+        codec.decode(&bytes)
+    }
+}
@@ -1,46 +1,399 @@
-use std::net::{TcpListener, TcpStream};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 
-use crate::connection::TcpConnection;
+use crate::async_connection::{AsyncConnection, AsyncTcpConnection};
+use crate::connection::{Codec, Connection, TcpConnection};
+use crate::error::{Error, Result};
+use crate::message::Hello;
+use crate::secure::{Identity, PublicKey};
+use crypto::deck::DeckSpec;
+use rand::thread_rng;
+
+const MIN_RETRY_DELAY: Duration = Duration::from_millis(10);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(1);
 
 pub struct ConStartup {
+    num_players: usize,
     player_id: usize,
+    curve: String,
+    deck: Option<[u8; 32]>,
+    game: u64,
+    codec: Codec,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    identity: Option<Identity>,
+    peers: Vec<PublicKey>,
 }
 
 impl ConStartup {
     pub fn new(num_players: usize, player_id: usize) -> Self {
         assert!(player_id < num_players);
-        ConStartup { player_id }
+        ConStartup {
+            num_players,
+            player_id,
+            curve: String::new(),
+            deck: None,
+            game: 0,
+            codec: Codec::default(),
+            connect_timeout: None,
+            read_timeout: None,
+            identity: None,
+            peers: vec![],
+        }
+    }
+
+    /// Name of the curve used by the player, peers with different curves are rejected.
+    pub fn with_curve(mut self, curve: &str) -> Self {
+        self.curve = curve.to_string();
+        self
+    }
+
+    /// Deck the game starts with, peers with different decks are rejected.
+    pub fn with_deck(mut self, deck: &DeckSpec) -> Self {
+        self.deck = Some(deck.hash());
+        self
+    }
+
+    /// Id of the game session, peers from other sessions are rejected.
+    pub fn with_game(mut self, game: u64) -> Self {
+        self.game = game;
+        self
+    }
+
+    /// Codec used for messages sent over established connections.
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Dialing a player fails with `Error::Timeout` if it does not accept within `timeout`.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Read timeout of all connections, including the handshake.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Secures all connections, `peers[i]` is the pinned identity of player `i`.
+    /// Entry of the player itself is ignored.
+    pub fn with_identity(mut self, identity: Identity, peers: Vec<PublicKey>) -> Self {
+        assert_eq!(peers.len(), self.num_players);
+        self.identity = Some(identity);
+        self.peers = peers;
+        self
+    }
+
+    // the side which opened the connection initiates the handshake
+    fn connection(&self, stream: TcpStream, initiator: bool) -> Result<TcpConnection> {
+        let mut connection = TcpConnection::with_codec(stream, self.codec);
+        connection.set_read_timeout(self.read_timeout)?;
+        if let Some(identity) = &self.identity {
+            connection.secure(identity, initiator)?;
+        }
+        Ok(connection)
+    }
+
+    // the other player may not listen yet, attempts are repeated with growing delays
+    fn start_client(&self, address: &String) -> Result<TcpStream> {
+        let deadline = self.connect_timeout.map(|timeout| Instant::now() + timeout);
+        let mut delay = MIN_RETRY_DELAY;
+        loop {
+            if let Ok(stream) = TcpStream::connect(address) {
+                return Ok(stream);
+            }
+            if deadline.is_some_and(|deadline| Instant::now() + delay > deadline) {
+                return Err(Error::Timeout);
+            }
+            sleep(delay);
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+    }
+
+    // on secured connections hello is signed, so the peer knows it comes from the pinned identity
+    fn hello(&self, handshake_hash: Option<&[u8]>) -> Hello {
+        let hello = Hello {
+            player_id: self.player_id,
+            num_players: self.num_players,
+            curve: self.curve.clone(),
+            deck: self.deck,
+            game: self.game,
+            signature: None,
+        };
+        match (&self.identity, handshake_hash) {
+            (Some(identity), Some(hash)) => hello.sign(hash, identity.signer(), &mut thread_rng()),
+            _ => hello,
+        }
+    }
+
+    fn send_hello(&self, connection: &mut TcpConnection) -> Result<()> {
+        let hello = self.hello(connection.handshake_hash());
+        connection.send(&hello)
+    }
+
+    fn receive_hello(&self, connection: &mut TcpConnection) -> Result<usize> {
+        let hello = connection.receive()?;
+        self.check_hello(
+            hello,
+            connection.peer_identity(),
+            connection.handshake_hash(),
+        )
     }
 
-    fn start_server(&self, address: &String) -> TcpStream {
-        let listener = TcpListener::bind(address).unwrap();
-        let mut stream_opt: Option<TcpStream> = None;
-        for stream in listener.incoming() {
-            if stream.is_ok() {
-                stream_opt = Some(stream.unwrap());
-                break;
+    // `peer` and `handshake_hash` come from the secured connection
+    fn check_hello(
+        &self,
+        hello: Hello,
+        peer: Option<[u8; 32]>,
+        handshake_hash: Option<&[u8]>,
+    ) -> Result<usize> {
+        if hello.game != self.game {
+            return Err(Error::protocol("Different game"));
+        }
+        if hello.curve != self.curve {
+            return Err(Error::protocol("Different curve"));
+        }
+        if hello.deck != self.deck {
+            return Err(Error::protocol("Different deck"));
+        }
+        if hello.num_players != self.num_players {
+            return Err(Error::protocol("Wrong number of players"));
+        }
+        let player_id = hello.player_id;
+        if player_id >= self.num_players || player_id == self.player_id {
+            return Err(Error::protocol("Wrong player id"));
+        }
+        if self.identity.is_some() {
+            let pinned = self.peers.get(player_id).unwrap();
+            if peer != Some(pinned.noise) {
+                return Err(Error::protocol("Peer identity does not match pinned key"));
+            }
+            if !handshake_hash.is_some_and(|hash| hello.verify(hash, &pinned.signing)) {
+                return Err(Error::protocol("Hello is not signed by the pinned key"));
             }
         }
-        stream_opt.unwrap()
+        Ok(player_id)
     }
 
-    fn start_client(&self, address: &String) -> TcpStream {
+    fn dial(&self, address: &String, other_id: usize) -> Result<TcpConnection> {
+        let mut connection = self.connection(self.start_client(address)?, true)?;
+        self.send_hello(&mut connection)?;
+        if self.receive_hello(&mut connection)? != other_id {
+            return Err(Error::protocol("Wrong player id"));
+        }
+        Ok(connection)
+    }
+
+    fn accept(&self, listener: &TcpListener) -> Result<(usize, TcpConnection)> {
+        let (stream, _) = listener.accept()?;
+        let mut connection = self.connection(stream, false)?;
+        let other_id = self.receive_hello(&mut connection)?;
+        self.send_hello(&mut connection)?;
+        Ok((other_id, connection))
+    }
+
+    /// Opens a new connection to player `other_id` after the old one dropped.
+    /// Players with lower ids are dialed at `address`, others are accepted on `listener`.
+    pub fn reconnect(
+        &self,
+        listener: &TcpListener,
+        address: &String,
+        other_id: usize,
+    ) -> Result<TcpConnection> {
+        if other_id < self.player_id {
+            return self.dial(address, other_id);
+        }
+        match self.accept(listener)? {
+            (id, connection) if id == other_id => Ok(connection),
+            _ => Err(Error::protocol("Wrong player id")),
+        }
+    }
+
+    /*
+     * Every player dials all players with lower ids and accepts connections
+     * from all players with higher ids, peers introduce themselves with `Hello`.
+     * Connections which are already present are not established again.
+     */
+    fn build_mesh(
+        &self,
+        listener: &TcpListener,
+        addresses: &[String],
+        mut connections: Vec<Option<TcpConnection>>,
+    ) -> Result<Vec<TcpConnection>> {
+        if addresses.len() != self.num_players {
+            return Err(Error::protocol("Wrong number of addresses"));
+        }
+        for (other_id, address) in addresses.iter().enumerate().take(self.player_id) {
+            if connections.get(other_id).unwrap().is_none() {
+                *connections.get_mut(other_id).unwrap() = Some(self.dial(address, other_id)?);
+            }
+        }
+        let missing = (self.player_id + 1..self.num_players)
+            .filter(|&id| connections.get(id).unwrap().is_none())
+            .count();
+        for _ in 0..missing {
+            let (other_id, connection) = self.accept(listener)?;
+            let slot = connections.get_mut(other_id).unwrap();
+            if other_id < self.player_id || slot.is_some() {
+                return Err(Error::protocol("Player connected twice"));
+            }
+            *slot = Some(connection);
+        }
+        Ok(connections.into_iter().flatten().collect())
+    }
+
+    /// Player 0 listens on `address`, the other player dials it.
+    pub fn initialize(&self, address: &String) -> Result<TcpConnection> {
+        if self.player_id == 0 {
+            return self.initialize_on(&TcpListener::bind(address)?);
+        }
+        let connection = self.connection(self.start_client(address)?, true)?;
+        self.exchange_hello(connection)
+    }
+
+    /// Same as `initialize` for player 0, but accepts on already bound `listener`.
+    pub fn initialize_on(&self, listener: &TcpListener) -> Result<TcpConnection> {
+        let (stream, _) = listener.accept()?;
+        let connection = self.connection(stream, false)?;
+        self.exchange_hello(connection)
+    }
+
+    fn exchange_hello(&self, mut connection: TcpConnection) -> Result<TcpConnection> {
+        self.send_hello(&mut connection)?;
+        self.receive_hello(&mut connection)?;
+        Ok(connection)
+    }
+
+    /// Same as `initialize`, but returns an async connection.
+    pub async fn initialize_async(&self, address: &String) -> Result<AsyncTcpConnection> {
+        if self.player_id == 0 {
+            let listener = tokio::net::TcpListener::bind(address).await?;
+            return self.initialize_async_on(&listener).await;
+        }
+        let stream = self.start_client_async(address).await?;
+        self.exchange_hello_async(stream, true).await
+    }
+
+    /// Same as `initialize_on`, but returns an async connection.
+    pub async fn initialize_async_on(
+        &self,
+        listener: &tokio::net::TcpListener,
+    ) -> Result<AsyncTcpConnection> {
+        let (stream, _) = listener.accept().await?;
+        self.exchange_hello_async(stream, false).await
+    }
+
+    // the side which opened the connection initiates the handshake
+    async fn exchange_hello_async(
+        &self,
+        stream: tokio::net::TcpStream,
+        initiator: bool,
+    ) -> Result<AsyncTcpConnection> {
+        let mut connection = AsyncTcpConnection::with_codec(stream, self.codec);
+        connection.set_read_timeout(self.read_timeout);
+        if let Some(identity) = &self.identity {
+            connection.secure(identity, initiator).await?;
+        }
+        connection
+            .send(&self.hello(connection.handshake_hash()))
+            .await?;
+        let hello = connection.receive().await?;
+        self.check_hello(
+            hello,
+            connection.peer_identity(),
+            connection.handshake_hash(),
+        )?;
+        Ok(connection)
+    }
+
+    async fn start_client_async(&self, address: &String) -> Result<tokio::net::TcpStream> {
+        let deadline = self.connect_timeout.map(|timeout| Instant::now() + timeout);
+        let mut delay = MIN_RETRY_DELAY;
         loop {
-            let result = TcpStream::connect(address);
-            if result.is_ok() {
-                let stream = result.unwrap();
-                return stream;
+            if let Ok(stream) = tokio::net::TcpStream::connect(address).await {
+                return Ok(stream);
             }
+            if deadline.is_some_and(|deadline| Instant::now() + delay > deadline) {
+                return Err(Error::Timeout);
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
         }
     }
 
-    pub fn initialize(&self, address: &String) -> TcpConnection {
+    /// Builds full mesh of connections, `addresses[i]` is the address player `i` listens on.
+    /// Returns connections to other players ordered by their ids.
+    pub fn initialize_mesh(&self, addresses: &[String]) -> Result<Vec<TcpConnection>> {
+        let address = addresses
+            .get(self.player_id)
+            .ok_or(Error::protocol("Wrong number of addresses"))?;
+        let listener = TcpListener::bind(address)?;
+        self.connect_mesh(&listener, addresses)
+    }
+
+    /// Same as `initialize_mesh`, but listens on already bound `listener`.
+    pub fn connect_mesh(
+        &self,
+        listener: &TcpListener,
+        addresses: &[String],
+    ) -> Result<Vec<TcpConnection>> {
+        let connections = (0..self.num_players).map(|_| None).collect();
+        self.build_mesh(listener, addresses, connections)
+    }
+
+    /// Builds full mesh of connections with player 0 hosting on `host`.
+    /// Every other player listens on `listen_address` and registers its port at the host,
+    /// which advertises it with the address the player connected from,
+    /// so players may listen on unspecified addresses like `0.0.0.0`.
+    /// Returns connections to other players ordered by their ids.
+    pub fn initialize_rendezvous(
+        &self,
+        host: &String,
+        listen_address: &String,
+    ) -> Result<Vec<TcpConnection>> {
         if self.player_id == 0 {
-            let stream = self.start_server(address);
-            TcpConnection::new(stream)
+            let listener = TcpListener::bind(host)?;
+            self.host_rendezvous(&listener, host)
         } else {
-            let stream = self.start_client(address);
-            TcpConnection::new(stream)
+            let listener = TcpListener::bind(listen_address)?;
+            self.join_rendezvous(&listener, host)
+        }
+    }
+
+    fn host_rendezvous(&self, listener: &TcpListener, host: &str) -> Result<Vec<TcpConnection>> {
+        let mut addresses = vec![String::new(); self.num_players];
+        *addresses.get_mut(0).unwrap() = host.to_string();
+        let mut connections: Vec<Option<TcpConnection>> =
+            (0..self.num_players).map(|_| None).collect();
+        for _ in 1..self.num_players {
+            let (other_id, mut connection) = self.accept(listener)?;
+            let slot = connections.get_mut(other_id).unwrap();
+            if slot.is_some() {
+                return Err(Error::protocol("Player connected twice"));
+            }
+            let port: u16 = connection.receive()?;
+            let address = SocketAddr::new(connection.peer_addr()?.ip(), port);
+            *addresses.get_mut(other_id).unwrap() = address.to_string();
+            *slot = Some(connection);
+        }
+        let mut connections = connections.into_iter().flatten().collect::<Vec<_>>();
+        for connection in connections.iter_mut() {
+            connection.send(&addresses)?;
         }
+        Ok(connections)
+    }
+
+    fn join_rendezvous(&self, listener: &TcpListener, host: &String) -> Result<Vec<TcpConnection>> {
+        let mut host_connection = self.dial(host, 0)?;
+        host_connection.send(&listener.local_addr()?.port())?;
+        let addresses: Vec<String> = host_connection.receive()?;
+        let mut connections: Vec<Option<TcpConnection>> =
+            (0..self.num_players).map(|_| None).collect();
+        *connections.get_mut(0).unwrap() = Some(host_connection);
+        self.build_mesh(listener, &addresses, connections)
     }
 }
@@ -1,48 +1,393 @@
+use std::io;
 use std::io::prelude::*;
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
 
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::ser::{self, Impossible};
+use serde::{Serialize, Serializer};
+
+use crate::error::{Error, Result};
+use crate::secure::{Identity, SecureChannel};
+
+/// Version of the framing, frames with other version are rejected.
+pub const PROTOCOL_VERSION: u8 = 3;
+/// Default limit for the payload of a single frame.
+pub const MAX_FRAME_SIZE: usize = 64 << 20;
 
 pub trait Connection {
-    fn send<T: Serialize>(&mut self, message: &T);
-    fn receive<T: DeserializeOwned>(&mut self) -> T;
+    fn send<T: Serialize>(&mut self, message: &T) -> Result<()>;
+    fn receive<T: DeserializeOwned + Serialize>(&mut self) -> Result<T>;
+}
+
+/// Encoding of the frame payload.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Codec {
+    #[default]
+    Json,
+    Cbor,
+}
+
+impl Codec {
+    fn tag(self) -> u8 {
+        match self {
+            Codec::Json => 0,
+            Codec::Cbor => 1,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<Self> {
+        match tag {
+            0 => Ok(Codec::Json),
+            1 => Ok(Codec::Cbor),
+            _ => Err(Error::protocol("Unknown codec")),
+        }
+    }
+
+    pub(crate) fn encode<T: Serialize>(self, message: &T) -> Result<Vec<u8>> {
+        match self {
+            Codec::Json => Ok(serde_json::to_vec(message)?),
+            Codec::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(message, &mut bytes)?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    pub(crate) fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T> {
+        match self {
+            Codec::Json => Ok(serde_json::from_slice(bytes)?),
+            Codec::Cbor => Ok(ciborium::from_reader(bytes)?),
+        }
+    }
+}
+
+/*
+ * Every frame starts with 7 byte header: protocol version, message type,
+ * codec of the payload and little-endian u32 length of the payload.
+ * Messages are sent with the codec of the connection, received frames are
+ * decoded with the codec from their header.
+ * Message type is the variant of the outermost enum of the message counted from 1,
+ * 0 for messages which are not enums. The receiver decodes the type it expects
+ * and rejects the frame if the decoded message has another message type.
+ */
+pub(crate) const HEADER_SIZE: usize = 7;
+
+/// Header and payload of a frame with `message`.
+pub(crate) fn encode_frame<T: Serialize>(
+    codec: Codec,
+    max_frame_size: usize,
+    message: &T,
+) -> Result<Vec<u8>> {
+    let payload = codec.encode(message)?;
+    if payload.len() > max_frame_size || payload.len() > u32::MAX as usize {
+        return Err(Error::protocol("Frame too large"));
+    }
+    let mut buffer = Vec::with_capacity(HEADER_SIZE + payload.len());
+    buffer.push(PROTOCOL_VERSION);
+    buffer.push(message_type(message));
+    buffer.push(codec.tag());
+    buffer.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(&payload);
+    Ok(buffer)
+}
+
+/// Message type of `message` for the frame header.
+pub(crate) fn message_type<T: Serialize>(message: &T) -> u8 {
+    match message.serialize(VariantProbe) {
+        Err(Probe::Variant(index)) => u8::try_from(index + 1).unwrap_or(u8::MAX),
+        _ => 0,
+    }
+}
+
+// serializer stopping at the first enum variant, wrappers like `Box` pass through
+struct VariantProbe;
+
+#[derive(Debug)]
+enum Probe {
+    Variant(u32),
+    NotEnum,
+}
+
+impl std::fmt::Display for Probe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for Probe {}
+
+impl ser::Error for Probe {
+    fn custom<T: std::fmt::Display>(_msg: T) -> Self {
+        Probe::NotEnum
+    }
+}
+
+macro_rules! not_enum {
+    ($($method:ident($value:ty)),*) => {
+        $(fn $method(self, _value: $value) -> std::result::Result<(), Probe> {
+            Err(Probe::NotEnum)
+        })*
+    };
+}
+
+impl Serializer for VariantProbe {
+    type Ok = ();
+    type Error = Probe;
+    type SerializeSeq = Impossible<(), Probe>;
+    type SerializeTuple = Impossible<(), Probe>;
+    type SerializeTupleStruct = Impossible<(), Probe>;
+    type SerializeTupleVariant = Impossible<(), Probe>;
+    type SerializeMap = Impossible<(), Probe>;
+    type SerializeStruct = Impossible<(), Probe>;
+    type SerializeStructVariant = Impossible<(), Probe>;
+
+    not_enum!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_unit_struct(&'static str)
+    );
+
+    fn serialize_none(self) -> std::result::Result<(), Probe> {
+        Err(Probe::NotEnum)
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> std::result::Result<(), Probe> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> std::result::Result<(), Probe> {
+        Err(Probe::NotEnum)
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> std::result::Result<(), Probe> {
+        Err(Probe::Variant(variant_index))
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> std::result::Result<(), Probe> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> std::result::Result<(), Probe> {
+        Err(Probe::Variant(variant_index))
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> std::result::Result<Self::SerializeSeq, Probe> {
+        Err(Probe::NotEnum)
+    }
+    fn serialize_tuple(self, _len: usize) -> std::result::Result<Self::SerializeTuple, Probe> {
+        Err(Probe::NotEnum)
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> std::result::Result<Self::SerializeTupleStruct, Probe> {
+        Err(Probe::NotEnum)
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> std::result::Result<Self::SerializeTupleVariant, Probe> {
+        Err(Probe::Variant(variant_index))
+    }
+    fn serialize_map(self, _len: Option<usize>) -> std::result::Result<Self::SerializeMap, Probe> {
+        Err(Probe::NotEnum)
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> std::result::Result<Self::SerializeStruct, Probe> {
+        Err(Probe::NotEnum)
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> std::result::Result<Self::SerializeStructVariant, Probe> {
+        Err(Probe::Variant(variant_index))
+    }
+}
+
+/*
+ * Read timeout may only expire while waiting for the first byte of a frame.
+ * Once a part of the frame is read, the position in the stream is lost if the rest
+ * does not arrive, so the connection fails for good and has to be replaced.
+ */
+pub(crate) fn out_of_sync() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Connection failed in the middle of a frame",
+    ))
+}
+
+/// Message type, codec and length of the payload from a frame header.
+pub(crate) fn parse_header(
+    header: [u8; HEADER_SIZE],
+    max_frame_size: usize,
+) -> Result<(u8, Codec, usize)> {
+    let [version, message_type, tag, length @ ..] = header;
+    if version != PROTOCOL_VERSION {
+        return Err(Error::protocol("Unsupported protocol version"));
+    }
+    let codec = Codec::from_tag(tag)?;
+    let length = u32::from_le_bytes(length) as usize;
+    if length > max_frame_size {
+        return Err(Error::protocol("Frame too large"));
+    }
+    Ok((message_type, codec, length))
+}
+
+/// Message from the payload of a frame with `message_type` in its header.
+pub(crate) fn decode_frame<T: DeserializeOwned + Serialize>(
+    expected: u8,
+    codec: Codec,
+    payload: &[u8],
+) -> Result<T> {
+    let message = codec.decode(payload)?;
+    if message_type(&message) != expected {
+        return Err(Error::protocol("Message type does not match the payload"));
+    }
+    Ok(message)
 }
 
 pub struct TcpConnection {
     stream: TcpStream,
+    codec: Codec,
+    max_frame_size: usize,
+    channel: Option<SecureChannel>,
+    /// A frame was read only partly.
+    out_of_sync: bool,
 }
 
 impl TcpConnection {
     pub fn new(stream: TcpStream) -> Self {
-        TcpConnection { stream }
+        Self::with_codec(stream, Codec::default())
+    }
+    pub fn with_codec(stream: TcpStream, codec: Codec) -> Self {
+        TcpConnection {
+            stream,
+            codec,
+            max_frame_size: MAX_FRAME_SIZE,
+            channel: None,
+            out_of_sync: false,
+        }
+    }
+    pub fn set_codec(&mut self, codec: Codec) {
+        self.codec = codec;
+    }
+    /// Frames with longer payload are rejected both when sending and receiving.
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size;
+    }
+    /// Receiving fails with `Error::Timeout` if nothing arrives within `timeout`.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        Ok(self.stream.set_read_timeout(timeout)?)
+    }
+    /// Runs the handshake, all later messages are encrypted and authenticated.
+    /// The side which opened the connection has to be the `initiator`.
+    pub fn secure(&mut self, identity: &Identity, initiator: bool) -> Result<()> {
+        self.channel = Some(SecureChannel::handshake(
+            &mut self.stream,
+            identity,
+            initiator,
+        )?);
+        Ok(())
     }
-    fn read_u32(&mut self) -> u32 {
-        let mut buffer = [0; 4];
-        self.stream.read_exact(&mut buffer).unwrap();
-        u32::from_le_bytes(buffer)
+    /// Address the peer connected from or was dialed at.
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        Ok(self.stream.peer_addr()?)
+    }
+    /// Static key of the peer, if the connection is secured.
+    pub fn peer_identity(&self) -> Option<[u8; 32]> {
+        self.channel.as_ref().map(SecureChannel::peer)
+    }
+    pub(crate) fn handshake_hash(&self) -> Option<&[u8]> {
+        self.channel.as_ref().map(SecureChannel::handshake_hash)
+    }
+    #[cfg(test)]
+    pub(crate) fn shutdown(&self) {
+        self.stream.shutdown(std::net::Shutdown::Both).unwrap();
+    }
+    fn read_exact(&mut self, buffer: &mut [u8]) -> Result<()> {
+        match self.channel.as_mut() {
+            Some(channel) => channel.read_exact(&mut self.stream, buffer),
+            None => Ok(self.stream.read_exact(buffer)?),
+        }
+    }
+    fn write_all(&mut self, buffer: &[u8]) -> Result<()> {
+        match self.channel.as_mut() {
+            Some(channel) => channel.write_all(&mut self.stream, buffer),
+            None => Ok(self.stream.write_all(buffer)?),
+        }
+    }
+    // nothing is consumed, so timing out here leaves the stream intact
+    fn wait_for_frame(&mut self) -> Result<()> {
+        if !self
+            .channel
+            .as_ref()
+            .is_some_and(SecureChannel::has_buffered)
+        {
+            self.stream.peek(&mut [0; 1])?;
+        }
+        Ok(())
+    }
+    fn read_frame(&mut self) -> Result<(u8, Codec, Vec<u8>)> {
+        let mut header = [0; HEADER_SIZE];
+        self.read_exact(&mut header)?;
+        let (message_type, codec, length) = parse_header(header, self.max_frame_size)?;
+        let mut buffer = vec![0_u8; length];
+        self.read_exact(&mut buffer)?;
+        Ok((message_type, codec, buffer))
     }
 }
 
 impl Connection for TcpConnection {
-    fn send<T: Serialize>(&mut self, message: &T) {
-        let serialized = serde_json::to_string(&message).unwrap();
-        let length = serialized.len() as u32;
-        let lenght_bytes = length.to_le_bytes();
-        let mut buffer = vec![0; 4 + length as usize];
-        lenght_bytes
-            .chain(serialized.as_bytes())
-            .read_exact(&mut buffer)
-            .unwrap();
-        self.stream.write_all(&buffer).unwrap();
-    }
-
-    fn receive<T: DeserializeOwned>(&mut self) -> T {
-        let length = self.read_u32();
-        let mut buffer = vec![0_u8; length as usize];
-        self.stream.read_exact(&mut buffer).unwrap();
-        let serialized = String::from_utf8_lossy(buffer.as_slice()).to_string();
-        let deserialized: T = serde_json::from_str(&serialized).unwrap();
-        deserialized
+    fn send<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        let buffer = encode_frame(self.codec, self.max_frame_size, message)?;
+        self.write_all(&buffer)
+    }
+
+    fn receive<T: DeserializeOwned + Serialize>(&mut self) -> Result<T> {
+        if self.out_of_sync {
+            return Err(out_of_sync());
+        }
+        self.wait_for_frame()?;
+        let (message_type, codec, buffer) = self.read_frame().map_err(|err| {
+            self.out_of_sync = true;
+            match err {
+                Error::Timeout => out_of_sync(),
+                err => err,
+            }
+        })?;
+        decode_frame(message_type, codec, &buffer)
     }
 }
@@ -0,0 +1,106 @@
+use std::fmt::Display;
+
+use crate::message::{AbortNotice, Evidence};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    MalformedMessage(Box<dyn std::error::Error + Send + Sync>),
+    ProtocolViolation(String),
+    Cheating {
+        player: usize,
+        reason: String,
+        evidence: Option<Box<Evidence>>,
+    },
+    /// Other player stopped the game, the notice is checked against its pinned key.
+    Aborted(Box<AbortNotice>),
+    /// Other player did not send anything within the read timeout.
+    Timeout,
+    Secure(snow::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn protocol(reason: &str) -> Self {
+        Error::ProtocolViolation(reason.to_string())
+    }
+    pub fn cheating(player: usize, reason: &str) -> Self {
+        Error::Cheating {
+            player,
+            reason: reason.to_string(),
+            evidence: None,
+        }
+    }
+    /// Cheating which can be shown to others with `evidence`.
+    pub fn cheating_with(player: usize, reason: &str, evidence: Evidence) -> Self {
+        Error::Cheating {
+            player,
+            reason: reason.to_string(),
+            evidence: Some(Box::new(evidence)),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::MalformedMessage(err) => write!(f, "Malformed message: {}", err),
+            Error::ProtocolViolation(reason) => write!(f, "Protocol violation: {}", reason),
+            Error::Cheating { player, reason, .. } => {
+                write!(f, "Player {} cheated: {}", player, reason)
+            }
+            Error::Aborted(notice) => {
+                write!(f, "Player {} aborted: {}", notice.player_id, notice.reason)
+            }
+            Error::Timeout => write!(f, "Timed out waiting for other player"),
+            Error::Secure(err) => write!(f, "Secure channel error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::MalformedMessage(err) => Some(err.as_ref()),
+            Error::Secure(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        match value.kind() {
+            // reads past the timeout of the stream fail with one of these
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => Error::Timeout,
+            _ => Error::Io(value),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Error::MalformedMessage(Box::new(value))
+    }
+}
+
+impl From<ciborium::ser::Error<std::io::Error>> for Error {
+    fn from(value: ciborium::ser::Error<std::io::Error>) -> Self {
+        Error::MalformedMessage(Box::new(value))
+    }
+}
+
+impl From<ciborium::de::Error<std::io::Error>> for Error {
+    fn from(value: ciborium::de::Error<std::io::Error>) -> Self {
+        Error::MalformedMessage(Box::new(value))
+    }
+}
+
+impl From<snow::Error> for Error {
+    fn from(value: snow::Error) -> Self {
+        Error::Secure(value)
+    }
+}
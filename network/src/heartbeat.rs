@@ -0,0 +1,89 @@
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::message::{MessageConnection, ProtocolMessage};
+use crate::session::SessionState;
+
+/*
+ * Other players wait for the player with a read timeout, but the player itself
+ * may take long to decide, whether it picks a card or a bet. So heartbeats are sent
+ * by the connection, from a thread which sends one every interval.
+ * While the player receives, the connection is locked and the thread waits,
+ * the player is not the one deciding then. Dropping the connection stops the thread.
+ * Resumable connections send heartbeats outside of their numbered messages.
+ */
+
+pub struct HeartbeatConnection {
+    inner: Arc<Mutex<Box<dyn MessageConnection>>>,
+    // dropped with the connection, which ends the thread
+    _stop: Sender<()>,
+}
+
+impl HeartbeatConnection {
+    pub fn new<C: MessageConnection + 'static>(connection: C, interval: Duration) -> Self {
+        let inner: Arc<Mutex<Box<dyn MessageConnection>>> =
+            Arc::new(Mutex::new(Box::new(connection)));
+        let (stop, stopped) = channel();
+        let sender = inner.clone();
+        thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                // failed connection shows up on the next message of the player
+                let _ = sender.lock().unwrap().send_heartbeat();
+            }
+        });
+        HeartbeatConnection { inner, _stop: stop }
+    }
+}
+
+impl MessageConnection for HeartbeatConnection {
+    fn send_message(&mut self, message: &ProtocolMessage) -> Result<()> {
+        self.inner.lock().unwrap().send_message(message)
+    }
+    fn receive_message(&mut self) -> Result<ProtocolMessage> {
+        self.inner.lock().unwrap().receive_message()
+    }
+    fn send_heartbeat(&mut self) -> Result<()> {
+        self.inner.lock().unwrap().send_heartbeat()
+    }
+    fn checkpoint(&mut self) -> Option<SessionState> {
+        self.inner.lock().unwrap().checkpoint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::HeartbeatConnection;
+    use crate::channel::ChannelConnection;
+    use crate::error::Error;
+    use crate::message::{BetAction, MessageConnection, ProtocolMessage};
+
+    #[test]
+    fn heartbeats_while_idle() {
+        let (first, mut second) = ChannelConnection::pair();
+        let mut first = HeartbeatConnection::new(first, Duration::from_millis(5));
+        second.set_read_timeout(Some(Duration::from_millis(200)));
+        thread::sleep(Duration::from_millis(30));
+        first
+            .send_message(&ProtocolMessage::Bet(BetAction::Check))
+            .unwrap();
+        let mut heartbeats = 0;
+        while let ProtocolMessage::Heartbeat = second.receive_message().unwrap() {
+            heartbeats += 1;
+        }
+        assert!(heartbeats > 0);
+        drop(first);
+        // the thread stops with the connection, which closes the channel
+        loop {
+            match second.receive_message() {
+                Ok(ProtocolMessage::Heartbeat) => continue,
+                result => break assert!(matches!(result, Err(Error::Io(_)))),
+            }
+        }
+    }
+}
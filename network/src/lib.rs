@@ -1,46 +1,623 @@
+pub mod async_connection;
+pub mod channel;
 pub mod con_startup;
 pub mod connection;
+pub mod error;
+pub mod heartbeat;
 pub mod message;
+pub mod secure;
+pub mod session;
 
 #[cfg(test)]
 mod test {
-    use super::{con_startup::ConStartup, connection::Connection, message::Message};
+    use super::{
+        async_connection::AsyncConnection,
+        channel::ChannelConnection,
+        con_startup::ConStartup,
+        connection::{encode_frame, Codec, Connection, TcpConnection, HEADER_SIZE, MAX_FRAME_SIZE},
+        error::{Error, Result},
+        message::{AbortNotice, BetAction, ProtocolMessage, SignedAbort},
+        secure::{Identity, PublicKey},
+    };
 
+    use crypto::deck::DeckSpec;
+    use crypto::signature::SigningKey;
+    use crypto::types::EncryptedValue;
+    use rand::thread_rng;
+    use serde::{Deserialize, Serialize};
+
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
     use std::thread;
+    use std::time::Duration;
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct Message {
+        x: u32,
+        s: String,
+    }
 
     const ADDRESS: &str = "127.0.0.1:";
-    const PORT_BASE: u32 = 6700;
 
+    // port picked by the system, so tests running at once do not collide
+    fn listener() -> (TcpListener, String) {
+        let listener = TcpListener::bind(ADDRESS.to_string() + "0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        (listener, address)
+    }
+
+    // nobody listens on it, unless another test gets the same port
     fn address() -> String {
-        ADDRESS.to_string() + &PORT_BASE.to_string()
+        listener().1
     }
 
     #[test]
     fn test() {
+        let (listener, address) = listener();
         thread::spawn(move || {
             let con_startup = ConStartup::new(2, 0);
-            let mut connection = con_startup.initialize(&address());
+            let mut connection = con_startup.initialize_on(&listener).unwrap();
 
-            let msg: Message = connection.receive();
+            let msg: Message = connection.receive().unwrap();
             println!("server received: {} {}", msg.x, msg.s);
             assert_eq!(msg.x, 5);
 
-            connection.send(&Message {
-                x: 7,
-                s: String::from("helloo2"),
-            });
+            connection
+                .send(&Message {
+                    x: 7,
+                    s: String::from("helloo2"),
+                })
+                .unwrap();
         });
 
         let con_startup = ConStartup::new(2, 1);
-        let mut connection = con_startup.initialize(&address());
+        let mut connection = con_startup.initialize(&address).unwrap();
 
-        connection.send(&Message {
-            x: 5,
-            s: String::from("hello1"),
-        });
+        connection
+            .send(&Message {
+                x: 5,
+                s: String::from("hello1"),
+            })
+            .unwrap();
 
-        let msg: Message = connection.receive();
+        let msg: Message = connection.receive().unwrap();
         println!("client received: {} {}", msg.x, msg.s);
         assert_eq!(msg.x, 7);
     }
+
+    fn check_mesh<C: Connection>(num_players: usize, player_id: usize, mut connections: Vec<C>) {
+        assert_eq!(connections.len(), num_players - 1);
+        for connection in connections.iter_mut() {
+            connection.send(&player_id).unwrap();
+        }
+        let ids = connections
+            .iter_mut()
+            .map(|connection| connection.receive::<usize>().unwrap())
+            .collect::<Vec<_>>();
+        let expected = (0..num_players)
+            .filter(|&id| id != player_id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, expected);
+    }
+
+    fn mesh_connections(num_players: usize) -> Vec<Vec<TcpConnection>> {
+        let listeners = (0..num_players)
+            .map(|_| TcpListener::bind(ADDRESS.to_string() + "0").unwrap())
+            .collect::<Vec<_>>();
+        let addresses = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().to_string())
+            .collect::<Vec<_>>();
+        let handles = listeners
+            .into_iter()
+            .enumerate()
+            .map(|(player_id, listener)| {
+                let addresses = addresses.clone();
+                thread::spawn(move || {
+                    let con_startup = ConStartup::new(num_players, player_id);
+                    con_startup.connect_mesh(&listener, &addresses).unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    }
+
+    #[test]
+    fn mesh() {
+        const NUM_PLAYERS: usize = 4;
+        let handles = mesh_connections(NUM_PLAYERS)
+            .into_iter()
+            .enumerate()
+            .map(|(player_id, connections)| {
+                thread::spawn(move || check_mesh(NUM_PLAYERS, player_id, connections))
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+    }
+
+    #[test]
+    fn rendezvous() {
+        const NUM_PLAYERS: usize = 3;
+        let host = address();
+        let handles = (0..NUM_PLAYERS)
+            .map(|player_id| {
+                let host = host.clone();
+                thread::spawn(move || {
+                    let con_startup = ConStartup::new(NUM_PLAYERS, player_id);
+                    let connections = con_startup
+                        .initialize_rendezvous(&host, &"0.0.0.0:0".to_string())
+                        .unwrap();
+                    check_mesh(NUM_PLAYERS, player_id, connections);
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+    }
+
+    #[test]
+    fn different_curves() {
+        let listeners = (0..2)
+            .map(|_| TcpListener::bind(ADDRESS.to_string() + "0").unwrap())
+            .collect::<Vec<_>>();
+        let addresses = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().to_string())
+            .collect::<Vec<_>>();
+        let handles = listeners
+            .into_iter()
+            .zip(["pallas", "bls12_381"])
+            .enumerate()
+            .map(|(player_id, (listener, curve))| {
+                let addresses = addresses.clone();
+                thread::spawn(move || {
+                    let con_startup = ConStartup::new(2, player_id).with_curve(curve);
+                    con_startup.connect_mesh(&listener, &addresses)
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut results = handles.into_iter().map(|h| h.join().unwrap());
+        assert!(matches!(
+            results.next().unwrap(),
+            Err(Error::ProtocolViolation(_))
+        ));
+        assert!(results.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn different_decks() {
+        let listeners = (0..2)
+            .map(|_| TcpListener::bind(ADDRESS.to_string() + "0").unwrap())
+            .collect::<Vec<_>>();
+        let addresses = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().to_string())
+            .collect::<Vec<_>>();
+        let handles = listeners
+            .into_iter()
+            .zip([DeckSpec::schnapsen(), DeckSpec::skat()])
+            .enumerate()
+            .map(|(player_id, (listener, deck))| {
+                let addresses = addresses.clone();
+                thread::spawn(move || {
+                    let con_startup = ConStartup::new(2, player_id).with_deck(&deck);
+                    con_startup.connect_mesh(&listener, &addresses)
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut results = handles.into_iter().map(|h| h.join().unwrap());
+        assert!(matches!(
+            results.next().unwrap(),
+            Err(Error::ProtocolViolation(_))
+        ));
+        assert!(results.next().unwrap().is_err());
+    }
+
+    // `pinned[i]` are the identities player `i` expects from the others
+    fn secure_mesh(
+        identities: Vec<Identity>,
+        pinned: Vec<Vec<PublicKey>>,
+    ) -> Vec<Result<Vec<TcpConnection>>> {
+        let num_players = identities.len();
+        let listeners = (0..num_players)
+            .map(|_| TcpListener::bind(ADDRESS.to_string() + "0").unwrap())
+            .collect::<Vec<_>>();
+        let addresses = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().to_string())
+            .collect::<Vec<_>>();
+        let handles = listeners
+            .into_iter()
+            .zip(identities)
+            .zip(pinned)
+            .enumerate()
+            .map(|(player_id, ((listener, identity), peers))| {
+                let addresses = addresses.clone();
+                thread::spawn(move || {
+                    let con_startup =
+                        ConStartup::new(num_players, player_id).with_identity(identity, peers);
+                    con_startup.connect_mesh(&listener, &addresses)
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    }
+
+    #[test]
+    fn secure_connections() {
+        const NUM_PLAYERS: usize = 3;
+        let identities = (0..NUM_PLAYERS)
+            .map(|_| Identity::generate(&mut thread_rng()))
+            .collect::<Vec<_>>();
+        let keys = identities
+            .iter()
+            .map(Identity::public_key)
+            .collect::<Vec<_>>();
+        let results = secure_mesh(identities, vec![keys.clone(); NUM_PLAYERS]);
+        let handles = results
+            .into_iter()
+            .enumerate()
+            .map(|(player_id, connections)| {
+                let connections = connections.unwrap();
+                let peers = connections
+                    .iter()
+                    .map(|connection| connection.peer_identity().unwrap())
+                    .collect::<Vec<_>>();
+                let mut expected = keys.iter().map(|key| key.noise).collect::<Vec<_>>();
+                expected.remove(player_id);
+                assert_eq!(peers, expected);
+                thread::spawn(move || check_mesh(NUM_PLAYERS, player_id, connections))
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+    }
+
+    #[test]
+    fn wrong_pinned_identity() {
+        let identities = vec![
+            Identity::generate(&mut thread_rng()),
+            Identity::generate(&mut thread_rng()),
+        ];
+        let keys = identities
+            .iter()
+            .map(Identity::public_key)
+            .collect::<Vec<_>>();
+        let impostor = vec![
+            Identity::generate(&mut thread_rng()).public_key(),
+            *keys.get(1).unwrap(),
+        ];
+        let mut results = secure_mesh(identities, vec![keys, impostor]).into_iter();
+        assert!(results.next().unwrap().is_ok());
+        assert!(matches!(
+            results.next().unwrap(),
+            Err(Error::ProtocolViolation(_))
+        ));
+    }
+
+    #[test]
+    fn wrong_pinned_signing_key() {
+        let identities = vec![
+            Identity::generate(&mut thread_rng()),
+            Identity::generate(&mut thread_rng()),
+        ];
+        let keys = identities
+            .iter()
+            .map(Identity::public_key)
+            .collect::<Vec<_>>();
+        // static key of the first player is right, but hello is signed by another key
+        let mut forged = keys.clone();
+        forged.get_mut(0).unwrap().signing =
+            Identity::generate(&mut thread_rng()).public_key().signing;
+        let mut results = secure_mesh(identities, vec![keys, forged]).into_iter();
+        assert!(results.next().unwrap().is_ok());
+        assert!(matches!(
+            results.next().unwrap(),
+            Err(Error::ProtocolViolation(_))
+        ));
+    }
+
+    fn connection_pair() -> (TcpConnection, TcpConnection) {
+        let mut connections = mesh_connections(2);
+        let second = connections.pop().unwrap().pop().unwrap();
+        let first = connections.pop().unwrap().pop().unwrap();
+        (first, second)
+    }
+
+    #[test]
+    fn malformed_message() {
+        let (mut first, mut second) = connection_pair();
+        first.send(&"not a number").unwrap();
+        assert!(matches!(
+            second.receive::<usize>(),
+            Err(Error::MalformedMessage(_))
+        ));
+    }
+
+    #[test]
+    fn cbor_messages() {
+        let (mut first, mut second) = connection_pair();
+        first.set_codec(Codec::Cbor);
+        first
+            .send(&Message {
+                x: 5,
+                s: String::from("binary"),
+            })
+            .unwrap();
+        let msg: Message = second.receive().unwrap();
+        assert_eq!(msg.x, 5);
+        assert_eq!(msg.s, "binary");
+    }
+
+    #[test]
+    fn frame_header() {
+        let frame = encode_frame(Codec::Cbor, MAX_FRAME_SIZE, &5_usize).unwrap();
+        assert_eq!(*frame.get(1).unwrap(), 0);
+        let messages = [
+            ProtocolMessage::Heartbeat,
+            ProtocolMessage::Bet(BetAction::Check),
+            ProtocolMessage::Bet(BetAction::Raise(5)),
+        ];
+        let types = messages
+            .iter()
+            .map(|message| {
+                *encode_frame(Codec::Cbor, MAX_FRAME_SIZE, message)
+                    .unwrap()
+                    .get(1)
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_ne!(types.first(), types.get(1));
+        assert_eq!(types.get(1), types.get(2));
+        assert!(types.iter().all(|&message_type| message_type > 0));
+    }
+
+    #[test]
+    fn mismatched_message_type() {
+        let listener = TcpListener::bind(ADDRESS.to_string() + "0").unwrap();
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut connection = TcpConnection::new(listener.accept().unwrap().0);
+        let heartbeat = encode_frame(
+            Codec::default(),
+            MAX_FRAME_SIZE,
+            &ProtocolMessage::Heartbeat,
+        )
+        .unwrap();
+        let mut frame = encode_frame(
+            Codec::default(),
+            MAX_FRAME_SIZE,
+            &ProtocolMessage::Bet(BetAction::Check),
+        )
+        .unwrap();
+        *frame.get_mut(1).unwrap() = *heartbeat.get(1).unwrap();
+        stream.write_all(&frame).unwrap();
+        assert!(matches!(
+            connection.receive::<ProtocolMessage>(),
+            Err(Error::ProtocolViolation(_))
+        ));
+        stream.write_all(&heartbeat).unwrap();
+        assert!(matches!(
+            connection.receive::<ProtocolMessage>(),
+            Ok(ProtocolMessage::Heartbeat)
+        ));
+    }
+
+    #[test]
+    fn cbor_points_are_byte_strings() {
+        let point = *DeckSpec::short().deck().first().unwrap();
+        let bytes = Codec::Cbor.encode(&point).unwrap();
+        // major type 2 is a byte string, arrays of integers would take twice the size
+        assert_eq!(bytes.first().unwrap() >> 5, 2);
+        assert!(bytes.len() <= point.to_bytes().len() + 2);
+        assert_eq!(Codec::Cbor.decode::<EncryptedValue>(&bytes).unwrap(), point);
+    }
+
+    #[test]
+    fn oversized_frame() {
+        let (mut first, mut second) = connection_pair();
+        second.set_max_frame_size(8);
+        first.send(&"longer than eight bytes").unwrap();
+        assert!(matches!(
+            second.receive::<String>(),
+            Err(Error::ProtocolViolation(_))
+        ));
+        first.set_max_frame_size(8);
+        assert!(matches!(
+            first.send(&"longer than eight bytes"),
+            Err(Error::ProtocolViolation(_))
+        ));
+    }
+
+    #[test]
+    fn read_timeout() {
+        let (mut first, mut second) = connection_pair();
+        second
+            .set_read_timeout(Some(Duration::from_millis(20)))
+            .unwrap();
+        assert!(matches!(second.receive::<usize>(), Err(Error::Timeout)));
+        // nothing was read, the connection still works
+        first.send(&5_usize).unwrap();
+        assert_eq!(second.receive::<usize>().unwrap(), 5);
+    }
+
+    #[test]
+    fn partial_frame() {
+        let listener = TcpListener::bind(ADDRESS.to_string() + "0").unwrap();
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut connection = TcpConnection::new(listener.accept().unwrap().0);
+        connection
+            .set_read_timeout(Some(Duration::from_millis(20)))
+            .unwrap();
+        let frame = encode_frame(Codec::default(), MAX_FRAME_SIZE, &5_usize).unwrap();
+        stream.write_all(&frame[..HEADER_SIZE]).unwrap();
+        assert!(matches!(connection.receive::<usize>(), Err(Error::Io(_))));
+        // rest of the frame arrives too late, the position in the stream is lost
+        stream.write_all(&frame[HEADER_SIZE..]).unwrap();
+        stream.write_all(&frame).unwrap();
+        assert!(matches!(connection.receive::<usize>(), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn connect_timeout() {
+        let address = address();
+        let con_startup = ConStartup::new(2, 1).with_connect_timeout(Duration::from_millis(50));
+        assert!(matches!(
+            con_startup.initialize(&address),
+            Err(Error::Timeout)
+        ));
+    }
+
+    #[test]
+    fn closed_connection() {
+        let (first, mut second) = connection_pair();
+        drop(first);
+        assert!(matches!(second.receive::<usize>(), Err(Error::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn async_connection() {
+        let listener = tokio::net::TcpListener::bind(ADDRESS.to_string() + "0")
+            .await
+            .unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let con_startup = ConStartup::new(2, 0);
+            let mut connection = con_startup.initialize_async_on(&listener).await?;
+            let msg: Message = connection.receive().await?;
+            connection.send(&msg.x).await?;
+            Ok::<_, Error>(msg.s)
+        });
+
+        let con_startup = ConStartup::new(2, 1);
+        let mut connection = con_startup.initialize_async(&address).await.unwrap();
+        connection
+            .send(&Message {
+                x: 5,
+                s: String::from("async"),
+            })
+            .await
+            .unwrap();
+        assert_eq!(connection.receive::<u32>().await.unwrap(), 5);
+        assert_eq!(server.await.unwrap().unwrap(), "async");
+    }
+
+    #[test]
+    fn async_and_blocking() {
+        let (listener, address) = listener();
+        let server = thread::spawn(move || {
+            let mut connection = ConStartup::new(2, 0).initialize_on(&listener).unwrap();
+            connection.send(&"blocking").unwrap();
+        });
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let msg: String = runtime.block_on(async {
+            let mut connection = ConStartup::new(2, 1)
+                .initialize_async(&address)
+                .await
+                .unwrap();
+            connection.receive().await.unwrap()
+        });
+        assert_eq!(msg, "blocking");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn secure_async_and_blocking() {
+        let (listener, address) = listener();
+        let identities = [
+            Identity::generate(&mut thread_rng()),
+            Identity::generate(&mut thread_rng()),
+        ];
+        let keys = identities
+            .iter()
+            .map(Identity::public_key)
+            .collect::<Vec<_>>();
+        // longer than one Noise message
+        let long = "x".repeat(100_000);
+        let (server_identity, server_keys, server_long) = (
+            identities.first().unwrap().clone(),
+            keys.clone(),
+            long.clone(),
+        );
+        let server = thread::spawn(move || {
+            let mut connection = ConStartup::new(2, 0)
+                .with_identity(server_identity, server_keys)
+                .initialize_on(&listener)
+                .unwrap();
+            connection.send(&server_long).unwrap();
+            connection.receive::<String>().unwrap()
+        });
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (peer, msg) = runtime.block_on(async {
+            let mut connection = ConStartup::new(2, 1)
+                .with_identity(identities.get(1).unwrap().clone(), keys.clone())
+                .initialize_async(&address)
+                .await
+                .unwrap();
+            let msg: String = connection.receive().await.unwrap();
+            connection.send(&"async").await.unwrap();
+            (connection.peer_identity(), msg)
+        });
+        assert_eq!(peer, Some(keys.first().unwrap().noise));
+        assert_eq!(msg, long);
+        assert_eq!(server.join().unwrap(), "async");
+    }
+
+    #[test]
+    fn channel_mesh() {
+        const NUM_PLAYERS: usize = 4;
+        let handles = ChannelConnection::mesh(NUM_PLAYERS)
+            .into_iter()
+            .enumerate()
+            .map(|(player_id, connections)| {
+                thread::spawn(move || check_mesh(NUM_PLAYERS, player_id, connections))
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+    }
+
+    #[test]
+    fn channel_codecs() {
+        let (mut first, mut second) = ChannelConnection::pair();
+        first.set_codec(Codec::Cbor);
+        first.send(&"binary").unwrap();
+        assert_eq!(second.receive::<String>().unwrap(), "binary");
+        first.send(&"not a number").unwrap();
+        assert!(matches!(
+            second.receive::<usize>(),
+            Err(Error::MalformedMessage(_))
+        ));
+    }
+
+    #[test]
+    fn closed_channel() {
+        let (first, mut second) = ChannelConnection::pair();
+        second.set_read_timeout(Some(Duration::from_millis(20)));
+        assert!(matches!(second.receive::<usize>(), Err(Error::Timeout)));
+        drop(first);
+        assert!(matches!(second.receive::<usize>(), Err(Error::Io(_))));
+        assert!(matches!(second.send(&5), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn abort_accuses_only_with_pinned_key() {
+        let key = SigningKey::generate(&mut thread_rng());
+        let notice = AbortNotice {
+            player_id: 0,
+            reason: "Wrong share".to_string(),
+            cheater: Some(1),
+            evidence: None,
+        };
+        let abort = SignedAbort::sign(notice, &key, &mut thread_rng());
+        let pinned = key.verifying_key();
+        assert!(matches!(
+            abort.clone().into_error(0, Some(&pinned)),
+            Error::Aborted(notice) if notice.cheater == Some(1)
+        ));
+        assert!(matches!(
+            abort.clone().into_error(0, None),
+            Error::Aborted(notice) if notice.cheater.is_none()
+        ));
+        assert!(matches!(
+            abort.into_error(1, Some(&pinned)),
+            Error::ProtocolViolation(_)
+        ));
+    }
 }
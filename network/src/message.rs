@@ -1,7 +1,294 @@
+pub use common::game::BetAction;
+use crypto::shuffle_v2::{DecryptionShare, EncryptWithProof, ShuffleProof, ShuffleWithProof};
+use crypto::shuffle_v3::ShuffleWithArgument;
+use crypto::signature::{Signature, SigningKey, VerifyingKey};
+use crypto::types::{EncryptedValue, KeyType};
+use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Message {
-    pub x: u32,
-    pub s: String,
+use crate::channel::ChannelConnection;
+use crate::connection::{Connection, TcpConnection};
+use crate::error::{Error, Result};
+use crate::session::{ResumableConnection, SessionState};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hello {
+    pub player_id: usize,
+    pub num_players: usize,
+    pub curve: String,
+    /// Hash of the deck spec, if the player chose one.
+    pub deck: Option<[u8; 32]>,
+    /// Id of the game session.
+    pub game: u64,
+    /// Signature by the identity of the sender, present on secured connections.
+    #[serde(default)]
+    pub signature: Option<Signature>,
+}
+
+impl Hello {
+    // the handshake hash binds the signature to one connection, so it cannot be replayed
+    fn signed_bytes(&self, handshake_hash: &[u8]) -> Vec<u8> {
+        let unsigned = Hello {
+            signature: None,
+            ..self.clone()
+        };
+        let mut bytes = handshake_hash.to_vec();
+        bytes.extend(serde_json::to_vec(&unsigned).unwrap());
+        bytes
+    }
+
+    pub fn sign<R: RngCore + CryptoRng>(
+        mut self,
+        handshake_hash: &[u8],
+        key: &SigningKey,
+        rng: &mut R,
+    ) -> Self {
+        self.signature = Some(key.sign(&self.signed_bytes(handshake_hash), rng));
+        self
+    }
+
+    pub fn verify(&self, handshake_hash: &[u8], key: &VerifyingKey) -> bool {
+        self.signature
+            .as_ref()
+            .is_some_and(|signature| key.verify(&self.signed_bytes(handshake_hash), signature))
+    }
+}
+
+/// Chain of shuffles, tagged with the kind of proof.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ShuffleChain {
+    Proofs(Vec<ShuffleWithProof>),
+    Arguments(Vec<ShuffleWithArgument>),
+}
+
+/// Shuffle proofs which can be sent in `ProtocolMessage`.
+pub trait ShuffleMessage: ShuffleProof {
+    fn into_chain(proofs: Vec<Self>) -> ShuffleChain;
+    /// `None` if the chain holds other kind of proofs.
+    fn from_chain(chain: ShuffleChain) -> Option<Vec<Self>>;
+}
+
+impl ShuffleMessage for ShuffleWithProof {
+    fn into_chain(proofs: Vec<Self>) -> ShuffleChain {
+        ShuffleChain::Proofs(proofs)
+    }
+    fn from_chain(chain: ShuffleChain) -> Option<Vec<Self>> {
+        match chain {
+            ShuffleChain::Proofs(proofs) => Some(proofs),
+            _ => None,
+        }
+    }
+}
+
+impl ShuffleMessage for ShuffleWithArgument {
+    fn into_chain(proofs: Vec<Self>) -> ShuffleChain {
+        ShuffleChain::Arguments(proofs)
+    }
+    fn from_chain(chain: ShuffleChain) -> Option<Vec<Self>> {
+        match chain {
+            ShuffleChain::Arguments(proofs) => Some(proofs),
+            _ => None,
+        }
+    }
+}
+
+/// Data showing that a player cheated, anyone with the commitments can check it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Evidence {
+    /// Decryption share of card `ind` which does not match the commitment to the key.
+    Share {
+        ind: usize,
+        card: EncryptedValue,
+        share: Box<DecryptionShare>,
+    },
+    /// Step `step` of a chain of proofs which does not verify.
+    Proof { step: usize, proof: Value },
+}
+
+/// Reason why the sender stops the game.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AbortNotice {
+    /// Id of the sender.
+    pub player_id: usize,
+    pub reason: String,
+    /// Player the sender caught cheating.
+    pub cheater: Option<usize>,
+    pub evidence: Option<Evidence>,
+}
+
+impl AbortNotice {
+    /// Notice for other players after the game of `player_id` failed with `err`,
+    /// `None` if they cannot be told or already know.
+    pub fn for_error(player_id: usize, err: &Error) -> Option<Self> {
+        match err {
+            Error::Io(_) | Error::Aborted(_) => None,
+            Error::Cheating {
+                player,
+                reason,
+                evidence,
+            } => Some(AbortNotice {
+                player_id,
+                reason: reason.clone(),
+                cheater: Some(*player),
+                evidence: evidence.as_deref().cloned(),
+            }),
+            err => Some(AbortNotice {
+                player_id,
+                reason: err.to_string(),
+                cheater: None,
+                evidence: None,
+            }),
+        }
+    }
+}
+
+/// Abort notice signed by the long-term key of its sender.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedAbort {
+    pub notice: AbortNotice,
+    pub signature: Signature,
+}
+
+impl SignedAbort {
+    pub fn sign<R: RngCore + CryptoRng>(
+        notice: AbortNotice,
+        key: &SigningKey,
+        rng: &mut R,
+    ) -> Self {
+        let signature = key.sign(&serde_json::to_vec(&notice).unwrap(), rng);
+        SignedAbort { notice, signature }
+    }
+
+    pub fn verify(&self, key: &VerifyingKey) -> bool {
+        key.verify(&serde_json::to_vec(&self.notice).unwrap(), &self.signature)
+    }
+
+    /// Error for the abort which arrived from player `sender` with the pinned key `key`.
+    /// Without a pinned key the notice cannot be checked, so it accuses nobody.
+    pub fn into_error(self, sender: usize, key: Option<&VerifyingKey>) -> Error {
+        if self.notice.player_id != sender {
+            return Error::protocol("Abort is sent on behalf of another player");
+        }
+        match key {
+            Some(key) if !self.verify(key) => Error::protocol("Abort is not signed by its sender"),
+            Some(_) => Error::Aborted(Box::new(self.notice)),
+            None => Error::Aborted(Box::new(AbortNotice {
+                cheater: None,
+                evidence: None,
+                ..self.notice
+            })),
+        }
+    }
+}
+
+/// Every message exchanged by players after the connection is established.
+/// Version of the messages is carried in the frame header.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ProtocolMessage {
+    /// Deck passed around the ring during basic preparation.
+    Deck(Vec<EncryptedValue>),
+    /// Commitments to per-card keys after basic preparation.
+    KeyCommitments(Vec<EncryptedValue>),
+    /// Chain of shuffles made so far.
+    Shuffles(ShuffleChain),
+    /// Complete chain of shuffles together with encryptions made so far.
+    ShufflesAndEncryptions(ShuffleChain, Vec<EncryptWithProof>),
+    /// Complete chain of encryptions.
+    Encryptions(Vec<EncryptWithProof>),
+    /// Request for decryption share of card `ind` drawn from the deck,
+    /// with shares of the players asked before.
+    DrawRequest {
+        ind: usize,
+        shares: Vec<DecryptionShare>,
+    },
+    /// Decryption share answering `DrawRequest`.
+    KeyShare(DecryptionShare),
+    /// Decryption share of card `ind` revealed from the deck to everybody.
+    TableShare { ind: usize, share: DecryptionShare },
+    /// Card `ind` played face up, with shares of all players which decrypt it.
+    PlayCard {
+        ind: usize,
+        shares: Vec<DecryptionShare>,
+    },
+    /// Betting decision of the sender.
+    Bet(BetAction),
+    /// All per-card keys, revealed at the end of the game.
+    RevealKeys(Vec<KeyType>),
+    /// Sender stops the game.
+    Abort(Box<SignedAbort>),
+    /// Sender is alive, but still waiting for a decision.
+    Heartbeat,
+}
+
+impl ProtocolMessage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProtocolMessage::Deck(_) => "Deck",
+            ProtocolMessage::KeyCommitments(_) => "KeyCommitments",
+            ProtocolMessage::Shuffles(_) => "Shuffles",
+            ProtocolMessage::ShufflesAndEncryptions(_, _) => "ShufflesAndEncryptions",
+            ProtocolMessage::Encryptions(_) => "Encryptions",
+            ProtocolMessage::DrawRequest { .. } => "DrawRequest",
+            ProtocolMessage::KeyShare(_) => "KeyShare",
+            ProtocolMessage::TableShare { .. } => "TableShare",
+            ProtocolMessage::PlayCard { .. } => "PlayCard",
+            ProtocolMessage::Bet(_) => "Bet",
+            ProtocolMessage::RevealKeys(_) => "RevealKeys",
+            ProtocolMessage::Abort(_) => "Abort",
+            ProtocolMessage::Heartbeat => "Heartbeat",
+        }
+    }
+}
+
+/// Error for a message which was not expected at this point of the protocol.
+pub fn unexpected(message: ProtocolMessage) -> Error {
+    Error::ProtocolViolation(format!("Unexpected message {}", message.name()))
+}
+
+/// Connection carrying `ProtocolMessage`, usable as a trait object by players.
+pub trait MessageConnection: Send {
+    fn send_message(&mut self, message: &ProtocolMessage) -> Result<()>;
+    fn receive_message(&mut self) -> Result<ProtocolMessage>;
+    /// Keeps the other player waiting, see `HeartbeatConnection`.
+    fn send_heartbeat(&mut self) -> Result<()> {
+        self.send_message(&ProtocolMessage::Heartbeat)
+    }
+    /// State to store in a checkpoint, `None` if the connection cannot be resumed.
+    fn checkpoint(&mut self) -> Option<SessionState> {
+        None
+    }
+}
+
+impl MessageConnection for TcpConnection {
+    fn send_message(&mut self, message: &ProtocolMessage) -> Result<()> {
+        self.send(message)
+    }
+    fn receive_message(&mut self) -> Result<ProtocolMessage> {
+        self.receive()
+    }
+}
+
+impl MessageConnection for ChannelConnection {
+    fn send_message(&mut self, message: &ProtocolMessage) -> Result<()> {
+        self.send(message)
+    }
+    fn receive_message(&mut self) -> Result<ProtocolMessage> {
+        self.receive()
+    }
+}
+
+impl MessageConnection for ResumableConnection {
+    fn send_message(&mut self, message: &ProtocolMessage) -> Result<()> {
+        self.send(message)
+    }
+    fn receive_message(&mut self) -> Result<ProtocolMessage> {
+        self.receive()
+    }
+    fn send_heartbeat(&mut self) -> Result<()> {
+        ResumableConnection::send_heartbeat(self)
+    }
+    fn checkpoint(&mut self) -> Option<SessionState> {
+        Some(ResumableConnection::checkpoint(self))
+    }
 }
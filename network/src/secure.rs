@@ -0,0 +1,386 @@
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::str::FromStr;
+
+use crypto::signature::{SigningKey, VerifyingKey};
+use rand::{CryptoRng, RngCore};
+use snow::params::NoiseParams;
+use snow::resolvers::{CryptoResolver, DefaultResolver};
+use snow::{Builder, HandshakeState, TransportState};
+
+use crate::error::{Error, Result};
+
+/*
+ * Secure channel is established with Noise XX handshake, after which both
+ * sides know the static key of the peer. Static keys are identities of the
+ * players, they are compared with keys pinned out of band by `ConStartup`.
+ * Every Noise message is sent as big-endian u16 length followed by the message,
+ * framed data is split into as many messages as needed.
+ * Identity of a player also holds a signing key, public keys of both are pinned together
+ * and written as one hex string. Key file holds the private keys as hex.
+ */
+
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_SHA256";
+const MAX_NOISE_MESSAGE: usize = 65535;
+const TAG_SIZE: usize = 16;
+
+fn params() -> NoiseParams {
+    NOISE_PARAMS.parse().unwrap()
+}
+
+const KEY_SIZE: usize = 32;
+
+// public static key of the handshake belonging to `private`
+fn noise_public(private: &[u8]) -> [u8; KEY_SIZE] {
+    let mut dh = DefaultResolver.resolve_dh(&params().dh).unwrap();
+    dh.set(private);
+    dh.pubkey().try_into().unwrap()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2).unwrap(), 16).ok())
+        .collect()
+}
+
+/// Public keys identifying a player, static key of the handshake and key verifying its signatures.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PublicKey {
+    pub noise: [u8; KEY_SIZE],
+    pub signing: VerifyingKey,
+}
+
+impl PublicKey {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.noise.to_vec();
+        bytes.extend_from_slice(&self.signing.to_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() <= KEY_SIZE {
+            return None;
+        }
+        let (noise, signing_bytes) = bytes.split_at(KEY_SIZE);
+        // trailing bytes are rejected, every key has one encoding
+        let signing = VerifyingKey::from_bytes(signing_bytes)?;
+        (signing.to_bytes() == signing_bytes).then_some(PublicKey {
+            noise: noise.try_into().unwrap(),
+            signing,
+        })
+    }
+}
+
+impl Display for PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        to_hex(&self.to_bytes()).fmt(f)
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        from_hex(s)
+            .and_then(|bytes| PublicKey::from_bytes(&bytes))
+            .ok_or(Error::protocol("Invalid public key"))
+    }
+}
+
+/// Key pairs of a player, public part has to be given to other players out of band.
+#[derive(Clone)]
+pub struct Identity {
+    private: Vec<u8>,
+    public: [u8; KEY_SIZE],
+    signer: SigningKey,
+}
+
+impl Identity {
+    /// Both key pairs are drawn from `rng`.
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut private = [0; KEY_SIZE];
+        rng.fill_bytes(&mut private);
+        Identity {
+            private: private.to_vec(),
+            public: noise_public(&private),
+            signer: SigningKey::generate(rng),
+        }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey {
+            noise: self.public,
+            signing: self.signer.verifying_key(),
+        }
+    }
+
+    /// Key signing messages of the player.
+    pub fn signer(&self) -> &SigningKey {
+        &self.signer
+    }
+
+    /// Private and public static key followed by the signing key, all of it is secret.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.private.clone();
+        bytes.extend_from_slice(&self.public);
+        bytes.extend_from_slice(&self.signer.to_bytes());
+        bytes
+    }
+
+    /// `None` if the public static key does not belong to the private one.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() <= 2 * KEY_SIZE {
+            return None;
+        }
+        let (private, rest) = bytes.split_at(KEY_SIZE);
+        let (public, signer_bytes) = rest.split_at(KEY_SIZE);
+        if noise_public(private) != public {
+            return None;
+        }
+        let signer = SigningKey::from_bytes(signer_bytes)?;
+        (signer.to_bytes() == signer_bytes).then_some(Identity {
+            private: private.to_vec(),
+            public: public.try_into().unwrap(),
+            signer,
+        })
+    }
+
+    /// Writes the key file, on unix it is readable only by its owner.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(path)?
+            .write_all(to_hex(&self.to_bytes()).as_bytes())?;
+        Ok(())
+    }
+
+    /// `None` if there is no key file at `path`.
+    pub fn load(path: &str) -> Result<Option<Self>> {
+        match std::fs::read_to_string(path) {
+            Ok(hex) => from_hex(hex.trim())
+                .and_then(|bytes| Identity::from_bytes(&bytes))
+                .map(Some)
+                .ok_or(Error::protocol("Invalid key file")),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+// Noise message with its length, as sent on the stream
+fn record(message: &[u8]) -> Vec<u8> {
+    let mut record = (message.len() as u16).to_be_bytes().to_vec();
+    record.extend_from_slice(message);
+    record
+}
+
+fn read_record(stream: &mut TcpStream, record: &mut Vec<u8>) -> Result<()> {
+    let mut length = [0; 2];
+    stream.read_exact(&mut length)?;
+    record.resize(u16::from_be_bytes(length) as usize, 0);
+    stream.read_exact(record)?;
+    Ok(())
+}
+
+/// Noise handshake in progress, records are sent and received by the connection.
+pub(crate) struct Handshake {
+    state: HandshakeState,
+    my_turn: bool,
+    message: Vec<u8>,
+}
+
+impl Handshake {
+    /// The side which opened the connection is the initiator.
+    pub(crate) fn new(identity: &Identity, initiator: bool) -> Result<Self> {
+        let builder = Builder::new(params()).local_private_key(&identity.private);
+        let state = if initiator {
+            builder.build_initiator()?
+        } else {
+            builder.build_responder()?
+        };
+        Ok(Handshake {
+            state,
+            my_turn: initiator,
+            message: vec![0; MAX_NOISE_MESSAGE],
+        })
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.state.is_handshake_finished()
+    }
+
+    /// Record to send next, `None` if a record of the peer comes next.
+    pub(crate) fn write(&mut self) -> Result<Option<Vec<u8>>> {
+        if !self.my_turn {
+            return Ok(None);
+        }
+        self.my_turn = false;
+        let length = self.state.write_message(&[], &mut self.message)?;
+        Ok(Some(record(self.message.get(..length).unwrap())))
+    }
+
+    /// Reads `record` of the peer, without its length.
+    pub(crate) fn read(&mut self, record: &[u8]) -> Result<()> {
+        self.state.read_message(record, &mut self.message)?;
+        self.my_turn = true;
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> Result<SecureChannel> {
+        let peer = self
+            .state
+            .get_remote_static()
+            .and_then(|key| key.try_into().ok())
+            .ok_or(Error::protocol("Peer did not authenticate"))?;
+        let handshake_hash = self.state.get_handshake_hash().to_vec();
+        Ok(SecureChannel {
+            transport: self.state.into_transport_mode()?,
+            peer,
+            handshake_hash,
+            plaintext: Vec::new(),
+            position: 0,
+        })
+    }
+}
+
+pub(crate) struct SecureChannel {
+    transport: TransportState,
+    peer: [u8; KEY_SIZE],
+    handshake_hash: Vec<u8>,
+    plaintext: Vec<u8>,
+    position: usize,
+}
+
+impl SecureChannel {
+    /// Handshake over `stream`, the side which opened the connection is the initiator.
+    pub(crate) fn handshake(
+        stream: &mut TcpStream,
+        identity: &Identity,
+        initiator: bool,
+    ) -> Result<Self> {
+        let mut handshake = Handshake::new(identity, initiator)?;
+        let mut record = Vec::new();
+        while !handshake.is_finished() {
+            match handshake.write()? {
+                Some(record) => stream.write_all(&record)?,
+                None => {
+                    read_record(stream, &mut record)?;
+                    handshake.read(&record)?;
+                }
+            }
+        }
+        handshake.finish()
+    }
+
+    pub(crate) fn peer(&self) -> [u8; KEY_SIZE] {
+        self.peer
+    }
+
+    /// Part of a received message is not read yet.
+    pub(crate) fn has_buffered(&self) -> bool {
+        self.position < self.plaintext.len()
+    }
+
+    /// Same on both sides and unique for the connection.
+    pub(crate) fn handshake_hash(&self) -> &[u8] {
+        &self.handshake_hash
+    }
+
+    /// Records carrying `data`, ready to be sent.
+    pub(crate) fn seal(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut message = vec![0; MAX_NOISE_MESSAGE];
+        let mut records = Vec::new();
+        for chunk in data.chunks(MAX_NOISE_MESSAGE - TAG_SIZE) {
+            let length = self.transport.write_message(chunk, &mut message)?;
+            records.extend_from_slice(&record(message.get(..length).unwrap()));
+        }
+        Ok(records)
+    }
+
+    /// Decrypts received `record`, only when nothing is buffered.
+    pub(crate) fn open(&mut self, record: &[u8]) -> Result<()> {
+        self.plaintext.resize(MAX_NOISE_MESSAGE, 0);
+        let length = self.transport.read_message(record, &mut self.plaintext)?;
+        self.plaintext.truncate(length);
+        self.position = 0;
+        Ok(())
+    }
+
+    /// Moves buffered data to the start of `buffer`, returns how much was moved.
+    pub(crate) fn take(&mut self, buffer: &mut [u8]) -> usize {
+        let available = self.plaintext.get(self.position..).unwrap();
+        let count = available.len().min(buffer.len());
+        buffer
+            .get_mut(..count)
+            .unwrap()
+            .copy_from_slice(available.get(..count).unwrap());
+        self.position += count;
+        count
+    }
+
+    pub(crate) fn write_all(&mut self, stream: &mut TcpStream, data: &[u8]) -> Result<()> {
+        let records = self.seal(data)?;
+        Ok(stream.write_all(&records)?)
+    }
+
+    pub(crate) fn read_exact(&mut self, stream: &mut TcpStream, buffer: &mut [u8]) -> Result<()> {
+        let mut record = Vec::new();
+        let mut filled = 0;
+        while filled < buffer.len() {
+            if !self.has_buffered() {
+                read_record(stream, &mut record)?;
+                self.open(&record)?;
+            }
+            filled += self.take(buffer.get_mut(filled..).unwrap());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, thread_rng, SeedableRng};
+
+    use super::{Identity, PublicKey};
+
+    #[test]
+    fn key_encoding() {
+        let identity = Identity::generate(&mut thread_rng());
+        let public = identity.public_key();
+        assert_eq!(public.to_string().parse::<PublicKey>().unwrap(), public);
+        assert!("not a key".parse::<PublicKey>().is_err());
+        let mut longer = public.to_bytes();
+        longer.push(0);
+        assert!(PublicKey::from_bytes(&longer).is_none());
+        let restored = Identity::from_bytes(&identity.to_bytes()).unwrap();
+        assert_eq!(restored.public_key(), public);
+        assert!(Identity::from_bytes(&identity.to_bytes()[..40]).is_none());
+        let mut edited = identity.to_bytes();
+        *edited.get_mut(40).unwrap() ^= 1;
+        assert!(Identity::from_bytes(&edited).is_none());
+    }
+
+    #[test]
+    fn seeded_identity() {
+        let first = Identity::generate(&mut StdRng::seed_from_u64(7));
+        let second = Identity::generate(&mut StdRng::seed_from_u64(7));
+        assert_eq!(first.to_bytes(), second.to_bytes());
+        assert_ne!(
+            Identity::generate(&mut StdRng::seed_from_u64(8)).public_key(),
+            first.public_key()
+        );
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::VecDeque;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::connection::{Connection, TcpConnection};
+use crate::error::{Error, Result};
+
+/*
+ * Session survives dropped connections and restarts of the peer.
+ * Every message gets a sequence number starting with 1 and is kept until the peer
+ * acknowledges it. Once the peer stores checkpoints, acknowledged is only what it stored
+ * in the last one, so a peer restarted from the checkpoint can still get everything after it.
+ * Before its first checkpoint the peer cannot be restarted and acknowledges every message.
+ * After a new connection is established, both sides send `Resume` with the last received
+ * and acknowledged numbers and retransmit all kept messages the other side has not received.
+ * Messages with already received numbers are dropped, so nothing is applied twice,
+ * even when a restarted peer repeats messages it sent before its checkpoint.
+ * Hash of every received message the peer may repeat is kept, a repeated message
+ * has to match it, so a restarted peer cannot change what it already said.
+ * Messages tell which of their sender's messages are in its checkpoint, the sender never
+ * repeats those and their hashes are dropped.
+ * Heartbeats are sent at any time, so they are not numbered and never repeated.
+ */
+
+#[derive(Serialize, Deserialize, Debug)]
+enum Frame {
+    Message {
+        seq: u64,
+        ack: u64,
+        stored: u64,
+        message: Value,
+    },
+    Resume {
+        received: u64,
+        acked: u64,
+    },
+    Heartbeat,
+}
+
+/// Part of the session which is stored in a checkpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SessionState {
+    /// Number of the last sent message.
+    pub sent: u64,
+    /// Number of the last received message.
+    pub received: u64,
+    /// Number of the last received message acknowledged to the peer.
+    pub acked: u64,
+    /// Number of the last sent message stored in a checkpoint,
+    /// messages after it are sent again by the restored player.
+    pub stored: u64,
+    /// Sent messages the peer has not acknowledged yet, with their numbers.
+    pub unacked: VecDeque<(u64, Value)>,
+    /// Hashes of received messages the peer may repeat, with their numbers.
+    pub hashes: VecDeque<(u64, [u8; 32])>,
+}
+
+fn message_hash(message: &Value) -> [u8; 32] {
+    Sha256::digest(serde_json::to_vec(message).unwrap()).into()
+}
+
+/// Opens a new connection to the same peer, e.g. with `ConStartup::reconnect`.
+pub type Reconnect = Box<dyn FnMut() -> Result<TcpConnection> + Send>;
+
+pub struct ResumableConnection {
+    connection: TcpConnection,
+    state: SessionState,
+    reconnect: Reconnect,
+    /// Reconnects attempted after a single failure before giving up.
+    max_attempts: usize,
+    /// Checkpoints are stored, only what they hold is acknowledged.
+    checkpointed: bool,
+}
+
+const MAX_ATTEMPTS: usize = 3;
+
+impl ResumableConnection {
+    pub fn new(connection: TcpConnection, reconnect: Reconnect) -> Self {
+        ResumableConnection {
+            connection,
+            state: SessionState::default(),
+            reconnect,
+            max_attempts: MAX_ATTEMPTS,
+            checkpointed: false,
+        }
+    }
+
+    /// Continues a session from a checkpoint over a new `connection`.
+    pub fn restore(
+        connection: TcpConnection,
+        state: SessionState,
+        reconnect: Reconnect,
+    ) -> Result<Self> {
+        let mut resumable = ResumableConnection {
+            connection,
+            state,
+            reconnect,
+            max_attempts: MAX_ATTEMPTS,
+            checkpointed: true,
+        };
+        resumable.resume()?;
+        Ok(resumable)
+    }
+
+    pub fn set_max_attempts(&mut self, max_attempts: usize) {
+        self.max_attempts = max_attempts;
+    }
+
+    /// Keeps the peer waiting, outside of the numbered messages.
+    pub fn send_heartbeat(&mut self) -> Result<()> {
+        self.connection.send(&Frame::Heartbeat)
+    }
+
+    /// Marks everything received so far as stored, returns the state to store.
+    pub fn checkpoint(&mut self) -> SessionState {
+        self.checkpointed = true;
+        self.state.acked = self.state.received;
+        self.state.stored = self.state.sent;
+        self.state.clone()
+    }
+
+    fn prune(&mut self, acked: u64) {
+        while matches!(self.state.unacked.front(), Some((seq, _)) if *seq <= acked) {
+            self.state.unacked.pop_front();
+        }
+    }
+
+    // the peer does not repeat messages up to `stored`
+    fn forget(&mut self, stored: u64) {
+        while matches!(self.state.hashes.front(), Some((seq, _)) if *seq <= stored) {
+            self.state.hashes.pop_front();
+        }
+    }
+
+    fn send_frame(&mut self, seq: u64, message: Value) -> Result<()> {
+        self.connection.send(&Frame::Message {
+            seq,
+            ack: self.state.acked,
+            stored: self.state.stored,
+            message,
+        })
+    }
+
+    // exchanges `Resume` over the current connection and retransmits what the peer is missing
+    fn resume(&mut self) -> Result<()> {
+        self.connection.send(&Frame::Resume {
+            received: self.state.received,
+            acked: self.state.acked,
+        })?;
+        let (received, acked) = loop {
+            match self.connection.receive()? {
+                Frame::Resume { received, acked } => break (received, acked),
+                Frame::Heartbeat => continue,
+                Frame::Message { .. } => return Err(Error::protocol("Expected resume")),
+            }
+        };
+        self.prune(acked);
+        let missing = self
+            .state
+            .unacked
+            .iter()
+            .filter(|(seq, _)| *seq > received)
+            .cloned()
+            .collect::<Vec<_>>();
+        for (seq, message) in missing {
+            self.send_frame(seq, message)?;
+        }
+        Ok(())
+    }
+
+    // only I/O errors mean a dropped connection, everything else is reported
+    fn recover(&mut self, err: Error) -> Result<()> {
+        if !matches!(err, Error::Io(_)) {
+            return Err(err);
+        }
+        let mut last = err;
+        for _ in 0..self.max_attempts {
+            match (self.reconnect)().and_then(|connection| {
+                self.connection = connection;
+                self.resume()
+            }) {
+                Ok(()) => return Ok(()),
+                Err(err) => last = err,
+            }
+        }
+        Err(last)
+    }
+}
+
+impl Connection for ResumableConnection {
+    fn send<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        let message = serde_json::to_value(message)?;
+        self.state.sent += 1;
+        let seq = self.state.sent;
+        if !self.checkpointed {
+            self.state.stored = seq;
+        }
+        self.state.unacked.push_back((seq, message.clone()));
+        match self.send_frame(seq, message) {
+            // the message is retransmitted during the resume
+            Err(err) => self.recover(err),
+            Ok(()) => Ok(()),
+        }
+    }
+
+    fn receive<T: DeserializeOwned + Serialize>(&mut self) -> Result<T> {
+        loop {
+            let frame = match self.connection.receive() {
+                Ok(frame) => frame,
+                Err(err) => {
+                    self.recover(err)?;
+                    continue;
+                }
+            };
+            let (seq, ack, stored, message) = match frame {
+                Frame::Message {
+                    seq,
+                    ack,
+                    stored,
+                    message,
+                } => (seq, ack, stored, message),
+                Frame::Resume { .. } => return Err(Error::protocol("Unexpected resume")),
+                Frame::Heartbeat => continue,
+            };
+            self.prune(ack);
+            self.forget(stored);
+            let hash = message_hash(&message);
+            if seq <= self.state.received {
+                match self
+                    .state
+                    .hashes
+                    .iter()
+                    .find(|(received, _)| *received == seq)
+                {
+                    Some((_, received)) if *received == hash => continue,
+                    _ => return Err(Error::protocol("Repeated message differs")),
+                }
+            }
+            if seq != self.state.received + 1 {
+                return Err(Error::protocol("Missing message"));
+            }
+            self.state.received = seq;
+            self.state.hashes.push_back((seq, hash));
+            if !self.checkpointed {
+                self.state.acked = seq;
+            }
+            return Ok(serde_json::from_value(message)?);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::{Reconnect, ResumableConnection};
+    use crate::con_startup::ConStartup;
+    use crate::connection::Connection;
+    use crate::error::Error;
+
+    fn reconnect(listener: Arc<TcpListener>, address: String, player_id: usize) -> Reconnect {
+        let startup = ConStartup::new(2, player_id).with_game(3);
+        Box::new(move || startup.reconnect(&listener, &address, 1 - player_id))
+    }
+
+    fn resumable_pair() -> (ResumableConnection, ResumableConnection) {
+        let listener = Arc::new(TcpListener::bind("127.0.0.1:0").unwrap());
+        let address = listener.local_addr().unwrap().to_string();
+        let mut second_reconnect = reconnect(listener.clone(), address.clone(), 1);
+        let handle = thread::spawn(move || {
+            let connection = second_reconnect().unwrap();
+            ResumableConnection::new(connection, second_reconnect)
+        });
+        let mut first_reconnect = reconnect(listener, address, 0);
+        let connection = first_reconnect().unwrap();
+        let first = ResumableConnection::new(connection, first_reconnect);
+        (first, handle.join().unwrap())
+    }
+
+    #[test]
+    fn resume_after_drop() {
+        let (mut first, mut second) = resumable_pair();
+        first.send(&1).unwrap();
+        first.send(&2).unwrap();
+        assert_eq!(second.receive::<u32>().unwrap(), 1);
+        first.connection.shutdown();
+        let handle = thread::spawn(move || {
+            first.send(&3).unwrap();
+            first
+        });
+        assert_eq!(second.receive::<u32>().unwrap(), 2);
+        assert_eq!(second.receive::<u32>().unwrap(), 3);
+        let mut first = handle.join().unwrap();
+        second.send(&4).unwrap();
+        assert_eq!(first.receive::<u32>().unwrap(), 4);
+    }
+
+    #[test]
+    fn heartbeats_are_not_numbered() {
+        let (mut first, mut second) = resumable_pair();
+        first.send_heartbeat().unwrap();
+        first.send(&1).unwrap();
+        first.send_heartbeat().unwrap();
+        first.send(&2).unwrap();
+        assert_eq!(second.receive::<u32>().unwrap(), 1);
+        assert_eq!(second.receive::<u32>().unwrap(), 2);
+        assert_eq!(first.checkpoint().sent, 2);
+        assert_eq!(second.checkpoint().received, 2);
+    }
+
+    #[test]
+    fn acks_without_checkpoints() {
+        let (mut first, mut second) = resumable_pair();
+        for i in 0..10 {
+            first.send(&i).unwrap();
+            assert_eq!(second.receive::<u32>().unwrap(), i);
+            second.send(&i).unwrap();
+            assert_eq!(first.receive::<u32>().unwrap(), i);
+        }
+        // only the last reply is not acknowledged yet, only the last hashes are kept
+        assert!(first.state.unacked.is_empty());
+        assert_eq!(second.state.unacked.len(), 1);
+        for connection in [&first, &second] {
+            assert_eq!(connection.state.hashes.len(), 1);
+        }
+    }
+
+    #[test]
+    fn restore_from_checkpoint() {
+        let (mut first, mut second) = resumable_pair();
+        first.send(&1).unwrap();
+        assert_eq!(second.receive::<u32>().unwrap(), 1);
+        let state = second.checkpoint();
+        first.send(&2).unwrap();
+        assert_eq!(second.receive::<u32>().unwrap(), 2);
+        second.send(&10).unwrap();
+        assert_eq!(first.receive::<u32>().unwrap(), 10);
+
+        // second restarts from the checkpoint and repeats what it did after it
+        let ResumableConnection {
+            connection,
+            mut reconnect,
+            ..
+        } = second;
+        drop(connection);
+        let handle = thread::spawn(move || {
+            let connection = reconnect().unwrap();
+            let mut second = ResumableConnection::restore(connection, state, reconnect).unwrap();
+            assert_eq!(second.receive::<u32>().unwrap(), 2);
+            second.send(&10).unwrap();
+            second.send(&11).unwrap();
+        });
+        assert_eq!(first.receive::<u32>().unwrap(), 11);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn changed_repeated_message() {
+        let (mut first, mut second) = resumable_pair();
+        first.send(&1).unwrap();
+        assert_eq!(second.receive::<u32>().unwrap(), 1);
+        let state = second.checkpoint();
+        second.send(&10).unwrap();
+        assert_eq!(first.receive::<u32>().unwrap(), 10);
+
+        // second restarts from the checkpoint and sends something else this time
+        let ResumableConnection {
+            connection,
+            mut reconnect,
+            ..
+        } = second;
+        drop(connection);
+        let handle = thread::spawn(move || {
+            let connection = reconnect().unwrap();
+            let mut second = ResumableConnection::restore(connection, state, reconnect).unwrap();
+            second.send(&12).unwrap();
+            second
+        });
+        assert!(matches!(
+            first.receive::<u32>(),
+            Err(Error::ProtocolViolation(_))
+        ));
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,172 @@
+use std::future::{ready, Future};
+
+use common::cards::Card;
+use network::{
+    error::{Error, Result},
+    message::Evidence,
+};
+
+use crate::async_player::AsyncPlayer;
+use crate::moves::{self, Moves};
+
+/// Same as `Moves`, the protocol is shared, so async and blocking players can play together.
+/// Blocking players make their moves right away, so games can drive both.
+pub trait AsyncMoves {
+    fn draw_from_deck(&mut self) -> impl Future<Output = Result<Card>> + Send;
+    fn let_draw_from_deck(&mut self, other: usize) -> impl Future<Output = Result<()>> + Send;
+    fn play_card(&mut self, ind: usize) -> impl Future<Output = Result<()>> + Send;
+    fn let_play_card(&mut self, other: usize) -> impl Future<Output = Result<Card>> + Send;
+    fn reveal_from_deck(&mut self) -> impl Future<Output = Result<Card>> + Send;
+    /// Tells other players why the game failed with `err`.
+    fn abort(&mut self, err: &Error) -> impl Future<Output = ()> + Send;
+    /// Whether `evidence` shows that player `cheater` cheated.
+    fn check_evidence(&self, cheater: usize, evidence: &Evidence) -> bool;
+}
+
+impl AsyncMoves for AsyncPlayer {
+    fn draw_from_deck(&mut self) -> impl Future<Output = Result<Card>> + Send {
+        moves::draw_from_deck(self)
+    }
+    fn let_draw_from_deck(&mut self, other: usize) -> impl Future<Output = Result<()>> + Send {
+        moves::let_draw_from_deck(self, other)
+    }
+    fn play_card(&mut self, ind: usize) -> impl Future<Output = Result<()>> + Send {
+        moves::play_card(self, ind)
+    }
+    fn let_play_card(&mut self, other: usize) -> impl Future<Output = Result<Card>> + Send {
+        moves::let_play_card(self, other)
+    }
+    fn reveal_from_deck(&mut self) -> impl Future<Output = Result<Card>> + Send {
+        moves::reveal_from_deck(self)
+    }
+    fn abort(&mut self, err: &Error) -> impl Future<Output = ()> + Send {
+        moves::abort(self, err)
+    }
+    fn check_evidence(&self, cheater: usize, evidence: &Evidence) -> bool {
+        moves::check_evidence(self, cheater, evidence)
+    }
+}
+
+impl<T: Moves> AsyncMoves for T {
+    fn draw_from_deck(&mut self) -> impl Future<Output = Result<Card>> + Send {
+        ready(Moves::draw_from_deck(self))
+    }
+    fn let_draw_from_deck(&mut self, other: usize) -> impl Future<Output = Result<()>> + Send {
+        ready(Moves::let_draw_from_deck(self, other))
+    }
+    fn play_card(&mut self, ind: usize) -> impl Future<Output = Result<()>> + Send {
+        ready(Moves::play_card(self, ind))
+    }
+    fn let_play_card(&mut self, other: usize) -> impl Future<Output = Result<Card>> + Send {
+        ready(Moves::let_play_card(self, other))
+    }
+    fn reveal_from_deck(&mut self) -> impl Future<Output = Result<Card>> + Send {
+        ready(Moves::reveal_from_deck(self))
+    }
+    fn abort(&mut self, err: &Error) -> impl Future<Output = ()> + Send {
+        Moves::abort(self, err);
+        ready(())
+    }
+    fn check_evidence(&self, cheater: usize, evidence: &Evidence) -> bool {
+        Moves::check_evidence(self, cheater, evidence)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::thread;
+
+    use crypto::deck::DeckSpec;
+    use network::async_connection::AsyncTcpConnection;
+    use network::con_startup::ConStartup;
+
+    use super::AsyncMoves;
+    use crate::async_player::{
+        AsyncDeckPreparation, AsyncDeckPreparationBasic, AsyncDeckPreparationVerification,
+        AsyncOtherPlayer, AsyncPlayer,
+    };
+    use crate::moves::Moves;
+    use crate::player::{
+        game_rng, test::signer, DeckPreparation, DeckPreparationBasic, OtherPlayer,
+    };
+
+    /// Connections of two players, by their ids, on a port picked by the system.
+    pub(crate) async fn async_connections() -> [AsyncTcpConnection; 2] {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (server, client) = (ConStartup::new(2, 0), ConStartup::new(2, 1));
+        let (first, second) = tokio::join!(
+            server.initialize_async_on(&listener),
+            client.initialize_async(&address)
+        );
+        [first.unwrap(), second.unwrap()]
+    }
+
+    pub(crate) async fn prepare_async_player<P: AsyncDeckPreparation>(
+        connection: AsyncTcpConnection,
+        player_id: usize,
+    ) -> AsyncPlayer {
+        let other =
+            AsyncOtherPlayer::new(connection).with_peer_key(signer(1 - player_id).verifying_key());
+        P::prepare(
+            format!("P{}", player_id),
+            signer(player_id),
+            vec![other],
+            player_id,
+            DeckSpec::short(),
+            &mut game_rng(None),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn async_draw_and_play() {
+        let [first, second] = async_connections().await;
+        let (mut first, mut second) = tokio::join!(
+            prepare_async_player::<AsyncDeckPreparationVerification>(first, 0),
+            prepare_async_player::<AsyncDeckPreparationVerification>(second, 1)
+        );
+        assert_eq!(first.deck, second.deck);
+        let (drawn, let_draw) = tokio::join!(first.draw_from_deck(), second.let_draw_from_deck(0));
+        let_draw.unwrap();
+        let (played, seen) = tokio::join!(first.play_card(0), second.let_play_card(0));
+        played.unwrap();
+        assert_eq!(drawn.unwrap(), seen.unwrap());
+        let (revealed, also_revealed) =
+            tokio::join!(first.reveal_from_deck(), second.reveal_from_deck());
+        assert_eq!(revealed.unwrap(), also_revealed.unwrap());
+    }
+
+    #[test]
+    fn async_against_blocking() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let connection = ConStartup::new(2, 0).initialize_on(&listener).unwrap();
+            let mut player = DeckPreparationBasic::<OtherPlayer>::prepare(
+                "P0".to_string(),
+                signer(0),
+                vec![OtherPlayer::new(connection)],
+                0,
+                DeckSpec::short(),
+                &mut game_rng(None),
+            )
+            .unwrap();
+            let card = Moves::draw_from_deck(&mut player).unwrap();
+            Moves::play_card(&mut player, 0).unwrap();
+            card
+        });
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let seen = runtime.block_on(async {
+            let connection = ConStartup::new(2, 1)
+                .initialize_async(&address)
+                .await
+                .unwrap();
+            let mut player = prepare_async_player::<AsyncDeckPreparationBasic>(connection, 1).await;
+            player.let_draw_from_deck(0).await.unwrap();
+            player.let_play_card(0).await.unwrap()
+        });
+        assert_eq!(handle.join().unwrap(), seen);
+    }
+}
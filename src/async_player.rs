@@ -0,0 +1,129 @@
+use std::future::Future;
+
+use crypto::deck::DeckSpec;
+use crypto::shuffle_v2::ShuffleWithProof;
+use crypto::signature::{SigningKey, VerifyingKey};
+use network::async_connection::{AsyncConnection, AsyncTcpConnection};
+use network::error::Error;
+use network::error::Result;
+use network::message::ProtocolMessage;
+use network::message::ShuffleMessage;
+use rand::CryptoRng;
+use rand::RngCore;
+
+use crate::player::{DeckPreparationBasic, DeckPreparationVerification, Peer, Player};
+use crate::transcript::{Entry, Recorder};
+
+/*
+ * Async players run the same protocol as the blocking ones and can play against them,
+ * only waiting for the other players does not block a thread.
+ * Preparations and moves are shared with blocking players, see `Peer`.
+ */
+
+pub struct AsyncOtherPlayer {
+    connection: AsyncTcpConnection,
+    /// Id of the other player and transcript of the player, once recording started.
+    recording: Option<(usize, Recorder)>,
+    /// Long-term key of the other player, aborts signed by it may accuse somebody.
+    peer_key: Option<VerifyingKey>,
+}
+
+impl AsyncOtherPlayer {
+    pub fn new(connection: AsyncTcpConnection) -> Self {
+        AsyncOtherPlayer {
+            connection,
+            recording: None,
+            peer_key: None,
+        }
+    }
+
+    /// Pins the long-term key of the other player.
+    pub fn with_peer_key(mut self, key: VerifyingKey) -> Self {
+        self.peer_key = Some(key);
+        self
+    }
+}
+
+impl Peer for AsyncOtherPlayer {
+    async fn send(&mut self, message: &ProtocolMessage) -> Result<()> {
+        self.connection.send(message).await?;
+        if let Some((to, recorder)) = &self.recording {
+            recorder.record(Entry::Sent {
+                to: *to,
+                message: message.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<ProtocolMessage> {
+        let message = loop {
+            match self.connection.receive().await? {
+                ProtocolMessage::Heartbeat => continue,
+                message => break message,
+            }
+        };
+        if let Some((from, recorder)) = &self.recording {
+            recorder.record(Entry::Received {
+                from: *from,
+                message: message.clone(),
+            });
+        }
+        match message {
+            ProtocolMessage::Abort(abort) => Err(match &self.recording {
+                Some((from, _)) => abort.into_error(*from, self.peer_key.as_ref()),
+                None => Error::protocol("Abort before the game started"),
+            }),
+            message => Ok(message),
+        }
+    }
+
+    fn record(&mut self, other_id: usize, recorder: Recorder) {
+        self.recording = Some((other_id, recorder));
+    }
+}
+
+pub type AsyncPlayer = Player<AsyncOtherPlayer>;
+
+/// Same as `DeckPreparation`, with async connections to other players.
+pub trait AsyncDeckPreparation {
+    fn prepare<R: RngCore + CryptoRng + Send>(
+        name: String,
+        signer: SigningKey,
+        others: Vec<AsyncOtherPlayer>,
+        player_id: usize,
+        deck: DeckSpec,
+        rng: &mut R,
+    ) -> impl Future<Output = Result<AsyncPlayer>> + Send;
+}
+
+pub type AsyncDeckPreparationBasic = DeckPreparationBasic<AsyncOtherPlayer>;
+
+impl AsyncDeckPreparation for AsyncDeckPreparationBasic {
+    fn prepare<R: RngCore + CryptoRng + Send>(
+        name: String,
+        signer: SigningKey,
+        others: Vec<AsyncOtherPlayer>,
+        player_id: usize,
+        deck: DeckSpec,
+        rng: &mut R,
+    ) -> impl Future<Output = Result<AsyncPlayer>> + Send {
+        Self::prepare_player(name, signer, others, player_id, deck, rng)
+    }
+}
+
+pub type AsyncDeckPreparationVerification<S = ShuffleWithProof> =
+    DeckPreparationVerification<S, AsyncOtherPlayer>;
+
+impl<S: ShuffleMessage + Send + Sync> AsyncDeckPreparation for AsyncDeckPreparationVerification<S> {
+    fn prepare<R: RngCore + CryptoRng + Send>(
+        name: String,
+        signer: SigningKey,
+        others: Vec<AsyncOtherPlayer>,
+        player_id: usize,
+        deck: DeckSpec,
+        rng: &mut R,
+    ) -> impl Future<Output = Result<AsyncPlayer>> + Send {
+        Self::prepare_player(name, signer, others, player_id, deck, rng)
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::{BTreeSet, HashMap};
+use std::iter::zip;
+
+use common::cards::Card;
+use crypto::{encryption::decrypt, shuffle_v2::key_commitment, types::KeyType};
+use network::{
+    error::{Error, Result},
+    message::{unexpected, ProtocolMessage},
+};
+
+use crate::player::{Owner, Player};
+
+/*
+ * At the end of the game everybody reveals all of their per-card keys.
+ * Deck decrypted with keys of all players has to be a permutation of the starting deck,
+ * it has to agree with every card revealed during the game and each revealed key
+ * has to match the commitment made during the preparation.
+ * Zero key cannot decrypt anything, revealing one is cheating which stops the audit.
+ */
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    /// Ids of players whose keys do not fit.
+    pub cheaters: Vec<usize>,
+    /// Positions in the deck which do not decrypt to the expected card.
+    pub invalid_cards: Vec<usize>,
+}
+
+impl AuditReport {
+    pub fn is_valid(&self) -> bool {
+        self.cheaters.is_empty() && self.invalid_cards.is_empty()
+    }
+}
+
+pub trait Audit {
+    fn audit(&mut self) -> Result<AuditReport>;
+}
+
+fn revealed_card(owner: &Option<Owner>) -> Option<Card> {
+    match owner {
+        Some(Owner::Me(card))
+        | Some(Owner::Player(card))
+        | Some(Owner::OtherPlayed(_, card))
+        | Some(Owner::Table(card)) => Some(card.card),
+        _ => None,
+    }
+}
+
+fn audit_keys(player: &Player, others_keys: &[Vec<KeyType>]) -> Result<AuditReport> {
+    let n = player.deck.len();
+    let mut cheaters = BTreeSet::new();
+    for (other, keys) in others_keys.iter().enumerate() {
+        if keys.contains(&KeyType::from(0)) {
+            return Err(Error::cheating(
+                player.other_player_id(other),
+                "Revealed zero key",
+            ));
+        }
+        let commitments = player.commitments.get(other).unwrap();
+        if keys.len() != n || zip(keys, commitments).any(|(k, c)| key_commitment(k) != *c) {
+            cheaters.insert(player.other_player_id(other));
+        }
+    }
+    if others_keys.iter().any(|keys| keys.len() != n) {
+        return Ok(AuditReport {
+            cheaters: cheaters.into_iter().collect(),
+            invalid_cards: (0..n).collect(),
+        });
+    }
+
+    let mut remaining = HashMap::<usize, usize>::new();
+    for card in player.start_deck.iter() {
+        if let Some(card) = player.translator.translate(*card) {
+            *remaining.entry(card).or_default() += 1;
+        }
+    }
+    let mut invalid_cards = vec![];
+    for ind in 0..n {
+        let mut value = decrypt(player.deck.get(ind).unwrap(), player.keys.get(ind).unwrap());
+        for keys in others_keys {
+            value = decrypt(&value, keys.get(ind).unwrap());
+        }
+        let card = player.translator.translate(value);
+        let valid = match card.and_then(|card| remaining.get_mut(&card)) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        };
+        let matches_revealed = match revealed_card(player.owners.get(ind).unwrap()) {
+            Some(revealed) => card.is_some_and(|card| Card::try_from(card).unwrap() == revealed),
+            None => true,
+        };
+        if !valid || !matches_revealed {
+            invalid_cards.push(ind);
+        }
+    }
+    // with more players invalid cards cannot be attributed without mismatched keys
+    if !invalid_cards.is_empty() && others_keys.len() == 1 {
+        cheaters.insert(player.other_player_id(0));
+    }
+    Ok(AuditReport {
+        cheaters: cheaters.into_iter().collect(),
+        invalid_cards,
+    })
+}
+
+impl Audit for Player {
+    fn audit(&mut self) -> Result<AuditReport> {
+        for o in self.players.iter_mut() {
+            o.send(&ProtocolMessage::RevealKeys(self.keys.clone()))?;
+        }
+        let others_keys = self
+            .players
+            .iter_mut()
+            .map(|o| match o.receive()? {
+                ProtocolMessage::RevealKeys(keys) => Ok(keys),
+                message => Err(unexpected(message)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        audit_keys(self, &others_keys)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use crypto::{deck::DeckSpec, types::KeyType};
+    use network::{
+        error::{Error, Result},
+        message::ProtocolMessage,
+    };
+
+    use super::{Audit, AuditReport};
+    use crate::{
+        moves::Moves,
+        player::{test::prepare_players, DeckPreparationBasic, Player},
+    };
+
+    fn prepare_and_play() -> (Player, Player) {
+        let mut players = prepare_players::<DeckPreparationBasic>(2, DeckSpec::short());
+        let mut second = players.pop().unwrap();
+        let mut first = players.pop().unwrap();
+        let handle = thread::spawn(move || {
+            second.let_draw_from_deck(0).unwrap();
+            second.draw_from_deck().unwrap();
+            second.let_play_card(0).unwrap();
+            second
+        });
+        first.draw_from_deck().unwrap();
+        first.let_draw_from_deck(0).unwrap();
+        first.play_card(0).unwrap();
+        (first, handle.join().unwrap())
+    }
+
+    #[test]
+    fn honest_audit() {
+        let (mut first, mut second) = prepare_and_play();
+        let handle = thread::spawn(move || second.audit().unwrap());
+        assert_eq!(first.audit().unwrap(), AuditReport::default());
+        assert!(handle.join().unwrap().is_valid());
+    }
+
+    fn audit_with_keys(
+        mut first: Player,
+        mut second: Player,
+        keys: Vec<KeyType>,
+    ) -> Result<AuditReport> {
+        let handle = thread::spawn(move || {
+            let other = second.players.first_mut().unwrap();
+            other.send(&ProtocolMessage::RevealKeys(keys)).unwrap();
+            other.receive().unwrap();
+        });
+        let report = first.audit();
+        handle.join().unwrap();
+        report
+    }
+
+    #[test]
+    fn changed_revealed_key() {
+        let (first, second) = prepare_and_play();
+        let mut keys = second.keys.clone();
+        *keys.first_mut().unwrap() = KeyType::from(7);
+        let report = audit_with_keys(first, second, keys).unwrap();
+        assert_eq!(report.cheaters, vec![1]);
+        assert_eq!(report.invalid_cards, vec![0]);
+    }
+
+    #[test]
+    fn duplicated_card() {
+        let (mut first, second) = prepare_and_play();
+        let mut keys = second.keys.clone();
+        // deck after preparation contains the same card on two last positions
+        let last = first.deck.len() - 1;
+        *first.deck.get_mut(last).unwrap() = *first.deck.get(last - 1).unwrap();
+        *first.keys.get_mut(last).unwrap() = *first.keys.get(last - 1).unwrap();
+        *keys.get_mut(last).unwrap() = *keys.get(last - 1).unwrap();
+        let report = audit_with_keys(first, second, keys).unwrap();
+        assert_eq!(report.cheaters, vec![1]);
+        assert_eq!(report.invalid_cards, vec![last]);
+    }
+
+    #[test]
+    fn zero_revealed_key() {
+        let (first, second) = prepare_and_play();
+        let mut keys = second.keys.clone();
+        *keys.last_mut().unwrap() = KeyType::from(0);
+        assert!(matches!(
+            audit_with_keys(first, second, keys),
+            Err(Error::Cheating { player: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,40 @@
+use clap::Parser;
+use network::error::Result;
+use network::secure::PublicKey;
+use secure_card_game::simple_game::SimpleRules;
+use secure_card_game::transcript::{verify_transcript, SignedTranscript};
+
+/// Checks a signed transcript of a simple game without any network
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Transcript written with --transcript
+    path: String,
+
+    /// Public key of the player who wrote the transcript, as pinned with --peer
+    #[clap(long)]
+    signer: String,
+}
+
+fn run(cli: Cli) -> Result<Vec<usize>> {
+    let signed: SignedTranscript = serde_json::from_slice(&std::fs::read(&cli.path)?)?;
+    let signer: PublicKey = cli.signer.parse()?;
+    verify_transcript(&signed, &signer.signing, SimpleRules {})
+}
+
+fn main() {
+    match run(Cli::parse()) {
+        Ok(scores) => {
+            let best = scores.iter().max().unwrap();
+            let winners = (0..scores.len())
+                .filter(|&id| scores.get(id).unwrap() == best)
+                .collect::<Vec<_>>();
+            println!("Transcript is valid");
+            println!("Scores: {:?}", scores);
+            println!("Winners: {:?}", winners);
+        }
+        Err(err) => {
+            eprintln!("Transcript is invalid: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use std::cmp::Reverse;
+use std::sync::{Arc, Mutex};
+
+use common::{
+    cards::Card,
+    game::{
+        ActionSelector, BetAction, BettingState, CardFromDeck, CardSelector, GameOutcome,
+        GamePrinter, GameState,
+    },
+    hands::{beats, RankOrder},
+};
+use crypto::deck::DeckSpec;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::player::GameRng;
+use crate::simple_game::SimpleRules;
+use crate::trick_game::GameRules;
+
+/*
+ * Computer opponents for `SimpleGame`. The selector only gets the legal cards,
+ * so the bot also watches the game through its printer. Completed tricks
+ * tell which cards may be still held by other players or hidden in the deck.
+ * In `Holdem` only the random bot bets on its own, other strategies check or call.
+ */
+
+/// How the bot picks a card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    Random,
+    /// Lowest card beating the trick so far when responding, otherwise the lowest card.
+    Greedy,
+    /// Card winning the trick most often in `simulations` random deals of unseen cards.
+    MonteCarlo {
+        simulations: usize,
+    },
+}
+
+const SIMULATIONS: usize = 200;
+const RULES: SimpleRules = SimpleRules {};
+
+impl Strategy {
+    /// Strategy by its name: random, greedy or monte-carlo.
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "random" => Some(Strategy::Random),
+            "greedy" => Some(Strategy::Greedy),
+            "monte-carlo" => Some(Strategy::MonteCarlo {
+                simulations: SIMULATIONS,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct View {
+    /// Cards of the trick in progress, starting with the leader.
+    trick: Vec<Card>,
+    /// Cards of all completed tricks.
+    played: Vec<Card>,
+    hand: Vec<Card>,
+}
+
+impl View {
+    // every trick is printed from the empty table, shorter table starts a new trick
+    fn update(&mut self, game_state: &GameState) {
+        if game_state.table_cards.len() < self.trick.len() {
+            self.played.append(&mut self.trick);
+        }
+        self.trick = game_state.table_cards.clone();
+        self.hand = game_state.hand.clone();
+    }
+}
+
+fn strength(card: Card) -> (u8, Card) {
+    (RankOrder::AceHigh.strength(card.rank), card)
+}
+
+// position of the lowest card among `positions` of `cards`
+fn lowest(cards: &[Card], positions: impl Iterator<Item = usize>) -> Option<usize> {
+    positions.min_by_key(|&position| strength(*cards.get(position).unwrap()))
+}
+
+// position in `hand` of the card the greedy strategy plays to `trick`
+fn greedy(hand: &[Card], trick: &[Card]) -> usize {
+    let Some(&best) = trick.get(RULES.trick_winner(trick)) else {
+        return lowest(hand, 0..hand.len()).unwrap();
+    };
+    let winning = (0..hand.len())
+        .filter(|&position| beats(*hand.get(position).unwrap(), best, None, RankOrder::AceHigh));
+    lowest(hand, winning)
+        .or_else(|| lowest(hand, 0..hand.len()))
+        .unwrap()
+}
+
+pub struct BotSelector {
+    strategy: Strategy,
+    num_players: usize,
+    deck: Vec<Card>,
+    view: Arc<Mutex<View>>,
+    rng: GameRng,
+}
+
+impl BotSelector {
+    pub fn new(strategy: Strategy, num_players: usize, deck: &DeckSpec, rng: GameRng) -> Self {
+        BotSelector {
+            strategy,
+            num_players,
+            deck: deck
+                .cards()
+                .iter()
+                .map(|&code| Card::try_from(code).unwrap())
+                .collect(),
+            view: Arc::new(Mutex::new(View::default())),
+            rng,
+        }
+    }
+
+    /// Printer showing the game with `inner`, the bot sees the game through it.
+    pub fn printer<P: GamePrinter>(&self, inner: P) -> BotPrinter<P> {
+        BotPrinter {
+            inner,
+            view: self.view.clone(),
+        }
+    }
+
+    // cards held by other players or hidden in the deck, copies are removed one by one
+    fn unseen(&self, view: &View) -> Vec<Card> {
+        let mut unseen = self.deck.clone();
+        for card in view.played.iter().chain(&view.trick).chain(&view.hand) {
+            if let Some(position) = unseen.iter().position(|c| c == card) {
+                unseen.swap_remove(position);
+            }
+        }
+        unseen
+    }
+
+    /*
+     * Players after the bot get random hands of the bot's size from unseen cards
+     * and respond greedily. Every candidate is tried with the same deals,
+     * ties go to the lower card, so high cards are kept for later tricks.
+     */
+    fn monte_carlo(&mut self, legal: &[Card], simulations: usize) -> usize {
+        let (trick, hand_size, mut unseen) = {
+            let view = self.view.lock().unwrap();
+            (view.trick.clone(), view.hand.len(), self.unseen(&view))
+        };
+        let position = trick.len();
+        let responders = self.num_players - position - 1;
+        let mut wins = vec![0; legal.len()];
+        for _ in 0..simulations {
+            unseen.shuffle(&mut self.rng);
+            let hands = unseen
+                .chunks(hand_size.max(1))
+                .take(responders)
+                .collect::<Vec<_>>();
+            for (candidate, &card) in legal.iter().enumerate() {
+                let mut simulated = trick.clone();
+                simulated.push(card);
+                for hand in hands.iter() {
+                    simulated.push(*hand.get(greedy(hand, &simulated)).unwrap());
+                }
+                if RULES.trick_winner(&simulated) == position {
+                    *wins.get_mut(candidate).unwrap() += 1;
+                }
+            }
+        }
+        (0..legal.len())
+            .min_by_key(|&candidate| {
+                let card = *legal.get(candidate).unwrap();
+                (Reverse(*wins.get(candidate).unwrap()), strength(card))
+            })
+            .unwrap()
+    }
+}
+
+impl CardSelector for BotSelector {
+    fn select_card(&mut self, hand: &[CardFromDeck]) -> CardFromDeck {
+        let legal = hand.iter().map(|c| c.card).collect::<Vec<_>>();
+        let position = match self.strategy {
+            Strategy::Random => self.rng.gen_range(0..legal.len()),
+            Strategy::Greedy => greedy(&legal, &self.view.lock().unwrap().trick),
+            Strategy::MonteCarlo { simulations } => self.monte_carlo(&legal, simulations),
+        };
+        *hand.get(position).unwrap()
+    }
+}
+
+impl ActionSelector for BotSelector {
+    fn select_action(&mut self, state: &BettingState) -> BetAction {
+        let passive = if state.to_call == 0 {
+            BetAction::Check
+        } else {
+            BetAction::Call
+        };
+        match self.strategy {
+            Strategy::Random => match self.rng.gen_range(0..3) {
+                0 if state.to_call > 0 => BetAction::Fold,
+                1 if state.max_raise > 0 => {
+                    BetAction::Raise(self.rng.gen_range(1..=state.max_raise))
+                }
+                _ => passive,
+            },
+            Strategy::Greedy | Strategy::MonteCarlo { .. } => passive,
+        }
+    }
+}
+
+pub struct BotPrinter<P> {
+    inner: P,
+    view: Arc<Mutex<View>>,
+}
+
+impl<P: GamePrinter> GamePrinter for BotPrinter<P> {
+    fn print_game(&mut self, game_state: &GameState) {
+        self.view.lock().unwrap().update(game_state);
+        self.inner.print_game(game_state);
+    }
+    fn print_outcome(&mut self, outcome: &GameOutcome) {
+        self.inner.print_outcome(outcome);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use common::{
+        cards::{Card, Rank, Suit},
+        game::{
+            ActionSelector, BetAction, BettingState, CardFromDeck, CardSelector, GamePrinter,
+            GameState,
+        },
+    };
+    use crypto::deck::DeckSpec;
+
+    use super::{greedy, BotSelector, Strategy};
+    use crate::player::{game_rng, test::prepare_players, DeckPreparationBasic};
+    use crate::simple_game::{SimpleGame, SimpleRules};
+
+    struct MockPrinter {}
+
+    impl GamePrinter for MockPrinter {
+        fn print_game(&mut self, _game_state: &GameState) {}
+    }
+
+    fn card(rank: Rank, suit: Suit) -> Card {
+        Card { rank, suit }
+    }
+
+    fn from_deck(cards: &[Card]) -> Vec<CardFromDeck> {
+        cards
+            .iter()
+            .enumerate()
+            .map(|(ind, &card)| CardFromDeck { card, ind })
+            .collect()
+    }
+
+    #[test]
+    fn greedy_responses() {
+        let hand = [
+            card(Rank::Nine, Suit::Spades),
+            card(Rank::Two, Suit::Spades),
+            card(Rank::King, Suit::Hearts),
+        ];
+        assert_eq!(greedy(&hand, &[]), 1);
+        assert_eq!(greedy(&hand, &[card(Rank::Five, Suit::Spades)]), 0);
+        assert_eq!(greedy(&hand, &[card(Rank::Ace, Suit::Spades)]), 1);
+        assert_eq!(greedy(&hand, &[card(Rank::Three, Suit::Hearts)]), 2);
+    }
+
+    #[test]
+    fn monte_carlo_takes_sure_trick() {
+        let mut selector = BotSelector::new(
+            Strategy::MonteCarlo { simulations: 50 },
+            2,
+            &DeckSpec::standard(),
+            game_rng(Some(1)),
+        );
+        let hand = from_deck(&[
+            card(Rank::Three, Suit::Hearts),
+            card(Rank::Ace, Suit::Clubs),
+        ]);
+        let mut printer = selector.printer(MockPrinter {});
+        printer.print_game(&GameState {
+            hand: hand.iter().map(|c| c.card).collect(),
+            table_cards: vec![card(Rank::King, Suit::Clubs)],
+            deck_cards: 0,
+        });
+        assert_eq!(selector.select_card(&hand).card.rank, Rank::Ace);
+        // nothing wins, the lowest card is thrown away
+        printer.print_game(&GameState {
+            hand: hand.iter().map(|c| c.card).collect(),
+            table_cards: vec![card(Rank::Ten, Suit::Spades)],
+            deck_cards: 0,
+        });
+        assert_eq!(selector.select_card(&hand).card.rank, Rank::Three);
+    }
+
+    #[test]
+    fn unseen_cards() {
+        let deck = DeckSpec::short();
+        let selector = BotSelector::new(Strategy::Greedy, 2, &deck, game_rng(Some(1)));
+        let mut printer = selector.printer(MockPrinter {});
+        let cards = selector.deck.clone();
+        let hand = cards[..2].to_vec();
+        for table in [vec![], cards[2..3].to_vec(), cards[2..4].to_vec(), vec![]] {
+            printer.print_game(&GameState {
+                hand: hand.clone(),
+                table_cards: table,
+                deck_cards: 0,
+            });
+        }
+        let unseen = selector.unseen(&selector.view.lock().unwrap());
+        assert_eq!(unseen.len(), deck.len() - 4);
+        assert!(cards[..4].iter().all(|card| !unseen.contains(card)));
+    }
+
+    #[test]
+    fn random_bets_are_legal() {
+        let mut selector =
+            BotSelector::new(Strategy::Random, 2, &DeckSpec::short(), game_rng(Some(1)));
+        let mut state = BettingState {
+            hole_cards: vec![],
+            community_cards: vec![],
+            pot: 3,
+            to_call: 0,
+            chips: 98,
+            max_raise: 0,
+        };
+        for _ in 0..20 {
+            assert_eq!(selector.select_action(&state), BetAction::Check);
+        }
+        state.to_call = 1;
+        state.max_raise = 5;
+        for _ in 0..20 {
+            match selector.select_action(&state) {
+                BetAction::Raise(amount) => assert!((1..=5).contains(&amount)),
+                action => assert!(matches!(action, BetAction::Fold | BetAction::Call)),
+            }
+        }
+    }
+
+    #[test]
+    fn bots_play_full_game() {
+        let deck = DeckSpec::short();
+        let strategies = [Strategy::Random, Strategy::MonteCarlo { simulations: 20 }];
+        let handles = prepare_players::<DeckPreparationBasic>(2, deck.clone())
+            .into_iter()
+            .zip(strategies)
+            .enumerate()
+            .map(|(player_id, (player, strategy))| {
+                let selector =
+                    BotSelector::new(strategy, 2, &deck, game_rng(Some(player_id as u64)));
+                thread::spawn(move || {
+                    let mut game = SimpleGame::new(
+                        SimpleRules {},
+                        player_id,
+                        2,
+                        player,
+                        selector.printer(MockPrinter {}),
+                        selector,
+                    );
+                    game.play().unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let (score, scores) = handle.join().unwrap();
+            assert_eq!(score + scores.iter().sum::<usize>(), deck.len() / 2);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use crypto::{
+    deck::DeckSpec,
+    shuffle_v2::DecryptionShare,
+    signature::SigningKey,
+    types::{EncryptedValue, KeyType},
+};
+use network::{error::Result, session::SessionState};
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    player::{other_player_id, GameRng, OtherPlayer, Owner, Peer, Player},
+    transcript::{Entry, Recorder},
+    trick_game::TrickState,
+};
+
+/*
+ * Player stores a checkpoint between steps of the game, after a crash or a dropped
+ * connection it continues from the last one. Connections to other players are resumed
+ * from their stored sessions, so messages received after the checkpoint are sent again
+ * and messages the player repeats are ignored by the others.
+ * Randomness of the player is reseeded at every checkpoint and the seed is stored,
+ * so the restored player repeats exactly the messages it sent after the checkpoint,
+ * which the others have to get unchanged.
+ * Checkpoint holds the per-card keys in the clear, anybody who reads it sees
+ * all cards of the player, so the file is only readable by its owner.
+ */
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Checkpoint {
+    pub game: u64,
+    pub name: String,
+    pub player_id: usize,
+    pub spec: DeckSpec,
+    pub deck: Vec<EncryptedValue>,
+    pub keys: Vec<KeyType>,
+    pub owners: Vec<Option<Owner>>,
+    pub commitments: Vec<Vec<EncryptedValue>>,
+    pub drawn: HashMap<usize, Vec<DecryptionShare>>,
+    /// `sessions[ind]` is the state of the connection to `players[ind]`.
+    pub sessions: Vec<SessionState>,
+    pub entries: Vec<Entry>,
+    /// Seed of the randomness the player continues with after the checkpoint.
+    pub seed: [u8; 32],
+    pub state: TrickState,
+}
+
+impl Checkpoint {
+    /// Replaces the file at `path` only once the checkpoint is written completely.
+    /// The file contains secret keys, on unix it is created with mode 0600.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let tmp = format!("{}.tmp", path);
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(&tmp)?.write_all(&serde_json::to_vec(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// `None` if there is no checkpoint at `path`.
+    pub fn load(path: &str) -> Result<Option<Self>> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl Player {
+    /// Stores the player in game `game` with the game `state`,
+    /// marks everything received so far as stored for the other players.
+    pub fn checkpoint(&mut self, game: u64, state: TrickState) -> Checkpoint {
+        let sessions = self
+            .players
+            .iter_mut()
+            .map(|o| o.checkpoint().unwrap_or_default())
+            .collect();
+        let seed = self.rng.gen();
+        self.rng = GameRng::from_seed(seed);
+        Checkpoint {
+            game,
+            name: self.name.clone(),
+            player_id: self.player_id,
+            spec: self.spec.clone(),
+            deck: self.deck.clone(),
+            keys: self.keys.clone(),
+            owners: self.owners.clone(),
+            commitments: self.commitments.clone(),
+            drawn: self.drawn.clone(),
+            sessions,
+            entries: self.recorder.entries(),
+            seed,
+            state,
+        }
+    }
+
+    /// Player from `checkpoint` with new connections `players`, ordered by ids
+    /// and resumed from `checkpoint.sessions`, and the state of the game.
+    /// `signer` is the long-term key of the player, it is not stored in the checkpoint.
+    pub fn restore(
+        checkpoint: Checkpoint,
+        mut players: Vec<OtherPlayer>,
+        signer: SigningKey,
+    ) -> (Player, TrickState) {
+        let recorder = Recorder::with_entries(checkpoint.entries);
+        let rng = GameRng::from_seed(checkpoint.seed);
+        for (ind, o) in players.iter_mut().enumerate() {
+            o.record(other_player_id(checkpoint.player_id, ind), recorder.clone());
+        }
+        let player = Player {
+            translator: checkpoint.spec.translator(),
+            start_deck: checkpoint.spec.deck(),
+            deck: checkpoint.deck,
+            keys: checkpoint.keys,
+            players,
+            owners: checkpoint.owners,
+            name: checkpoint.name,
+            player_id: checkpoint.player_id,
+            commitments: checkpoint.commitments,
+            drawn: checkpoint.drawn,
+            signer,
+            rng,
+            spec: checkpoint.spec,
+            recorder,
+        };
+        (player, checkpoint.state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use std::time::Duration;
+
+    use common::game::{CardFromDeck, CardSelector, GamePrinter, GameState};
+    use crypto::deck::DeckSpec;
+    use network::{
+        con_startup::ConStartup,
+        session::{Reconnect, ResumableConnection},
+    };
+
+    use super::Checkpoint;
+    use crate::{
+        audit::Audit,
+        player::{
+            game_rng,
+            test::{prepare_players, signer},
+            DeckPreparation, DeckPreparationBasic, OtherPlayer, Player,
+        },
+        simple_game::{SimpleGame, SimpleRules},
+    };
+
+    struct MockPrinter {}
+
+    impl GamePrinter for MockPrinter {
+        fn print_game(&mut self, _game_state: &GameState) {}
+    }
+
+    struct MockSelector {}
+
+    impl CardSelector for MockSelector {
+        fn select_card(&mut self, hand: &[CardFromDeck]) -> CardFromDeck {
+            *hand.first().unwrap()
+        }
+    }
+
+    fn new_game(player_id: usize, player: Player) -> SimpleGame<MockPrinter, MockSelector, Player> {
+        SimpleGame::new(
+            SimpleRules {},
+            player_id,
+            2,
+            player,
+            MockPrinter {},
+            MockSelector {},
+        )
+    }
+
+    // both players reconnect through `listener`, player 0 accepts and player 1 dials,
+    // so a failing test ends with timeouts instead of waiting for a connection
+    fn reconnector(listener: Arc<TcpListener>, address: String, player_id: usize) -> Reconnect {
+        let startup = ConStartup::new(2, player_id).with_read_timeout(Duration::from_secs(5));
+        Box::new(move || startup.reconnect(&listener, &address, 1 - player_id))
+    }
+
+    fn opponent(connection: ResumableConnection, player_id: usize) -> OtherPlayer {
+        OtherPlayer::new(connection).with_peer_key(signer(1 - player_id).verifying_key())
+    }
+
+    #[test]
+    fn restore_after_crash() {
+        let listener = Arc::new(TcpListener::bind("127.0.0.1:0").unwrap());
+        let address = listener.local_addr().unwrap().to_string();
+        let crashed = Arc::new(Barrier::new(2));
+        let handles = (0..2)
+            .map(|player_id| {
+                let (listener, address) = (listener.clone(), address.clone());
+                let crashed = crashed.clone();
+                thread::spawn(move || {
+                    let mut reconnect = reconnector(listener.clone(), address.clone(), player_id);
+                    let connection = reconnect().unwrap();
+                    let player = DeckPreparationBasic::prepare(
+                        format!("P{}", player_id),
+                        signer(player_id),
+                        vec![opponent(
+                            ResumableConnection::new(connection, reconnect),
+                            player_id,
+                        )],
+                        player_id,
+                        DeckSpec::short(),
+                        &mut game_rng(None),
+                    )
+                    .unwrap();
+                    let mut game = new_game(player_id, player);
+                    game.play_one_step().unwrap();
+                    let state = game.state();
+                    let checkpoint = game.player_mut().checkpoint(7, state);
+                    // the first trick sends shares of the played card and of the drawn ones
+                    game.play_one_step().unwrap();
+                    crashed.wait();
+                    if player_id == 1 {
+                        // the connection is dropped with the crashed player
+                        drop(game);
+                        let mut reconnect = reconnector(listener, address, player_id);
+                        let connection = reconnect().unwrap();
+                        let session = checkpoint.sessions.first().cloned().unwrap();
+                        let connection =
+                            ResumableConnection::restore(connection, session, reconnect).unwrap();
+                        let (player, state) = Player::restore(
+                            checkpoint,
+                            vec![opponent(connection, player_id)],
+                            signer(player_id),
+                        );
+                        game = new_game(player_id, player).with_state(state);
+                    }
+                    let (score, scores) = game.play().unwrap();
+                    assert_eq!(score + scores.iter().sum::<usize>(), 8);
+                    assert!(game.into_player().audit().unwrap().is_valid());
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+    }
+
+    #[test]
+    fn continue_from_checkpoint() {
+        let handles = prepare_players::<DeckPreparationBasic>(2, DeckSpec::short())
+            .into_iter()
+            .enumerate()
+            .map(|(player_id, player)| {
+                thread::spawn(move || {
+                    let mut game = new_game(player_id, player);
+                    game.play_one_step().unwrap();
+                    game.play_one_step().unwrap();
+                    let state = game.state();
+                    let path = std::env::temp_dir().join(format!(
+                        "checkpoint-{}-{}",
+                        std::process::id(),
+                        player_id
+                    ));
+                    let path = path.to_str().unwrap();
+                    game.player_mut().checkpoint(7, state).save(path).unwrap();
+                    #[cfg(unix)]
+                    {
+                        use std::os::unix::fs::PermissionsExt;
+                        let mode = std::fs::metadata(path).unwrap().permissions().mode();
+                        assert_eq!(mode & 0o777, 0o600);
+                    }
+                    let mut player = game.into_player();
+                    let others = std::mem::take(&mut player.players);
+                    let checkpoint = Checkpoint::load(path).unwrap().unwrap();
+                    std::fs::remove_file(path).unwrap();
+                    assert_eq!(checkpoint.game, 7);
+                    let (player, state) = Player::restore(checkpoint, others, signer(player_id));
+                    let mut game = new_game(player_id, player).with_state(state);
+                    let (score, scores) = game.play().unwrap();
+                    assert_eq!(score + scores.iter().sum::<usize>(), 8);
+                    assert!(game.into_player().audit().unwrap().is_valid());
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+    }
+}
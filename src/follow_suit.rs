@@ -0,0 +1,39 @@
+use crate::trick_game::GameRules;
+use common::{
+    cards::Card,
+    game::CardFromDeck,
+    hands::{trick_winner, RankOrder},
+};
+
+/// Whole deck is dealt at the start, players have to follow the led suit
+/// if they can and the highest card in the led suit wins.
+#[derive(Debug, Clone, Copy)]
+pub struct FollowSuitRules {}
+
+impl GameRules for FollowSuitRules {
+    fn hand_size(&self, num_players: usize, deck_size: usize) -> usize {
+        deck_size / num_players
+    }
+    fn legal_moves(&self, hand: &[CardFromDeck], trick: &[Card]) -> Vec<CardFromDeck> {
+        let following = trick
+            .first()
+            .map(|led| {
+                hand.iter()
+                    .filter(|c| c.card.suit == led.suit)
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if following.is_empty() {
+            hand.to_vec()
+        } else {
+            following
+        }
+    }
+    fn trick_winner(&self, trick: &[Card]) -> usize {
+        trick_winner(trick, None, RankOrder::AceHigh)
+    }
+    fn draw_after_trick(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,477 @@
+use common::{
+    cards::Card,
+    game::{
+        ActionSelector, BetAction, BettingState, GameOutcome, GamePrinter, GameState, ShowHandDeck,
+    },
+    hands::evaluate_hand,
+};
+use network::{
+    error::{Error, Result},
+    message::{unexpected, ProtocolMessage},
+};
+
+use crate::{moves::Moves, player::Player, trick_game::failure_outcome};
+
+/*
+ * Single hand of Texas Hold'em for players with ids 0..num_players.
+ * Player 0 posts the small blind and player 1 the big blind, then everybody draws
+ * two private hole cards in order of ids. Community cards are revealed from the deck
+ * to everybody, see `Moves::reveal_from_deck`. Preflop betting starts after the big blind,
+ * later rounds start with player 0. Raises are limited by the smallest stack
+ * still in the hand, so everybody can always call and no side pots arise.
+ * At showdown players still in the hand play their hole cards in order of ids.
+ */
+
+const HOLE_CARDS: usize = 2;
+const STREETS: [usize; 3] = [3, 1, 1];
+
+pub trait Betting {
+    fn bet(&mut self, action: BetAction) -> Result<()>;
+    fn let_bet(&mut self, other: usize) -> Result<BetAction>;
+}
+
+impl Betting for Player {
+    fn bet(&mut self, action: BetAction) -> Result<()> {
+        for o in self.players.iter_mut() {
+            o.send(&ProtocolMessage::Bet(action))?;
+        }
+        Ok(())
+    }
+
+    fn let_bet(&mut self, other: usize) -> Result<BetAction> {
+        match self.players.get_mut(other).unwrap().receive()? {
+            ProtocolMessage::Bet(action) => Ok(action),
+            message => Err(unexpected(message)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Table {
+    /// Chips not yet put in the pot.
+    chips: Vec<u64>,
+    /// Chips put in during the current betting round.
+    bets: Vec<u64>,
+    folded: Vec<bool>,
+    /// Chips from finished betting rounds.
+    pot: u64,
+}
+
+impl Table {
+    fn new(chips: Vec<u64>) -> Self {
+        let num_players = chips.len();
+        Table {
+            chips,
+            bets: vec![0; num_players],
+            folded: vec![false; num_players],
+            pot: 0,
+        }
+    }
+
+    fn in_hand(&self) -> impl Iterator<Item = usize> + '_ {
+        self.folded
+            .iter()
+            .enumerate()
+            .filter(|(_, &folded)| !folded)
+            .map(|(id, _)| id)
+    }
+
+    fn current_bet(&self) -> u64 {
+        *self.bets.iter().max().unwrap()
+    }
+
+    fn to_call(&self, id: usize) -> u64 {
+        self.current_bet() - self.bets.get(id).unwrap()
+    }
+
+    fn max_raise(&self) -> u64 {
+        self.in_hand()
+            .map(|id| self.chips.get(id).unwrap() + self.bets.get(id).unwrap())
+            .min()
+            .unwrap()
+            - self.current_bet()
+    }
+
+    fn put(&mut self, id: usize, amount: u64) {
+        *self.chips.get_mut(id).unwrap() -= amount;
+        *self.bets.get_mut(id).unwrap() += amount;
+    }
+
+    /// Applies action of player `id`, returns whether the bet was raised.
+    fn apply(&mut self, id: usize, action: BetAction) -> std::result::Result<bool, &'static str> {
+        let to_call = self.to_call(id);
+        match action {
+            BetAction::Fold => {
+                *self.folded.get_mut(id).unwrap() = true;
+                Ok(false)
+            }
+            BetAction::Check if to_call == 0 => Ok(false),
+            BetAction::Check => Err("Check with bet to call"),
+            BetAction::Call => {
+                self.put(id, to_call);
+                Ok(false)
+            }
+            BetAction::Raise(amount) if amount > 0 && amount <= self.max_raise() => {
+                self.put(id, to_call + amount);
+                Ok(true)
+            }
+            BetAction::Raise(_) => Err("Raise out of range"),
+        }
+    }
+
+    fn collect_bets(&mut self) {
+        self.pot += self.bets.iter().sum::<u64>();
+        self.bets.iter_mut().for_each(|bet| *bet = 0);
+    }
+}
+
+pub struct Holdem<Printer, Selector, PlayerType>
+where
+    Printer: GamePrinter,
+    Selector: ActionSelector,
+    PlayerType: Moves + Betting + ShowHandDeck,
+{
+    player: PlayerType,
+    player_id: usize,
+    num_players: usize,
+    big_blind: u64,
+    table: Table,
+    hole_cards: Vec<Card>,
+    community_cards: Vec<Card>,
+    printer: Printer,
+    selector: Selector,
+}
+
+impl<Printer, Selector, PlayerType> Holdem<Printer, Selector, PlayerType>
+where
+    Printer: GamePrinter,
+    Selector: ActionSelector,
+    PlayerType: Moves + Betting + ShowHandDeck,
+{
+    /// `chips[id]` is the stack of player `id`, every stack has to cover the big blind.
+    pub fn new(
+        player_id: usize,
+        player: PlayerType,
+        chips: Vec<u64>,
+        big_blind: u64,
+        printer: Printer,
+        selector: Selector,
+    ) -> Self {
+        let num_players = chips.len();
+        assert!(num_players >= 2 && player_id < num_players);
+        assert!(chips.iter().all(|&c| c >= big_blind));
+        Holdem {
+            player,
+            player_id,
+            num_players,
+            big_blind,
+            table: Table::new(chips),
+            hole_cards: vec![],
+            community_cards: vec![],
+            printer,
+            selector,
+        }
+    }
+
+    // index of player `id` among other players, as used by `Moves`
+    fn other_index(&self, id: usize) -> usize {
+        if id < self.player_id {
+            id
+        } else {
+            id - 1
+        }
+    }
+
+    fn print_game(&mut self) {
+        self.printer.print_game(&GameState {
+            hand: self.hole_cards.clone(),
+            table_cards: self.community_cards.clone(),
+            deck_cards: self.player.deck_size(),
+        });
+    }
+
+    fn betting_state(&self) -> BettingState {
+        BettingState {
+            hole_cards: self.hole_cards.clone(),
+            community_cards: self.community_cards.clone(),
+            pot: self.table.pot + self.table.bets.iter().sum::<u64>(),
+            to_call: self.table.to_call(self.player_id),
+            chips: *self.table.chips.get(self.player_id).unwrap(),
+            max_raise: self.table.max_raise(),
+        }
+    }
+
+    fn played_card(&self, card: Card) -> usize {
+        self.player
+            .show_hand()
+            .iter()
+            .find(|c| c.card == card)
+            .unwrap()
+            .ind
+    }
+
+    fn deal_hole_cards(&mut self) -> Result<()> {
+        for id in 0..self.num_players {
+            for _ in 0..HOLE_CARDS {
+                if id == self.player_id {
+                    let card = self.player.draw_from_deck()?;
+                    self.hole_cards.push(card);
+                } else {
+                    self.player.let_draw_from_deck(self.other_index(id))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn deal_community_card(&mut self) -> Result<()> {
+        let card = self.player.reveal_from_deck()?;
+        self.community_cards.push(card);
+        Ok(())
+    }
+
+    fn action(&mut self, id: usize) -> Result<BetAction> {
+        if id == self.player_id {
+            let action = self.selector.select_action(&self.betting_state());
+            self.table.apply(id, action).map_err(Error::protocol)?;
+            self.player.bet(action)?;
+            Ok(action)
+        } else {
+            let action = self.player.let_bet(self.other_index(id))?;
+            self.table
+                .apply(id, action)
+                .map_err(|reason| Error::cheating(id, reason))?;
+            Ok(action)
+        }
+    }
+
+    fn betting_round(&mut self, first: usize) -> Result<()> {
+        let mut to_act = self.table.in_hand().count();
+        let mut id = first;
+        while to_act > 0 && self.table.in_hand().count() > 1 {
+            if !self.table.folded.get(id).unwrap() {
+                self.print_game();
+                let action = self.action(id)?;
+                if matches!(action, BetAction::Raise(_)) {
+                    to_act = self.table.in_hand().count() - 1;
+                } else {
+                    to_act -= 1;
+                }
+            }
+            id = (id + 1) % self.num_players;
+        }
+        self.table.collect_bets();
+        Ok(())
+    }
+
+    // ids of players with the best hand among players still in the hand
+    fn showdown(&mut self) -> Result<Vec<usize>> {
+        let mut hands = vec![];
+        for id in self.table.in_hand().collect::<Vec<_>>() {
+            let mut cards = self.community_cards.clone();
+            if id == self.player_id {
+                for card in self.hole_cards.clone() {
+                    self.player.play_card(self.played_card(card))?;
+                    cards.push(card);
+                }
+            } else {
+                for _ in 0..HOLE_CARDS {
+                    cards.push(self.player.let_play_card(self.other_index(id))?);
+                }
+            }
+            let value = evaluate_hand(&cards)
+                .ok_or_else(|| Error::protocol("Cards do not make a poker hand"))?;
+            hands.push((id, value));
+        }
+        let best = hands.iter().map(|(_, value)| value).max().unwrap().clone();
+        Ok(hands
+            .into_iter()
+            .filter(|(_, value)| *value == best)
+            .map(|(id, _)| id)
+            .collect())
+    }
+
+    /// Plays the hand and returns stacks of all players ordered by ids.
+    pub fn play(&mut self) -> Result<Vec<u64>> {
+        self.table.put(0, self.big_blind / 2);
+        self.table.put(1, self.big_blind);
+        self.deal_hole_cards()?;
+        self.betting_round(2 % self.num_players)?;
+        for cards in STREETS {
+            if self.table.in_hand().count() == 1 {
+                break;
+            }
+            for _ in 0..cards {
+                self.deal_community_card()?;
+            }
+            self.betting_round(0)?;
+        }
+        let winners = if self.table.in_hand().count() == 1 {
+            self.table.in_hand().collect()
+        } else {
+            self.showdown()?
+        };
+        self.print_game();
+        // odd chips go to the winners with the lowest ids
+        let share = self.table.pot / winners.len() as u64;
+        let remainder = self.table.pot % winners.len() as u64;
+        for (i, id) in winners.into_iter().enumerate() {
+            *self.table.chips.get_mut(id).unwrap() += share + u64::from((i as u64) < remainder);
+        }
+        self.table.pot = 0;
+        Ok(self.table.chips.clone())
+    }
+
+    /// Outcome of the hand after `play` returned `result`, scores are the stacks.
+    /// Other players are told why the hand failed, as in `TrickGame::outcome`.
+    pub fn outcome(&mut self, result: Result<Vec<u64>>) -> Result<GameOutcome> {
+        let outcome = match result {
+            Ok(mut stacks) => {
+                let score = stacks.remove(self.player_id) as usize;
+                let scores = stacks.into_iter().map(|chips| chips as usize).collect();
+                GameOutcome::Finished { score, scores }
+            }
+            Err(err) => {
+                self.player.abort(&err);
+                failure_outcome(
+                    err,
+                    self.player_id,
+                    self.num_players,
+                    |cheater, evidence| self.player.check_evidence(cheater, evidence),
+                )?
+            }
+        };
+        self.printer.print_outcome(&outcome);
+        Ok(outcome)
+    }
+
+    /// Plays the hand to the end, see `outcome`.
+    pub fn play_to_end(&mut self) -> Result<GameOutcome> {
+        let result = self.play();
+        self.outcome(result)
+    }
+
+    pub fn into_player(self) -> PlayerType {
+        self.player
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+    use std::thread;
+
+    use common::game::{
+        ActionSelector, BetAction, BettingState, GameOutcome, GamePrinter, GameState,
+    };
+    use crypto::deck::DeckSpec;
+    use network::error::Error;
+
+    use super::{Holdem, Table};
+    use crate::player::{test::prepare_players, DeckPreparationBasic};
+
+    struct MockPrinter {}
+
+    impl GamePrinter for MockPrinter {
+        fn print_game(&mut self, _game_state: &GameState) {}
+    }
+
+    // plays scripted actions, then calls
+    struct ScriptSelector {
+        actions: VecDeque<BetAction>,
+    }
+
+    impl ActionSelector for ScriptSelector {
+        fn select_action(&mut self, _state: &BettingState) -> BetAction {
+            self.actions.pop_front().unwrap_or(BetAction::Call)
+        }
+    }
+
+    fn play_hand(chips: Vec<u64>, scripts: Vec<Vec<BetAction>>) -> Vec<Vec<u64>> {
+        let players = prepare_players::<DeckPreparationBasic>(chips.len(), DeckSpec::short());
+        let handles = players
+            .into_iter()
+            .zip(scripts)
+            .enumerate()
+            .map(|(player_id, (player, script))| {
+                let chips = chips.clone();
+                thread::spawn(move || {
+                    let selector = ScriptSelector {
+                        actions: script.into(),
+                    };
+                    Holdem::new(player_id, player, chips, 2, MockPrinter {}, selector)
+                        .play()
+                        .unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    }
+
+    #[test]
+    fn showdown() {
+        let results = play_hand(
+            vec![100; 3],
+            vec![vec![BetAction::Raise(4)], vec![], vec![]],
+        );
+        let first = results.first().unwrap();
+        assert!(results.iter().all(|chips| chips == first));
+        assert_eq!(first.iter().sum::<u64>(), 300);
+    }
+
+    #[test]
+    fn everybody_folds() {
+        let results = play_hand(
+            vec![100; 3],
+            vec![
+                vec![BetAction::Fold],
+                vec![BetAction::Fold],
+                vec![BetAction::Raise(10)],
+            ],
+        );
+        for chips in results {
+            assert_eq!(chips, vec![99, 98, 103]);
+        }
+    }
+
+    #[test]
+    fn failed_hand_is_aborted() {
+        let players = prepare_players::<DeckPreparationBasic>(2, DeckSpec::short());
+        let handles = players
+            .into_iter()
+            .enumerate()
+            .map(|(player_id, player)| {
+                thread::spawn(move || {
+                    // player 0 raises more than it has, fails and tells player 1
+                    let selector = ScriptSelector {
+                        actions: vec![BetAction::Raise(1000)].into(),
+                    };
+                    Holdem::new(player_id, player, vec![100; 2], 2, MockPrinter {}, selector)
+                        .play_to_end()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut results = handles.into_iter().map(|h| h.join().unwrap());
+        assert!(matches!(
+            results.next().unwrap(),
+            Err(Error::ProtocolViolation(_))
+        ));
+        assert!(matches!(
+            results.next().unwrap(),
+            Ok(GameOutcome::Aborted { player: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn betting_rules() {
+        let mut table = Table::new(vec![50, 20]);
+        table.put(0, 1);
+        table.put(1, 2);
+        assert!(table.apply(0, BetAction::Check).is_err());
+        assert!(table.apply(0, BetAction::Raise(19)).is_err());
+        assert_eq!(table.apply(0, BetAction::Raise(18)), Ok(true));
+        assert_eq!(table.max_raise(), 0);
+        assert_eq!(table.apply(1, BetAction::Call), Ok(false));
+        assert_eq!(table.chips, vec![30, 0]);
+    }
+}
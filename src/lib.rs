@@ -0,0 +1,12 @@
+pub mod async_moves;
+pub mod async_player;
+pub mod audit;
+pub mod bot;
+pub mod checkpoint;
+pub mod follow_suit;
+pub mod holdem;
+pub mod moves;
+pub mod player;
+pub mod simple_game;
+pub mod transcript;
+pub mod trick_game;
@@ -1,14 +1,46 @@
-use clap::{ArgGroup, Parser};
+use std::time::Duration;
+
+use clap::{error::ErrorKind, ArgGroup, CommandFactory, Parser, ValueEnum};
+use common::game::{
+    ActionSelector, BetAction, BettingState, CardSelector, GameOutcome, GamePrinter,
+};
 // use cli::{CliPrinter, CliSelector};
-use crypto::encryption::{basic_deck, short_deck};
+use crypto::curve::CURVE;
+use crypto::deck::DeckSpec;
+use crypto::shuffle_v2::ShuffleWithProof;
+use crypto::shuffle_v3::ShuffleWithArgument;
+use crypto::signature::SigningKey;
 use network::con_startup::ConStartup;
-use player::{DeckPreparation, DeckPreparationBasic, DeckPreparationVerification, OtherPlayer};
-use simple_game::SimpleGame;
-use webapp::get_web_interface;
+use network::connection::Codec;
+use network::error::Result;
+use network::heartbeat::HeartbeatConnection;
+use network::message::MessageConnection;
+use network::secure::{Identity, PublicKey};
+use network::session::{Reconnect, ResumableConnection};
+use rand::SeedableRng;
+use secure_card_game::audit::Audit;
+use secure_card_game::bot::{BotSelector, Strategy};
+use secure_card_game::checkpoint::Checkpoint;
+use secure_card_game::holdem::Holdem;
+use secure_card_game::player::{
+    game_rng, DeckPreparation, DeckPreparationBasic, DeckPreparationVerification, GameRng,
+    OtherPlayer, Player,
+};
+use secure_card_game::simple_game::{SimpleGame, SimpleRules};
+use secure_card_game::trick_game::TrickState;
+use webapp::{get_web_interface, WebInterface};
+
+/// Stack of every player at the start of a hold'em hand.
+const CHIPS: u64 = 100;
+const BIG_BLIND: u64 = 2;
 
-pub mod moves;
-pub mod player;
-pub mod simple_game;
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Trick-taking game
+    Trick,
+    /// Single hand of Texas Hold'em, bets are typed in the terminal unless a bot plays
+    Holdem,
+}
 
 #[derive(Parser, Debug)]
 #[clap(group(
@@ -37,66 +69,403 @@ struct Cli {
     #[clap(long)]
     verify: bool,
 
+    /// Linear-size shuffle proofs for verify
+    #[clap(long, requires = "verify")]
+    fast_shuffle: bool,
+
     /// 52 instead of 16
-    #[clap(long)]
+    #[clap(long, conflicts_with = "deck")]
     big_deck: bool,
+
+    /// Deck: standard, short, schnapsen, skat, double, canasta or five-suits
+    #[clap(long, value_parser = parse_deck)]
+    deck: Option<DeckSpec>,
+
+    /// Binary (CBOR) messages instead of JSON
+    #[clap(long)]
+    binary: bool,
+
+    /// Writes transcript of the trick game signed by the identity to this file
+    #[clap(long, requires = "identity")]
+    transcript: Option<String>,
+
+    /// Game to play
+    #[clap(long, value_enum, default_value_t = Mode::Trick)]
+    mode: Mode,
+
+    /// Id of the game session, both players have to use the same
+    #[clap(long, default_value_t = 0)]
+    game: u64,
+
+    /// Stores progress of the trick game to this file and resumes from it after a restart
+    #[clap(long)]
+    checkpoint: Option<String>,
+
+    /// Seconds to wait for the opponent before giving up
+    #[clap(long)]
+    timeout: Option<u64>,
+
+    /// Debug only: seeds all randomness, so the game can be reproduced
+    #[clap(long)]
+    debug_seed: Option<u64>,
+
+    /// Computer plays instead of the browser: random, greedy or monte-carlo
+    #[clap(long, value_parser = parse_strategy)]
+    bot: Option<Strategy>,
+
+    /// Key file with identity of the player, a new identity is written to it if missing
+    #[clap(long)]
+    identity: Option<String>,
+
+    /// Public key of the opponent, the connection is secured and accepts only this key
+    #[clap(long, requires = "identity", value_parser = parse_peer)]
+    peer: Option<PublicKey>,
+}
+
+fn parse_deck(name: &str) -> std::result::Result<DeckSpec, String> {
+    DeckSpec::by_name(name).ok_or(format!("Unknown deck {}", name))
+}
+
+fn parse_strategy(name: &str) -> std::result::Result<Strategy, String> {
+    Strategy::by_name(name).ok_or(format!("Unknown bot {}", name))
+}
+
+fn parse_peer(key: &str) -> std::result::Result<PublicKey, String> {
+    key.parse()
+        .map_err(|_| format!("Invalid public key {}", key))
+}
+
+// heartbeats keep the opponent waiting while the player decides,
+// accusations in aborts of the opponent are only trusted with its pinned key
+fn opponent<C: MessageConnection + 'static>(
+    connection: C,
+    timeout: Option<Duration>,
+    peer: Option<PublicKey>,
+) -> OtherPlayer {
+    let other = match timeout {
+        Some(timeout) => OtherPlayer::new(HeartbeatConnection::new(connection, timeout / 3)),
+        None => OtherPlayer::new(connection),
+    };
+    match peer {
+        Some(peer) => other.with_peer_key(peer.signing),
+        None => other,
+    }
+}
+
+// identity from the key file, a new one is generated and saved on the first run
+fn load_identity(path: &str, rng: &mut GameRng) -> Result<Identity> {
+    if let Some(identity) = Identity::load(path)? {
+        return Ok(identity);
+    }
+    let identity = Identity::generate(rng);
+    identity.save(path)?;
+    println!("New identity written to {}", path);
+    Ok(identity)
+}
+
+// stores a checkpoint before every step of the game
+fn play_with_checkpoints<P: GamePrinter, S: CardSelector>(
+    game: &mut SimpleGame<P, S, Player>,
+    checkpoint: Option<&str>,
+    game_id: u64,
+) -> Result<()> {
+    while !game.is_done() {
+        if let Some(path) = checkpoint {
+            let state = game.state();
+            game.player_mut().checkpoint(game_id, state).save(path)?;
+        }
+        game.play_one_step()?;
+    }
+    Ok(())
+}
+
+// betting decisions are typed in the terminal, the browser only picks cards
+struct PromptSelector {}
+
+impl ActionSelector for PromptSelector {
+    fn select_action(&mut self, state: &BettingState) -> BetAction {
+        println!(
+            "Pot {}, to call {}, your chips {}, raise up to {}",
+            state.pot, state.to_call, state.chips, state.max_raise
+        );
+        loop {
+            println!("fold, check, call or raise <amount>:");
+            let mut line = String::new();
+            // closed terminal gives up the hand
+            if std::io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+                return BetAction::Fold;
+            }
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next().map(str::parse::<u64>)) {
+                (Some("fold"), None) => return BetAction::Fold,
+                (Some("check"), None) if state.to_call == 0 => return BetAction::Check,
+                (Some("call"), None) => return BetAction::Call,
+                (Some("raise"), Some(Ok(amount))) if (1..=state.max_raise).contains(&amount) => {
+                    return BetAction::Raise(amount)
+                }
+                _ => println!("Not a valid action"),
+            }
+        }
+    }
+}
+
+// stacks after the hand are the scores, failures end it like a trick game
+fn play_holdem<P: GamePrinter, S: ActionSelector>(
+    mut game: Holdem<P, S, Player>,
+) -> Result<(GameOutcome, Player)> {
+    println!("Starting hold'em");
+
+    let outcome = game.play_to_end()?;
+    Ok((outcome, game.into_player()))
+}
+
+// the game is driven by the browser or by a bot, both show it in the browser
+fn play_game<P: GamePrinter, S: CardSelector>(
+    mut game: SimpleGame<P, S, Player>,
+    state: Option<TrickState>,
+    checkpoint: Option<&str>,
+    game_id: u64,
+) -> Result<(GameOutcome, Player)> {
+    if let Some(state) = state {
+        game = game.with_state(state);
+    }
+
+    println!("Starting game");
+
+    let result = play_with_checkpoints(&mut game, checkpoint, game_id);
+    let outcome = game.outcome(result)?;
+    Ok((outcome, game.into_player()))
 }
 
 fn main() {
+    let cli = Cli::parse();
+    assert_ne!(cli.client, cli.server);
+    // poker hands are only ranked for cards of the standard deck, each at most once
+    if cli.mode == Mode::Holdem
+        && cli
+            .deck
+            .as_ref()
+            .is_some_and(|deck| !deck.is_standard_subset())
+    {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--mode holdem needs a deck without jokers, extra suits or copies",
+            )
+            .exit();
+    }
+    // transcripts are replayed as trick games by verify-transcript
+    if cli.mode == Mode::Holdem && cli.transcript.is_some() {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--transcript can only be written in --mode trick",
+            )
+            .exit();
+    }
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    ctrlc::set_handler(|| std::process::exit(0)).expect("Setting handler should not fail");
+
+    let web_interface = get_web_interface(cli.port);
+
+    if let Err(err) = run(cli, web_interface.clone()) {
+        eprintln!("Game aborted: {}", err);
+        web_interface.stop_server();
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: Cli, web_interface: WebInterface) -> Result<()> {
     let Cli {
         address,
-        port,
-        client,
         server,
         verify,
+        fast_shuffle,
         big_deck,
-    } = Cli::parse();
-    assert_ne!(client, server);
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-    ctrlc::set_handler(|| std::process::exit(0)).expect("Setting handler should not fail");
-
-    let web_interface = get_web_interface(port);
+        deck,
+        binary,
+        debug_seed,
+        transcript,
+        mode,
+        game: game_id,
+        checkpoint,
+        timeout,
+        bot,
+        identity,
+        peer,
+        ..
+    } = cli;
 
     let num_players = 2;
+    // only trick games can be resumed
+    let checkpoint = checkpoint.filter(|_| mode == Mode::Trick);
     let player_id = if server { 0 } else { 1 };
-    let startup = ConStartup::new(num_players, player_id);
-
-    let other = OtherPlayer::new(startup.initialize(&address));
-    let name = if server {
-        "server".to_string()
-    } else {
-        "client".to_string()
+    let codec = if binary { Codec::Cbor } else { Codec::Json };
+    let deck = match deck {
+        Some(deck) => deck,
+        None if big_deck => DeckSpec::standard(),
+        None => DeckSpec::short(),
     };
-    println!("Preparation start");
-    let deck = if big_deck {
-        basic_deck().to_vec()
-    } else {
-        short_deck().to_vec()
+    let mut startup = ConStartup::new(num_players, player_id)
+        .with_curve(CURVE)
+        .with_deck(&deck)
+        .with_codec(codec)
+        .with_game(game_id);
+    let timeout = timeout.map(Duration::from_secs);
+    if let Some(timeout) = timeout {
+        startup = startup
+            .with_connect_timeout(timeout)
+            .with_read_timeout(timeout);
+    }
+    // players share the debug seed, so it is offset by the id as in the tests
+    let mut rng = game_rng(debug_seed.map(|seed| seed + player_id as u64));
+    let identity = match &identity {
+        Some(path) => Some(load_identity(path, &mut rng)?),
+        None => None,
+    };
+    if let Some(identity) = &identity {
+        println!("Your public key: {}", identity.public_key());
+    }
+    if let (Some(identity), Some(peer)) = (&identity, peer) {
+        let mut peers = vec![identity.public_key(), peer];
+        if player_id == 1 {
+            peers.reverse();
+        }
+        startup = startup.with_identity(identity.clone(), peers);
+    }
+    // without an identity aborts are signed by a key nobody pinned
+    let signer = match &identity {
+        Some(identity) => identity.signer().clone(),
+        None => SigningKey::generate(&mut rng),
+    };
+
+    let connection = startup.initialize(&address)?;
+    let reconnect: Reconnect = Box::new(move || startup.initialize(&address));
+    let saved = match &checkpoint {
+        Some(path) => Checkpoint::load(path)?.filter(|saved| saved.game == game_id),
+        None => None,
     };
-    let player = if verify {
-        DeckPreparationVerification::prepare(name, vec![other], server, deck)
+    let (player, state) = if let Some(saved) = saved {
+        println!("Resuming game {} from checkpoint", game_id);
+        let session = saved.sessions.first().cloned().unwrap_or_default();
+        let connection = ResumableConnection::restore(connection, session, reconnect)?;
+        let other = opponent(connection, timeout, peer);
+        let (player, state) = Player::restore(saved, vec![other], signer);
+        (player, Some(state))
     } else {
-        DeckPreparationBasic::prepare(name, vec![other], server, deck)
+        let other = opponent(
+            ResumableConnection::new(connection, reconnect),
+            timeout,
+            peer,
+        );
+        let name = if server {
+            "server".to_string()
+        } else {
+            "client".to_string()
+        };
+        println!("Preparation start");
+        if let Some(seed) = debug_seed {
+            println!("Using debug seed {}, the game is not secure", seed);
+        }
+        let player = if fast_shuffle {
+            DeckPreparationVerification::<ShuffleWithArgument>::prepare(
+                name,
+                signer.clone(),
+                vec![other],
+                player_id,
+                deck,
+                &mut rng,
+            )?
+        } else if verify {
+            DeckPreparationVerification::<ShuffleWithProof>::prepare(
+                name,
+                signer.clone(),
+                vec![other],
+                player_id,
+                deck,
+                &mut rng,
+            )?
+        } else {
+            DeckPreparationBasic::prepare(name, signer, vec![other], player_id, deck, &mut rng)?
+        };
+        println!("Preparation completed");
+        (player, None)
     };
-    println!("Preparation completed");
 
     println!("Player deck size: {}", player.deck.len());
 
-    let game = SimpleGame::new(
-        player_id,
-        num_players,
-        player,
-        web_interface.clone(),
-        web_interface.clone(),
-    );
-
-    println!("Starting game");
-
-    let (score, scores) = game.play();
+    let chips = vec![CHIPS; num_players];
+    let (outcome, mut player) = match (mode, bot) {
+        (Mode::Holdem, Some(strategy)) => {
+            println!("Playing as {:?} bot", strategy);
+            let bot_rng = GameRng::from_rng(&mut rng).unwrap();
+            let selector = BotSelector::new(strategy, num_players, &player.spec, bot_rng);
+            let printer = selector.printer(web_interface.clone());
+            let game = Holdem::new(player_id, player, chips, BIG_BLIND, printer, selector);
+            play_holdem(game)?
+        }
+        (Mode::Holdem, None) => {
+            let selector = PromptSelector {};
+            let printer = web_interface.clone();
+            let game = Holdem::new(player_id, player, chips, BIG_BLIND, printer, selector);
+            play_holdem(game)?
+        }
+        (Mode::Trick, Some(strategy)) => {
+            println!("Playing as {:?} bot", strategy);
+            let bot_rng = GameRng::from_rng(&mut rng).unwrap();
+            let selector = BotSelector::new(strategy, num_players, &player.spec, bot_rng);
+            let game = SimpleGame::new(
+                SimpleRules {},
+                player_id,
+                num_players,
+                player,
+                selector.printer(web_interface.clone()),
+                selector,
+            );
+            play_game(game, state, checkpoint.as_deref(), game_id)?
+        }
+        (Mode::Trick, None) => {
+            let game = SimpleGame::new(
+                SimpleRules {},
+                player_id,
+                num_players,
+                player,
+                web_interface.clone(),
+                web_interface.clone(),
+            );
+            play_game(game, state, checkpoint.as_deref(), game_id)?
+        }
+    };
+    if let Some(path) = &checkpoint {
+        std::fs::remove_file(path)?;
+    }
+    let (score, scores) = match outcome {
+        GameOutcome::Finished { score, scores } => (score, scores),
+        outcome => {
+            println!("{}", outcome);
+            return Ok(());
+        }
+    };
 
     println!();
     println!("Your score: {}", score);
     println!("Opponent's score: {:?}", scores.first().unwrap());
 
+    let report = player.audit()?;
+    if report.is_valid() {
+        println!("Deck audit passed");
+    } else {
+        println!("Deck audit failed: {:?}", report);
+    }
+
+    if let Some(path) = transcript {
+        let mut all_scores = scores.clone();
+        all_scores.insert(player_id, score);
+        let signed = player.transcript(all_scores).sign(&player.signer, &mut rng);
+        std::fs::write(&path, serde_json::to_vec(&signed)?)?;
+        println!("Transcript written to {}", path);
+    }
+
     web_interface.end_game(score, *scores.first().unwrap());
+    Ok(())
 }
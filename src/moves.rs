@@ -1,86 +1,631 @@
+use std::iter::zip;
+
 use common::{cards::Card, game::CardFromDeck};
-use crypto::{encryption::decrypt, types::KeyType};
-use network::connection::Connection;
+use crypto::{
+    encryption::decrypt,
+    shuffle_v2::{key_commitment, DecryptionShare},
+    types::EncryptedValue,
+};
+use network::{
+    error::{Error, Result},
+    message::{unexpected, Evidence, ProtocolMessage},
+};
+
+use crate::player::{run, send_abort, Owner, Peer, Player};
+use crate::transcript::Entry;
 
-use crate::player::{Owner, Player};
+/*
+ * Keys are never sent in the clear during the game. Instead a player removes
+ * its own layer of encryption and proves, against the commitment from the preparation,
+ * that it used the right key. Wrong share can be therefore attributed to its author.
+ * Drawn card is decrypted by the other players in order of ids. Every request carries
+ * the shares made so far, so each of them checks that it decrypts the card from the deck.
+ * Played card is sent with the shares of the draw and the share of its owner.
+ * Card revealed from the deck is decrypted by everybody in order of ids and each share
+ * is sent to all players. The reveal is not simultaneous, the last player learns the card
+ * before sending its share. It cannot change the card, but it may leave instead.
+ */
 
 pub trait Moves {
-    fn draw_from_deck(&mut self) -> Card;
-    fn let_draw_from_deck(&mut self, other: usize);
-    fn play_card(&mut self, ind: usize);
-    fn let_play_card(&mut self, other: usize) -> Card;
+    fn draw_from_deck(&mut self) -> Result<Card>;
+    fn let_draw_from_deck(&mut self, other: usize) -> Result<()>;
+    fn play_card(&mut self, ind: usize) -> Result<()>;
+    fn let_play_card(&mut self, other: usize) -> Result<Card>;
+    /// Reveals the top card to all players, the player with the highest id sees it first.
+    fn reveal_from_deck(&mut self) -> Result<Card>;
+    /// Tells other players why the game failed with `err`.
+    fn abort(&mut self, _err: &Error) {}
+    /// Whether `evidence` shows that player `cheater` cheated.
+    fn check_evidence(&self, _cheater: usize, _evidence: &Evidence) -> bool {
+        false
+    }
+}
+
+pub(crate) fn get_top_of_deck<O>(player: &Player<O>) -> Result<usize> {
+    player
+        .owners
+        .iter()
+        .position(|o| o.is_none())
+        .ok_or(Error::protocol("Deck is empty"))
+}
+
+// commitment of player `id` to its key for card `ind`
+fn commitment<O>(player: &Player<O>, id: usize, ind: usize) -> EncryptedValue {
+    if id == player.player_id {
+        return key_commitment(player.keys.get(ind).unwrap());
+    }
+    let other = if id < player.player_id { id } else { id - 1 };
+    *player.commitments.get(other).unwrap().get(ind).unwrap()
+}
+
+/// Checks that `share` of player `id` removes its layer from `card`.
+pub(crate) fn verify_share<O>(
+    player: &Player<O>,
+    id: usize,
+    ind: usize,
+    card: &EncryptedValue,
+    share: &DecryptionShare,
+) -> Result<()> {
+    if share.verify(card, &commitment(player, id, ind)) {
+        Ok(())
+    } else {
+        let evidence = Evidence::Share {
+            ind,
+            card: *card,
+            share: Box::new(*share),
+        };
+        Err(Error::cheating_with(
+            id,
+            "Other player did not provide right key",
+            evidence,
+        ))
+    }
+}
+
+// other players asked for shares when `drawer` draws a card, in order
+pub(crate) fn draw_order(num_players: usize, drawer: usize) -> Vec<usize> {
+    (0..num_players).filter(|&id| id != drawer).collect()
 }
 
-fn get_top_of_deck(player: &mut Player) -> usize {
-    player.owners.iter().position(|o| o.is_none()).unwrap()
+/// Card `ind` of the deck decrypted by `shares` of players `authors`, in order.
+/// Shares are relayed by `sender`, who is blamed for shares of other authors.
+pub(crate) fn apply_shares<O>(
+    player: &Player<O>,
+    ind: usize,
+    authors: &[usize],
+    shares: &[DecryptionShare],
+    sender: usize,
+) -> Result<EncryptedValue> {
+    if shares.len() != authors.len() {
+        return Err(Error::cheating(sender, "Wrong number of shares"));
+    }
+    let mut card = *player.deck.get(ind).unwrap();
+    for (&author, share) in zip(authors, shares) {
+        match verify_share(player, author, ind, &card, share) {
+            Err(_) if author != sender => {
+                return Err(Error::cheating(sender, "Player relayed wrong share"))
+            }
+            result => result?,
+        }
+        card = share.value;
+    }
+    Ok(card)
+}
+
+pub(crate) fn decode_card<O>(player: &Player<O>, value: EncryptedValue) -> Result<Card> {
+    player
+        .translator
+        .translate(value)
+        .and_then(|code| Card::try_from(code).ok())
+        .ok_or(Error::protocol("Card is not in the deck"))
 }
 
 impl Moves for Player {
-    fn draw_from_deck(&mut self) -> Card {
-        let ind = get_top_of_deck(self);
-        let mut encrypted_card = *self.deck.get(ind).unwrap();
-        self.players
-            .iter_mut()
-            .map(|o| -> KeyType {
-                o.send(&ind);
-                o.receive()
-            })
-            .for_each(|k| encrypted_card = decrypt(&encrypted_card, &k));
-        let decrypted_card = decrypt(&encrypted_card, self.keys.get(ind).unwrap());
-        let card = self
-            .translator
-            .translate(decrypted_card)
-            .expect("Other player did not provide right key");
-
-        let card = Card::try_from(card).unwrap();
-        *self.owners.get_mut(ind).unwrap() = Some(Owner::Me(CardFromDeck { card, ind }));
-        card
-    }
-
-    fn let_draw_from_deck(&mut self, other: usize) {
-        let ind = get_top_of_deck(self);
-        let player = self.players.get_mut(other).unwrap();
-        assert_eq!(
+    fn draw_from_deck(&mut self) -> Result<Card> {
+        run(draw_from_deck(self))
+    }
+    fn let_draw_from_deck(&mut self, other: usize) -> Result<()> {
+        run(let_draw_from_deck(self, other))
+    }
+    fn play_card(&mut self, ind: usize) -> Result<()> {
+        run(play_card(self, ind))
+    }
+    fn let_play_card(&mut self, other: usize) -> Result<Card> {
+        run(let_play_card(self, other))
+    }
+    fn reveal_from_deck(&mut self) -> Result<Card> {
+        run(reveal_from_deck(self))
+    }
+    fn abort(&mut self, err: &Error) {
+        run(abort(self, err))
+    }
+    fn check_evidence(&self, cheater: usize, evidence: &Evidence) -> bool {
+        check_evidence(self, cheater, evidence)
+    }
+}
+
+/*
+ * Moves of blocking and async players, see `Peer`.
+ */
+
+pub(crate) async fn draw_from_deck<O: Peer>(player: &mut Player<O>) -> Result<Card> {
+    let ind = get_top_of_deck(player)?;
+    let mut encrypted_card = *player.deck.get(ind).unwrap();
+    let mut shares = vec![];
+    for other in 0..player.players.len() {
+        let o = player.players.get_mut(other).unwrap();
+        o.send(&ProtocolMessage::DrawRequest {
             ind,
-            player.receive::<usize>(),
-            "Other player asks for wrong card"
-        );
-        *self.owners.get_mut(ind).unwrap() = Some(Owner::Other(other));
-        player.send(self.keys.get(ind).unwrap());
-    }
-
-    // works only for two player game atm
-    fn play_card(&mut self, ind: usize) {
-        self.players
-            .iter_mut()
-            .for_each(|o| o.send(&(ind, self.keys.get(ind).unwrap())));
-        *self.owners.get_mut(ind).unwrap() = match *self.owners.get(ind).unwrap() {
-            Some(Owner::Me(card)) => Some(Owner::Player(card)),
-            _ => panic!("Player is not owner of this card"),
+            shares: shares.clone(),
+        })
+        .await?;
+        let share = match o.receive().await? {
+            ProtocolMessage::KeyShare(share) => share,
+            message => return Err(unexpected(message)),
         };
+        verify_share(
+            player,
+            player.other_player_id(other),
+            ind,
+            &encrypted_card,
+            &share,
+        )?;
+        encrypted_card = share.value;
+        shares.push(share);
+    }
+    let decrypted_card = decrypt(&encrypted_card, player.keys.get(ind).unwrap());
+    let card = decode_card(player, decrypted_card)?;
+    *player.owners.get_mut(ind).unwrap() = Some(Owner::Me(CardFromDeck { card, ind }));
+    player.drawn.insert(ind, shares);
+    Ok(card)
+}
+
+pub(crate) async fn let_draw_from_deck<O: Peer>(
+    player: &mut Player<O>,
+    other: usize,
+) -> Result<()> {
+    let ind = get_top_of_deck(player)?;
+    let other_id = player.other_player_id(other);
+    let (requested, shares) = match player.players.get_mut(other).unwrap().receive().await? {
+        ProtocolMessage::DrawRequest { ind, shares } => (ind, shares),
+        message => return Err(unexpected(message)),
+    };
+    if ind != requested {
+        return Err(Error::cheating(
+            other_id,
+            "Other player asks for wrong card",
+        ));
     }
+    // players with lower ids were asked before
+    let authors = draw_order(player.players.len() + 1, other_id)
+        .into_iter()
+        .take_while(|&id| id != player.player_id)
+        .collect::<Vec<_>>();
+    let encrypted_card = apply_shares(player, ind, &authors, &shares, other_id)?;
+    *player.owners.get_mut(ind).unwrap() = Some(Owner::Other(other));
+    let share = DecryptionShare::generate(
+        &encrypted_card,
+        player.keys.get(ind).unwrap(),
+        &mut player.rng,
+    );
+    player
+        .players
+        .get_mut(other)
+        .unwrap()
+        .send(&ProtocolMessage::KeyShare(share))
+        .await
+}
 
-    // works only for two player game atm
-    fn let_play_card(&mut self, other: usize) -> Card {
-        let player = self.players.get_mut(other).unwrap();
-        let (ind, key) = player.receive::<(usize, KeyType)>();
-        match self
-            .owners
-            .get(ind)
-            .expect("Wrong index")
-            .expect("Player is not owner of this card")
-        {
-            Owner::Other(owner) => assert_eq!(owner, other, "Player is not owner of this card"),
-            _ => panic!("Player is not owner of this card"),
+pub(crate) async fn play_card<O: Peer>(player: &mut Player<O>, ind: usize) -> Result<()> {
+    let card = match player.owners.get(ind) {
+        Some(Some(Owner::Me(card))) => *card,
+        _ => return Err(Error::protocol("Player is not owner of this card")),
+    };
+    let mut shares = player.drawn.remove(&ind).unwrap_or_default();
+    let encrypted_card = shares
+        .last()
+        .map_or(*player.deck.get(ind).unwrap(), |share| share.value);
+    shares.push(DecryptionShare::generate(
+        &encrypted_card,
+        player.keys.get(ind).unwrap(),
+        &mut player.rng,
+    ));
+    for o in player.players.iter_mut() {
+        o.send(&ProtocolMessage::PlayCard {
+            ind,
+            shares: shares.clone(),
+        })
+        .await?;
+    }
+    *player.owners.get_mut(ind).unwrap() = Some(Owner::Player(card));
+    Ok(())
+}
+
+pub(crate) async fn let_play_card<O: Peer>(player: &mut Player<O>, other: usize) -> Result<Card> {
+    let other_id = player.other_player_id(other);
+    let (ind, shares) = match player.players.get_mut(other).unwrap().receive().await? {
+        ProtocolMessage::PlayCard { ind, shares } => (ind, shares),
+        message => return Err(unexpected(message)),
+    };
+    match player.owners.get(ind) {
+        Some(Some(Owner::Other(owner))) if *owner == other => (),
+        _ => {
+            return Err(Error::cheating(
+                other_id,
+                "Player is not owner of this card",
+            ))
+        }
+    };
+    let mut authors = draw_order(player.players.len() + 1, other_id);
+    authors.push(other_id);
+    let decrypted_card = apply_shares(player, ind, &authors, &shares, other_id)?;
+    let card = decode_card(player, decrypted_card)?;
+    *player.owners.get_mut(ind).unwrap() =
+        Some(Owner::OtherPlayed(other, CardFromDeck { card, ind }));
+    Ok(card)
+}
+
+pub(crate) async fn reveal_from_deck<O: Peer>(player: &mut Player<O>) -> Result<Card> {
+    let ind = get_top_of_deck(player)?;
+    let mut encrypted_card = *player.deck.get(ind).unwrap();
+    for id in 0..=player.players.len() {
+        encrypted_card = if id == player.player_id {
+            let share = DecryptionShare::generate(
+                &encrypted_card,
+                player.keys.get(ind).unwrap(),
+                &mut player.rng,
+            );
+            for o in player.players.iter_mut() {
+                o.send(&ProtocolMessage::TableShare { ind, share }).await?;
+            }
+            share.value
+        } else {
+            let other = if id < player.player_id { id } else { id - 1 };
+            let o = player.players.get_mut(other).unwrap();
+            let (requested, share) = match o.receive().await? {
+                ProtocolMessage::TableShare { ind, share } => (ind, share),
+                message => return Err(unexpected(message)),
+            };
+            if ind != requested {
+                return Err(Error::cheating(id, "Other player reveals wrong card"));
+            }
+            verify_share(player, id, ind, &encrypted_card, &share)?;
+            share.value
         };
-        let decrypted_card = decrypt(
-            &decrypt(self.deck.get(ind).unwrap(), &key),
-            self.keys.get(ind).unwrap(),
+    }
+    let card = decode_card(player, encrypted_card)?;
+    *player.owners.get_mut(ind).unwrap() = Some(Owner::Table(CardFromDeck { card, ind }));
+    Ok(card)
+}
+
+pub(crate) async fn abort<O: Peer>(player: &mut Player<O>, err: &Error) {
+    send_abort(
+        &mut player.players,
+        player.player_id,
+        err,
+        &player.signer,
+        &mut player.rng,
+    )
+    .await;
+}
+
+/*
+ * Shares are not signed, a wrong share shows cheating only if the cheater made it.
+ * The player knows this just for shares it made and shares it got from their author,
+ * shares relayed by another player may be made up by the relaying player.
+ */
+pub(crate) fn check_evidence<O>(player: &Player<O>, cheater: usize, evidence: &Evidence) -> bool {
+    let Evidence::Share { ind, card, share } = evidence else {
+        return false;
+    };
+    let share: &DecryptionShare = share;
+    if *ind >= player.deck.len() || cheater > player.players.len() {
+        return false;
+    }
+    let made_by_cheater = player.recorder.entries().iter().any(|entry| match entry {
+        Entry::Sent { message, .. } if cheater == player.player_id => {
+            authored_shares(message).contains(share)
+        }
+        Entry::Received { from, message } if *from == cheater => {
+            authored_shares(message).contains(share)
+        }
+        _ => false,
+    });
+    made_by_cheater && !share.verify(card, &commitment(player, cheater, *ind))
+}
+
+// shares made by the sender of `message`, without the shares it relays
+fn authored_shares(message: &ProtocolMessage) -> Vec<DecryptionShare> {
+    match message {
+        ProtocolMessage::KeyShare(share) | ProtocolMessage::TableShare { share, .. } => {
+            vec![*share]
+        }
+        ProtocolMessage::PlayCard { shares, .. } => shares.last().copied().into_iter().collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use crypto::{
+        deck::DeckSpec, shuffle_v2::DecryptionShare, signature::SigningKey, types::KeyType,
+    };
+    use network::{
+        error::Error,
+        message::{AbortNotice, Evidence, ProtocolMessage, SignedAbort},
+    };
+    use rand::thread_rng;
+
+    use super::Moves;
+    use crate::player::{
+        test::prepare_players, DeckPreparationBasic, DeckPreparationVerification, Owner, Player,
+    };
+
+    fn prepare_two_players() -> (Player, Player) {
+        let mut players = prepare_players::<DeckPreparationBasic>(2, DeckSpec::short());
+        let second = players.pop().unwrap();
+        let first = players.pop().unwrap();
+        (first, second)
+    }
+
+    #[test]
+    fn draw_and_play() {
+        let (mut first, mut second) = prepare_two_players();
+        let handle = thread::spawn(move || {
+            second.let_draw_from_deck(0).unwrap();
+            second.let_play_card(0).unwrap()
+        });
+        let card = first.draw_from_deck().unwrap();
+        first.play_card(0).unwrap();
+        assert_eq!(handle.join().unwrap(), card);
+    }
+
+    #[test]
+    fn verified_draw_and_play() {
+        let deck = DeckSpec::new(DeckSpec::short().cards()[..8].to_vec());
+        let mut players = prepare_players::<DeckPreparationVerification>(3, deck);
+        let handles = players
+            .split_off(1)
+            .into_iter()
+            .map(|mut player| {
+                thread::spawn(move || {
+                    player.let_draw_from_deck(0).unwrap();
+                    player.let_play_card(0).unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut first = players.pop().unwrap();
+        let card = first.draw_from_deck().unwrap();
+        first.play_card(0).unwrap();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), card);
+        }
+    }
+
+    #[test]
+    fn wrong_card_requested() {
+        let (mut first, mut second) = prepare_two_players();
+        first
+            .players
+            .first_mut()
+            .unwrap()
+            .send(&ProtocolMessage::DrawRequest {
+                ind: 5,
+                shares: vec![],
+            })
+            .unwrap();
+        assert!(matches!(
+            second.let_draw_from_deck(0),
+            Err(Error::Cheating { player: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn forged_draw_request() {
+        let mut players = prepare_players::<DeckPreparationBasic>(3, DeckSpec::short());
+        let mut third = players.pop().unwrap();
+        let mut first = players.remove(0);
+        // share of player 1 is replaced by a share of another card
+        let share = DecryptionShare::generate(
+            first.deck.get(5).unwrap(),
+            first.keys.get(5).unwrap(),
+            &mut thread_rng(),
         );
-        let card = self
-            .translator
-            .translate(decrypted_card)
-            .expect("Other player did not provide right key");
-        Card::try_from(card).unwrap()
+        first
+            .players
+            .get_mut(1)
+            .unwrap()
+            .send(&ProtocolMessage::DrawRequest {
+                ind: 0,
+                shares: vec![share],
+            })
+            .unwrap();
+        assert!(matches!(
+            third.let_draw_from_deck(0),
+            Err(Error::Cheating { player: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn playing_not_owned_card() {
+        let (mut first, mut second) = prepare_two_players();
+        let share = DecryptionShare::generate(
+            first.deck.first().unwrap(),
+            first.keys.first().unwrap(),
+            &mut thread_rng(),
+        );
+        first
+            .players
+            .first_mut()
+            .unwrap()
+            .send(&ProtocolMessage::PlayCard {
+                ind: 0,
+                shares: vec![share],
+            })
+            .unwrap();
+        assert!(matches!(
+            second.let_play_card(0),
+            Err(Error::Cheating { player: 0, .. })
+        ));
+        assert!(matches!(
+            second.play_card(0),
+            Err(Error::ProtocolViolation(_))
+        ));
+    }
+
+    #[test]
+    fn wrong_share_on_draw() {
+        let (mut first, mut second) = prepare_two_players();
+        let handle = thread::spawn(move || {
+            let other = second.players.first_mut().unwrap();
+            let card = match other.receive().unwrap() {
+                ProtocolMessage::DrawRequest { ind, .. } => *second.deck.get(ind).unwrap(),
+                message => panic!("Unexpected message {}", message.name()),
+            };
+            let other = second.players.first_mut().unwrap();
+            let share = DecryptionShare::generate(&card, &KeyType::from(7), &mut thread_rng());
+            other.send(&ProtocolMessage::KeyShare(share)).unwrap();
+        });
+        assert!(matches!(
+            first.draw_from_deck(),
+            Err(Error::Cheating { player: 1, .. })
+        ));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn unexpected_message() {
+        let (mut first, mut second) = prepare_two_players();
+        let other = first.players.first_mut().unwrap();
+        other
+            .send(&ProtocolMessage::RevealKeys(first.keys.clone()))
+            .unwrap();
+        assert!(matches!(
+            second.let_draw_from_deck(0),
+            Err(Error::ProtocolViolation(_))
+        ));
+        let notice = AbortNotice {
+            player_id: 0,
+            reason: "leaving".to_string(),
+            cheater: None,
+            evidence: None,
+        };
+        let abort = SignedAbort::sign(notice.clone(), &first.signer, &mut thread_rng());
+        other
+            .send(&ProtocolMessage::Abort(Box::new(abort)))
+            .unwrap();
+        assert!(matches!(second.let_play_card(0), Err(Error::Aborted(_))));
+        // signed by a key other than the pinned one
+        let forged = SignedAbort::sign(
+            notice.clone(),
+            &SigningKey::generate(&mut thread_rng()),
+            &mut thread_rng(),
+        );
+        other
+            .send(&ProtocolMessage::Abort(Box::new(forged)))
+            .unwrap();
+        assert!(matches!(
+            second.let_play_card(0),
+            Err(Error::ProtocolViolation(_))
+        ));
+        // properly signed, but on behalf of another player
+        let impersonated = AbortNotice {
+            player_id: 1,
+            ..notice
+        };
+        let impersonated = SignedAbort::sign(impersonated, &first.signer, &mut thread_rng());
+        other
+            .send(&ProtocolMessage::Abort(Box::new(impersonated)))
+            .unwrap();
+        assert!(matches!(
+            second.let_play_card(0),
+            Err(Error::ProtocolViolation(_))
+        ));
+    }
+
+    #[test]
+    fn abort_with_evidence() {
+        let (mut first, mut second) = prepare_two_players();
+        let handle = thread::spawn(move || {
+            let other = second.players.first_mut().unwrap();
+            let card = match other.receive().unwrap() {
+                ProtocolMessage::DrawRequest { ind, .. } => *second.deck.get(ind).unwrap(),
+                message => panic!("Unexpected message {}", message.name()),
+            };
+            let other = second.players.first_mut().unwrap();
+            let share = DecryptionShare::generate(&card, &KeyType::from(7), &mut thread_rng());
+            other.send(&ProtocolMessage::KeyShare(share)).unwrap();
+            let result = second.let_play_card(0);
+            (second, result)
+        });
+        let err = first.draw_from_deck().unwrap_err();
+        first.abort(&err);
+        let (second, result) = handle.join().unwrap();
+        let notice = match result {
+            Err(Error::Aborted(notice)) => notice,
+            result => panic!("Unexpected result {:?}", result),
+        };
+        assert_eq!(notice.player_id, 0);
+        assert_eq!(notice.cheater, Some(1));
+        let evidence = notice.evidence.unwrap();
+        match &evidence {
+            Evidence::Share { ind, card, share } => {
+                let commitment = first.commitments.first().unwrap().get(*ind).unwrap();
+                assert!(!share.verify(card, commitment));
+            }
+            evidence => panic!("Unexpected evidence {:?}", evidence),
+        }
+        // both the author and the recipient know the share came from the cheater
+        assert!(second.check_evidence(1, &evidence));
+        assert!(first.check_evidence(1, &evidence));
+        // wrong share the accused player never sent proves nothing
+        let card = *second.deck.get(1).unwrap();
+        let made_up = Evidence::Share {
+            ind: 1,
+            card,
+            share: Box::new(DecryptionShare::generate(
+                &card,
+                &KeyType::from(7),
+                &mut thread_rng(),
+            )),
+        };
+        assert!(!second.check_evidence(1, &made_up));
+        assert!(!second.check_evidence(0, &evidence));
+    }
+
+    #[test]
+    fn heartbeats_are_skipped() {
+        let (mut first, mut second) = prepare_two_players();
+        let handle = thread::spawn(move || second.let_draw_from_deck(0).unwrap());
+        let other = first.players.first_mut().unwrap();
+        other.send(&ProtocolMessage::Heartbeat).unwrap();
+        other.send(&ProtocolMessage::Heartbeat).unwrap();
+        first.draw_from_deck().unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn reveal_from_deck() {
+        let players = prepare_players::<DeckPreparationBasic>(3, DeckSpec::short());
+        let handles = players
+            .into_iter()
+            .map(|mut player| {
+                thread::spawn(move || {
+                    let card = player.reveal_from_deck().unwrap();
+                    assert!(matches!(
+                        player.owners.first().unwrap(),
+                        Some(Owner::Table(revealed)) if revealed.card == card
+                    ));
+                    card
+                })
+            })
+            .collect::<Vec<_>>();
+        let cards = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>();
+        assert!(cards.iter().all(|card| card == cards.first().unwrap()));
     }
 }
@@ -1,256 +1,755 @@
 use common::game::CardFromDeck;
 use common::game::ShowHandDeck;
-use crypto::encryption::basic_deck;
+use crypto::deck::DeckSpec;
 use crypto::encryption::mul_key;
 use crypto::encryption::Translator;
 use crypto::shuffle::PartyBasic;
+use crypto::shuffle_v2::key_commitment;
+use crypto::shuffle_v2::DecryptionShare;
 use crypto::shuffle_v2::EncryptWithProof;
+use crypto::shuffle_v2::ProofStep;
+use crypto::shuffle_v2::ShuffleProof;
 use crypto::shuffle_v2::ShuffleWithProof;
+use crypto::signature::{SigningKey, VerifyingKey};
 use crypto::types::EncryptedValue;
 use crypto::types::KeyType;
 
-use network::connection::Connection;
-use network::connection::TcpConnection;
+use network::error::Error;
+use network::error::Result;
+use network::message::unexpected;
+use network::message::AbortNotice;
+use network::message::Evidence;
+use network::message::MessageConnection;
+use network::message::ProtocolMessage;
+use network::message::ShuffleChain;
+use network::message::ShuffleMessage;
+use network::message::SignedAbort;
+use network::session::SessionState;
+
+use crate::transcript::{Entry, Recorder};
+
+use std::collections::HashMap;
+use std::future::{ready, Future};
+use std::iter::zip;
+use std::marker::PhantomData;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
 
 use rand::prelude::SliceRandom;
-use rand::thread_rng;
+use rand::rngs::StdRng;
+use rand::CryptoRng;
+use rand::Rng;
+use rand::RngCore;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+/*
+ * Protocol steps are written once as async functions over `Peer`,
+ * so blocking and async players share them. Blocking connections finish
+ * sending and receiving before their futures are created, so `run` completes
+ * a step of a blocking player with a single poll.
+ */
+
+/// Connection to another player, blocking `OtherPlayer` or `AsyncOtherPlayer`.
+pub trait Peer {
+    fn send(&mut self, message: &ProtocolMessage) -> impl Future<Output = Result<()>> + Send;
+    /// Skips heartbeats, abort of the other player is returned as an error.
+    fn receive(&mut self) -> impl Future<Output = Result<ProtocolMessage>> + Send;
+    /// Records all further messages with the player `other_id`.
+    fn record(&mut self, other_id: usize, recorder: Recorder);
+}
+
+/// Runs a protocol step over blocking connections.
+pub(crate) fn run<F: Future>(step: F) -> F::Output {
+    match pin!(step).poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("Blocking connection did not finish"),
+    }
+}
 
 pub struct OtherPlayer {
-    connection: TcpConnection,
+    connection: Box<dyn MessageConnection>,
+    /// Id of the other player and transcript of the player, once recording started.
+    recording: Option<(usize, Recorder)>,
+    /// Long-term key of the other player, aborts signed by it may accuse somebody.
+    peer_key: Option<VerifyingKey>,
 }
 
 impl OtherPlayer {
-    pub fn new(conn: TcpConnection) -> Self {
-        OtherPlayer { connection: conn }
+    pub fn new<C: MessageConnection + 'static>(conn: C) -> Self {
+        OtherPlayer {
+            connection: Box::new(conn),
+            recording: None,
+            peer_key: None,
+        }
+    }
+
+    /// Pins the long-term key of the other player.
+    pub fn with_peer_key(mut self, key: VerifyingKey) -> Self {
+        self.peer_key = Some(key);
+        self
+    }
+
+    pub fn send(&mut self, message: &ProtocolMessage) -> Result<()> {
+        self.connection.send_message(message)?;
+        if let Some((to, recorder)) = &self.recording {
+            recorder.record(Entry::Sent {
+                to: *to,
+                message: message.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Skips heartbeats, they only keep the read timeout from expiring.
+    /// Abort of the other player ends the game with an error.
+    pub fn receive(&mut self) -> Result<ProtocolMessage> {
+        let message = loop {
+            match self.connection.receive_message()? {
+                ProtocolMessage::Heartbeat => continue,
+                message => break message,
+            }
+        };
+        if let Some((from, recorder)) = &self.recording {
+            recorder.record(Entry::Received {
+                from: *from,
+                message: message.clone(),
+            });
+        }
+        match message {
+            ProtocolMessage::Abort(abort) => Err(match &self.recording {
+                Some((from, _)) => abort.into_error(*from, self.peer_key.as_ref()),
+                None => Error::protocol("Abort before the game started"),
+            }),
+            message => Ok(message),
+        }
+    }
+
+    /// State of the connection to store in a checkpoint, if it can be resumed.
+    pub fn checkpoint(&mut self) -> Option<SessionState> {
+        self.connection.checkpoint()
     }
 }
 
-impl Connection for OtherPlayer {
-    fn send<T: serde::Serialize>(&mut self, message: &T) {
-        self.connection.send(message)
+impl Peer for OtherPlayer {
+    fn send(&mut self, message: &ProtocolMessage) -> impl Future<Output = Result<()>> + Send {
+        ready(OtherPlayer::send(self, message))
+    }
+    fn receive(&mut self) -> impl Future<Output = Result<ProtocolMessage>> + Send {
+        ready(OtherPlayer::receive(self))
     }
-    fn receive<T: serde::de::DeserializeOwned>(&mut self) -> T {
-        self.connection.receive()
+    fn record(&mut self, other_id: usize, recorder: Recorder) {
+        self.recording = Some((other_id, recorder));
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+// every player records its own transcript from the start of the preparation
+fn start_recording<O: Peer>(players: &mut [O], player_id: usize) -> Recorder {
+    let recorder = Recorder::default();
+    for (ind, o) in players.iter_mut().enumerate() {
+        o.record(other_player_id(player_id, ind), recorder.clone());
+    }
+    recorder
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Owner {
     Me(CardFromDeck),
     Other(usize),
     Player(CardFromDeck),
+    OtherPlayed(usize, CardFromDeck),
+    Table(CardFromDeck),
 }
 
-pub struct Player {
+/// `O` is the connection to other players, blocking by default.
+pub struct Player<O = OtherPlayer> {
     pub deck: Vec<EncryptedValue>,
     pub keys: Vec<KeyType>,
-    pub players: Vec<OtherPlayer>,
+    pub players: Vec<O>,
     pub owners: Vec<Option<Owner>>,
     pub name: String,
     pub translator: Translator,
+    pub player_id: usize,
+    pub start_deck: Vec<EncryptedValue>,
+    /// `commitments[other][ind]` is the commitment to the key of `players[other]` for card `ind`.
+    pub commitments: Vec<Vec<EncryptedValue>>,
+    /// Randomness for proofs during the game, derived from the preparation rng.
+    pub rng: GameRng,
+    pub spec: DeckSpec,
+    pub recorder: Recorder,
+    /// Signs aborts and the transcript of the player.
+    pub signer: SigningKey,
+    /// Shares of other players which decrypted card `ind` drawn by the player,
+    /// they are sent again when the card is played.
+    pub drawn: HashMap<usize, Vec<DecryptionShare>>,
+}
+
+/// Randomness of a player, a game seeded with the same values is reproduced exactly.
+pub type GameRng = StdRng;
+
+/// Fresh randomness, or deterministic randomness from a recorded `seed` for debugging.
+pub fn game_rng(seed: Option<u64>) -> GameRng {
+    match seed {
+        Some(seed) => GameRng::seed_from_u64(seed),
+        None => GameRng::from_entropy(),
+    }
 }
 
+pub(crate) fn other_player_id(player_id: usize, ind: usize) -> usize {
+    if ind < player_id {
+        ind
+    } else {
+        ind + 1
+    }
+}
+
+impl<O> Player<O> {
+    /// `recorder` is the transcript `players` record to since the preparation started.
+    pub(crate) fn new(
+        name: String,
+        signer: SigningKey,
+        player_id: usize,
+        (players, recorder): (Vec<O>, Recorder),
+        spec: &DeckSpec,
+        (deck, keys, commitments): PreparedDeck,
+        rng: GameRng,
+    ) -> Self {
+        let len = deck.len();
+        Player {
+            deck,
+            keys,
+            commitments,
+            players,
+            owners: vec![None; len],
+            name,
+            translator: spec.translator(),
+            player_id,
+            start_deck: spec.deck(),
+            rng,
+            spec: spec.clone(),
+            recorder,
+            signer,
+            drawn: HashMap::new(),
+        }
+    }
+
+    /// Id of the player connected by `players[ind]`.
+    pub fn other_player_id(&self, ind: usize) -> usize {
+        other_player_id(self.player_id, ind)
+    }
+}
+
+// sending is best effort, the game ends anyway
+pub(crate) async fn send_abort<O: Peer, R: RngCore + CryptoRng>(
+    players: &mut [O],
+    player_id: usize,
+    err: &Error,
+    signer: &SigningKey,
+    rng: &mut R,
+) {
+    if let Some(notice) = AbortNotice::for_error(player_id, err) {
+        let message = ProtocolMessage::Abort(Box::new(SignedAbort::sign(notice, signer, rng)));
+        for o in players.iter_mut() {
+            let _ = o.send(&message).await;
+        }
+    }
+}
+
+async fn abort_preparation<O: Peer, R: RngCore + CryptoRng>(
+    players: &mut [O],
+    player_id: usize,
+    err: Error,
+    signer: &SigningKey,
+    rng: &mut R,
+) -> Error {
+    send_abort(players, player_id, &err, signer, rng).await;
+    err
+}
+
+/// `others` are connections to all other players ordered by their ids,
+/// player with id 0 starts the preparation.
+/// `signer` is the long-term key of the player, other players pin its public part.
 pub trait DeckPreparation {
-    fn prepare(
+    fn prepare<R: RngCore + CryptoRng>(
         name: String,
+        signer: SigningKey,
         others: Vec<OtherPlayer>,
-        start: bool,
-        deck: Vec<EncryptedValue>,
-    ) -> Player;
+        player_id: usize,
+        deck: DeckSpec,
+        rng: &mut R,
+    ) -> Result<Player>;
+}
+
+// preparation passes the deck around the ring of players ordered by ids
+fn ring_neighbour<O>(players: &mut [O], player_id: usize, shift: usize) -> &mut O {
+    let num_players = players.len() + 1;
+    let other_id = (player_id + shift) % num_players;
+    let ind = if other_id < player_id {
+        other_id
+    } else {
+        other_id - 1
+    };
+    players.get_mut(ind).unwrap()
+}
+
+fn prev_player_id<O>(players: &[O], player_id: usize) -> usize {
+    let num_players = players.len() + 1;
+    (player_id + num_players - 1) % num_players
+}
+
+pub(crate) fn prev_player<O>(players: &mut [O], player_id: usize) -> &mut O {
+    let num_players = players.len() + 1;
+    ring_neighbour(players, player_id, num_players - 1)
 }
 
-pub struct DeckPreparationBasic {
-    players: Vec<OtherPlayer>,
+pub(crate) fn next_player<O>(players: &mut [O], player_id: usize) -> &mut O {
+    ring_neighbour(players, player_id, 1)
+}
+
+/// `O` is the connection to other players, blocking by default.
+pub struct DeckPreparationBasic<O = OtherPlayer> {
+    players: Vec<O>,
+    player_id: usize,
     name: String,
 }
 
 impl DeckPreparation for DeckPreparationBasic {
-    fn prepare(
+    fn prepare<R: RngCore + CryptoRng>(
         name: String,
+        signer: SigningKey,
         others: Vec<OtherPlayer>,
-        start: bool,
-        deck: Vec<EncryptedValue>,
-    ) -> Player {
+        player_id: usize,
+        deck: DeckSpec,
+        rng: &mut R,
+    ) -> Result<Player> {
+        run(Self::prepare_player(
+            name, signer, others, player_id, deck, rng,
+        ))
+    }
+}
+
+impl<O: Peer> DeckPreparationBasic<O> {
+    pub(crate) async fn prepare_player<R: RngCore + CryptoRng>(
+        name: String,
+        signer: SigningKey,
+        mut others: Vec<O>,
+        player_id: usize,
+        deck: DeckSpec,
+        rng: &mut R,
+    ) -> Result<Player<O>> {
+        let recorder = start_recording(&mut others, player_id);
         let mut preparation = DeckPreparationBasic {
             players: others,
+            player_id,
             name,
         };
-        let (deck, keys) = if start {
-            preparation.prepare_deck_start(deck)
+        let prepared = match preparation.prepare_deck(&deck, rng).await {
+            Ok(prepared) => prepared,
+            Err(err) => {
+                let players = &mut preparation.players;
+                return Err(abort_preparation(players, player_id, err, &signer, rng).await);
+            }
+        };
+        Ok(Player::new(
+            preparation.name,
+            signer,
+            player_id,
+            (preparation.players, recorder),
+            &deck,
+            prepared,
+            GameRng::from_rng(rng).unwrap(),
+        ))
+    }
+    async fn prepare_deck<R: RngCore + CryptoRng>(
+        &mut self,
+        deck: &DeckSpec,
+        rng: &mut R,
+    ) -> Result<PreparedDeck> {
+        let (encoded, keys) = if self.player_id == 0 {
+            self.prepare_deck_start(deck.deck(), rng).await?
         } else {
-            preparation.prepare_deck_join()
+            self.prepare_deck_join(rng).await?
         };
-        let len = deck.len();
-        Player {
-            deck,
-            keys,
-            players: preparation.players,
-            owners: vec![None; len],
-            name: preparation.name,
-            translator: Translator::new(&basic_deck()),
-        }
+        let commitments = exchange_commitments(&mut self.players, self.player_id, &keys).await?;
+        Ok((encoded, keys, commitments))
     }
-}
-
-impl DeckPreparationBasic {
-    fn prepare_deck_start(
+    async fn prepare_deck_start<R: RngCore + CryptoRng>(
         &mut self,
         mut deck: Vec<EncryptedValue>,
-    ) -> (Vec<EncryptedValue>, Vec<KeyType>) {
+        rng: &mut R,
+    ) -> Result<(Vec<EncryptedValue>, Vec<KeyType>)> {
         let mut party = PartyBasic::new();
         let mut start = true;
         while !party.is_done() {
             if !start {
-                deck = self.get_deck();
+                deck = self.get_deck().await?;
             }
             start = false;
-            party.make_turn(&mut deck);
-            self.send_deck(&deck);
+            party.make_turn(&mut deck, rng);
+            self.send_deck(&deck).await?;
         }
-        self.get_deck(); // wait for the rest
-        party.retrieve_deck()
+        self.get_deck().await?; // wait for the rest
+        Ok(party.retrieve_deck())
     }
-    fn prepare_deck_join(&mut self) -> (Vec<EncryptedValue>, Vec<KeyType>) {
+    async fn prepare_deck_join<R: RngCore + CryptoRng>(
+        &mut self,
+        rng: &mut R,
+    ) -> Result<(Vec<EncryptedValue>, Vec<KeyType>)> {
         let mut party = PartyBasic::new();
         while !party.is_done() {
-            let mut deck = self.get_deck();
-            party.make_turn(&mut deck);
-            self.send_deck(&deck);
+            let mut deck = self.get_deck().await?;
+            party.make_turn(&mut deck, rng);
+            self.send_deck(&deck).await?;
         }
-        party.retrieve_deck()
+        Ok(party.retrieve_deck())
     }
-    fn get_deck(&mut self) -> Vec<EncryptedValue> {
-        self.players
-            .first_mut()
-            .unwrap()
-            .connection
-            .receive::<Vec<_>>()
+    async fn get_deck(&mut self) -> Result<Vec<EncryptedValue>> {
+        match prev_player(&mut self.players, self.player_id)
+            .receive()
+            .await?
+        {
+            ProtocolMessage::Deck(deck) => Ok(deck),
+            message => Err(unexpected(message)),
+        }
     }
-    fn send_deck(&mut self, deck: &Vec<EncryptedValue>) {
-        self.players.last_mut().unwrap().send(&deck);
+    async fn send_deck(&mut self, deck: &[EncryptedValue]) -> Result<()> {
+        next_player(&mut self.players, self.player_id)
+            .send(&ProtocolMessage::Deck(deck.to_vec()))
+            .await
     }
 }
 
-pub struct DeckPreparationVerification {
-    players: Vec<OtherPlayer>,
+// without verification commitments to keys are just exchanged after the preparation
+async fn exchange_commitments<O: Peer>(
+    players: &mut [O],
+    player_id: usize,
+    keys: &[KeyType],
+) -> Result<Vec<Vec<EncryptedValue>>> {
+    let commitments = keys.iter().map(key_commitment).collect::<Vec<_>>();
+    for o in players.iter_mut() {
+        o.send(&ProtocolMessage::KeyCommitments(commitments.clone()))
+            .await?;
+    }
+    let mut others_commitments = vec![];
+    for (ind, o) in players.iter_mut().enumerate() {
+        let received = match o.receive().await? {
+            ProtocolMessage::KeyCommitments(received) => received,
+            message => return Err(unexpected(message)),
+        };
+        if received.len() != keys.len() {
+            return Err(Error::cheating(
+                other_player_id(player_id, ind),
+                "Wrong number of commitments",
+            ));
+        }
+        others_commitments.push(received);
+    }
+    Ok(others_commitments)
+}
+
+// deck, own keys and commitments to keys of other players
+pub(crate) type PreparedDeck = (Vec<EncryptedValue>, Vec<KeyType>, Vec<Vec<EncryptedValue>>);
+
+/// Verified preparation, generic over the proof used for shuffles
+/// and the connection to other players.
+pub struct DeckPreparationVerification<S = ShuffleWithProof, O = OtherPlayer> {
+    players: Vec<O>,
+    player_id: usize,
     name: String,
+    shuffle: PhantomData<S>,
 }
 
-impl DeckPreparation for DeckPreparationVerification {
-    fn prepare(
+impl<S: ShuffleMessage> DeckPreparation for DeckPreparationVerification<S> {
+    fn prepare<R: RngCore + CryptoRng>(
         name: String,
+        signer: SigningKey,
         others: Vec<OtherPlayer>,
-        start: bool,
-        deck: Vec<EncryptedValue>,
-    ) -> Player {
-        let mut preparation = DeckPreparationVerification {
+        player_id: usize,
+        deck: DeckSpec,
+        rng: &mut R,
+    ) -> Result<Player> {
+        run(Self::prepare_player(
+            name, signer, others, player_id, deck, rng,
+        ))
+    }
+}
+
+/*
+ * Verified preparation works on a ring of players ordered by ids,
+ * every party receives from its predecessor and sends to its successor.
+ * 1. Shuffles go around the ring, every party verifies the whole chain before adding its own.
+ * 2. Starting party sends the complete shuffle chain together with encryptions around the ring,
+ *    so everybody can verify shuffles of its successors and encryptions of its predecessors.
+ * 3. Starting party sends the complete encryption chain to everybody who has not seen it yet.
+ * Every party ends up with the same deck and its per-card keys combined with its shuffle key.
+ */
+
+const VERIFICATION_FAILED: &str = "Verification of other player failed";
+
+// `steps[i]` was made by the player with id `first_id + i`
+pub(crate) fn verify_steps<T: ProofStep + Serialize>(
+    deck: &[EncryptedValue],
+    steps: &[T],
+    first_id: usize,
+) -> Result<()> {
+    let mut prev = deck;
+    for (i, step) in steps.iter().enumerate() {
+        if !step.verify_step(prev) {
+            let evidence = Evidence::Proof {
+                step: first_id + i,
+                proof: serde_json::to_value(step).unwrap_or_default(),
+            };
+            return Err(Error::cheating_with(
+                first_id + i,
+                VERIFICATION_FAILED,
+                evidence,
+            ));
+        }
+        prev = step.result();
+    }
+    Ok(())
+}
+
+// checks that complete chain of `num_players` steps received from `sender`
+// contains our own step at `ind` and verifies all steps after it
+fn verify_successors<T: ProofStep + Serialize>(
+    own: &[EncryptedValue],
+    steps: &[T],
+    ind: usize,
+    num_players: usize,
+    sender: usize,
+) -> Result<()> {
+    match steps.get(ind) {
+        Some(step) if step.result() == own && steps.len() == num_players => {
+            verify_steps(own, steps.get(ind + 1..).unwrap(), ind + 1)
+        }
+        _ => Err(Error::cheating(sender, "Player tampered with proofs")),
+    }
+}
+
+// key commitments of all other players, ordered by ids
+pub(crate) fn collect_commitments<S: ShuffleProof>(
+    shuffle_proofs: &[S],
+    encrypt_proofs: &[EncryptWithProof],
+    player_id: usize,
+) -> Result<Vec<Vec<EncryptedValue>>> {
+    let mut commitments = vec![];
+    for (other_id, (shuffle, encrypt)) in zip(shuffle_proofs, encrypt_proofs).enumerate() {
+        if *shuffle.public_key() != encrypt.public_key {
+            return Err(Error::cheating(other_id, VERIFICATION_FAILED));
+        }
+        if other_id != player_id {
+            commitments.push(encrypt.key_commitments.clone());
+        }
+    }
+    Ok(commitments)
+}
+
+fn shuffles_from_chain<S: ShuffleMessage>(chain: ShuffleChain) -> Result<Vec<S>> {
+    S::from_chain(chain).ok_or(Error::protocol("Wrong kind of shuffle proofs"))
+}
+
+fn random_permutation<R: Rng>(n: usize, rng: &mut R) -> Vec<usize> {
+    let mut perm = (0..n).collect::<Vec<_>>();
+    perm.shuffle(rng);
+    perm
+}
+
+impl<S: ShuffleMessage, O: Peer> DeckPreparationVerification<S, O> {
+    pub(crate) async fn prepare_player<R: RngCore + CryptoRng>(
+        name: String,
+        signer: SigningKey,
+        mut others: Vec<O>,
+        player_id: usize,
+        deck: DeckSpec,
+        rng: &mut R,
+    ) -> Result<Player<O>> {
+        let recorder = start_recording(&mut others, player_id);
+        let mut preparation = DeckPreparationVerification::<S, O> {
             players: others,
+            player_id,
             name,
+            shuffle: PhantomData,
         };
-        let (deck, keys) = if start {
-            preparation.prepare_deck_start(deck)
+        let prepared = if player_id == 0 {
+            preparation.prepare_deck_start(deck.deck(), rng).await
         } else {
-            preparation.prepare_deck_join(&deck)
+            preparation.prepare_deck_join(&deck.deck(), rng).await
         };
-        let len = deck.len();
-        Player {
-            deck,
-            keys,
-            players: preparation.players,
-            owners: vec![None; len],
-            name: preparation.name,
-            translator: Translator::new(&basic_deck()),
-        }
+        let prepared = match prepared {
+            Ok(prepared) => prepared,
+            Err(err) => {
+                let players = &mut preparation.players;
+                return Err(abort_preparation(players, player_id, err, &signer, rng).await);
+            }
+        };
+        Ok(Player::new(
+            preparation.name,
+            signer,
+            player_id,
+            (preparation.players, recorder),
+            &deck,
+            prepared,
+            GameRng::from_rng(rng).unwrap(),
+        ))
     }
-}
-
-impl DeckPreparationVerification {
-    fn prepare_deck_start(
+    async fn prepare_deck_start<R: RngCore + CryptoRng>(
         &mut self,
         deck: Vec<EncryptedValue>,
-    ) -> (Vec<EncryptedValue>, Vec<KeyType>) {
+        rng: &mut R,
+    ) -> Result<PreparedDeck> {
         let n = deck.len();
-        let mut rng = thread_rng();
-        let p_key = KeyType::rand(&mut rng);
-        let mut perm = vec![0; n];
-        for i in 0..n {
-            *perm.get_mut(i).unwrap() = i;
-        }
-        perm.shuffle(&mut rng);
-        let shuffle_proof = ShuffleWithProof::generate(deck, &p_key, &perm, &mut rng);
-        self.players.get_mut(0).unwrap().send(&shuffle_proof);
-        let other_shuffle_proof = self
-            .players
-            .get_mut(0)
-            .unwrap()
-            .receive::<ShuffleWithProof>();
-        assert!(
-            other_shuffle_proof.verify(&shuffle_proof.values_aftr),
-            "Verification of other player failed"
-        );
-        let mut keys = (0..n).map(|_| KeyType::rand(&mut rng)).collect();
-        let encrypt_proof =
-            EncryptWithProof::generate(other_shuffle_proof.values_aftr, &keys, &mut rng);
-        self.players.get_mut(0).unwrap().send(&encrypt_proof);
-        let other_encrypt_proof = self
-            .players
-            .get_mut(0)
-            .unwrap()
-            .receive::<EncryptWithProof>();
-        assert!(
-            other_encrypt_proof.verify(&encrypt_proof.values_aftr),
-            "Verification of other player failed"
+        let num_players = self.players.len() + 1;
+        let prev_id = prev_player_id(&self.players, self.player_id);
+        let p_key = KeyType::rand(rng);
+        let perm = random_permutation(n, rng);
+        let shuffle_proof = S::generate(deck, &p_key, &perm, rng);
+        self.send_to_next(ProtocolMessage::Shuffles(S::into_chain(vec![
+            shuffle_proof.clone(),
+        ])))
+        .await?;
+
+        let shuffle_proofs = self.receive_shuffles().await?;
+        verify_successors(
+            shuffle_proof.result(),
+            &shuffle_proofs,
+            0,
+            num_players,
+            prev_id,
+        )?;
+        let mut keys = (0..n).map(|_| KeyType::rand(rng)).collect();
+        let encrypt_proof = EncryptWithProof::generate(
+            shuffle_proofs.last().unwrap().result().to_vec(),
+            &keys,
+            shuffle_proof.public_key(),
+            rng,
         );
+        self.send_to_next(ProtocolMessage::ShufflesAndEncryptions(
+            S::into_chain(shuffle_proofs.clone()),
+            vec![encrypt_proof.clone()],
+        ))
+        .await?;
+
+        let (_, encrypt_proofs) = self.receive_shuffles_and_encryptions().await?;
+        verify_successors(
+            &encrypt_proof.values_aftr,
+            &encrypt_proofs,
+            0,
+            num_players,
+            prev_id,
+        )?;
+        if num_players > 2 {
+            self.send_to_next(ProtocolMessage::Encryptions(encrypt_proofs.clone()))
+                .await?;
+        }
+        let commitments = collect_commitments(&shuffle_proofs, &encrypt_proofs, self.player_id)?;
         keys.iter_mut().for_each(|k| *k = mul_key(k, &p_key));
-        (other_encrypt_proof.values_aftr, keys)
+        Ok((
+            encrypt_proofs.last().unwrap().values_aftr.clone(),
+            keys,
+            commitments,
+        ))
     }
-    fn prepare_deck_join(
+    async fn prepare_deck_join<R: RngCore + CryptoRng>(
         &mut self,
-        deck: &Vec<EncryptedValue>,
-    ) -> (Vec<EncryptedValue>, Vec<KeyType>) {
-        let other_shuffle_proof = self
-            .players
-            .get_mut(0)
-            .unwrap()
-            .receive::<ShuffleWithProof>();
-        assert!(
-            other_shuffle_proof.verify(deck),
-            "Verification of other player failed"
-        );
-        let n = deck.len();
-        let mut rng = thread_rng();
-        let p_key = KeyType::rand(&mut rng);
-        let mut perm = vec![0; n];
-        for i in 0..n {
-            *perm.get_mut(i).unwrap() = i;
+        deck: &[EncryptedValue],
+        rng: &mut R,
+    ) -> Result<PreparedDeck> {
+        let ind = self.player_id;
+        let num_players = self.players.len() + 1;
+        let prev_id = prev_player_id(&self.players, self.player_id);
+        let mut shuffle_proofs = self.receive_shuffles().await?;
+        if shuffle_proofs.len() != ind {
+            return Err(Error::cheating(prev_id, "Player tampered with proofs"));
         }
-        perm.shuffle(&mut rng);
-        let shuffle_proof = ShuffleWithProof::generate(
-            other_shuffle_proof.values_aftr.clone(),
+        verify_steps(deck, &shuffle_proofs, 0)?;
+        let n = deck.len();
+        let p_key = KeyType::rand(rng);
+        let perm = random_permutation(n, rng);
+        let shuffle_proof = S::generate(
+            shuffle_proofs.last().unwrap().result().to_vec(),
             &p_key,
             &perm,
-            &mut rng,
+            rng,
         );
-        self.players.get_mut(0).unwrap().send(&shuffle_proof);
-        let other_encrypt_proof = self
-            .players
-            .get_mut(0)
-            .unwrap()
-            .receive::<EncryptWithProof>();
-        assert!(
-            other_encrypt_proof.verify(&shuffle_proof.values_aftr),
-            "Verification of other player failed"
+        let shuffled = shuffle_proof.result().to_vec();
+        let public_key = *shuffle_proof.public_key();
+        shuffle_proofs.push(shuffle_proof);
+        self.send_to_next(ProtocolMessage::Shuffles(S::into_chain(shuffle_proofs)))
+            .await?;
+
+        let (shuffle_proofs, mut encrypt_proofs) = self.receive_shuffles_and_encryptions().await?;
+        verify_successors(&shuffled, &shuffle_proofs, ind, num_players, prev_id)?;
+        if encrypt_proofs.len() != ind {
+            return Err(Error::cheating(prev_id, "Player tampered with proofs"));
+        }
+        verify_steps(shuffle_proofs.last().unwrap().result(), &encrypt_proofs, 0)?;
+        let mut keys = (0..n).map(|_| KeyType::rand(rng)).collect();
+        let encrypt_proof = EncryptWithProof::generate(
+            encrypt_proofs.last().unwrap().values_aftr.clone(),
+            &keys,
+            &public_key,
+            rng,
         );
-        let mut keys = (0..n).map(|_| KeyType::rand(&mut rng)).collect();
-        let encrypt_proof =
-            EncryptWithProof::generate(other_encrypt_proof.values_aftr.clone(), &keys, &mut rng);
-        self.players.get_mut(0).unwrap().send(&encrypt_proof);
+        let encrypted = encrypt_proof.values_aftr.clone();
+        encrypt_proofs.push(encrypt_proof);
+        self.send_to_next(ProtocolMessage::ShufflesAndEncryptions(
+            S::into_chain(shuffle_proofs.clone()),
+            encrypt_proofs.clone(),
+        ))
+        .await?;
+
+        if ind + 1 < num_players {
+            encrypt_proofs = self.receive_encryptions().await?;
+            verify_successors(&encrypted, &encrypt_proofs, ind, num_players, prev_id)?;
+            if ind + 2 < num_players {
+                self.send_to_next(ProtocolMessage::Encryptions(encrypt_proofs.clone()))
+                    .await?;
+            }
+        }
+        let commitments = collect_commitments(&shuffle_proofs, &encrypt_proofs, self.player_id)?;
         keys.iter_mut().for_each(|k| *k = mul_key(k, &p_key));
-        (encrypt_proof.values_aftr, keys)
+        Ok((
+            encrypt_proofs.last().unwrap().values_aftr.clone(),
+            keys,
+            commitments,
+        ))
+    }
+    async fn receive_from_prev(&mut self) -> Result<ProtocolMessage> {
+        prev_player(&mut self.players, self.player_id)
+            .receive()
+            .await
+    }
+    async fn send_to_next(&mut self, message: ProtocolMessage) -> Result<()> {
+        next_player(&mut self.players, self.player_id)
+            .send(&message)
+            .await
+    }
+    async fn receive_shuffles(&mut self) -> Result<Vec<S>> {
+        match self.receive_from_prev().await? {
+            ProtocolMessage::Shuffles(chain) => shuffles_from_chain(chain),
+            message => Err(unexpected(message)),
+        }
+    }
+    async fn receive_shuffles_and_encryptions(
+        &mut self,
+    ) -> Result<(Vec<S>, Vec<EncryptWithProof>)> {
+        match self.receive_from_prev().await? {
+            ProtocolMessage::ShufflesAndEncryptions(chain, encryptions) => {
+                Ok((shuffles_from_chain(chain)?, encryptions))
+            }
+            message => Err(unexpected(message)),
+        }
+    }
+    async fn receive_encryptions(&mut self) -> Result<Vec<EncryptWithProof>> {
+        match self.receive_from_prev().await? {
+            ProtocolMessage::Encryptions(encryptions) => Ok(encryptions),
+            message => Err(unexpected(message)),
+        }
     }
 }
 
-impl ShowHandDeck for Player {
+impl<O> ShowHandDeck for Player<O> {
     fn show_hand(&self) -> Vec<CardFromDeck> {
         self.owners
             .iter()
@@ -266,38 +765,45 @@ impl ShowHandDeck for Player {
 }
 
 #[cfg(test)]
-mod test {
-    const ADDRESS: &str = "localhost:1234";
-
+pub(crate) mod test {
     use super::*;
-    use crypto::encryption::{basic_deck, decrypt, Translator};
-    use network::con_startup::ConStartup;
+    use crypto::encryption::decrypt;
+    use crypto::shuffle_v3::ShuffleWithArgument;
+    use network::channel::ChannelConnection;
+    use std::iter::zip;
     use std::thread;
 
     #[test]
-    fn encrypt_over_network() {
+    fn encrypt_over_channel() {
         println!("Starting game for two players");
+        let (first, second) = ChannelConnection::pair();
         let t1 = thread::spawn(|| {
-            let startup = ConStartup::new(2, 0);
-            let opponent = OtherPlayer::new(startup.initialize(&ADDRESS.to_string()));
+            let opponent = OtherPlayer::new(first);
+            let mut rng = game_rng(None);
             let player_1 = DeckPreparationBasic::prepare(
                 "P1".to_string(),
+                SigningKey::generate(&mut rng),
                 vec![opponent],
-                true,
-                basic_deck().to_vec(),
-            );
+                0,
+                DeckSpec::standard(),
+                &mut rng,
+            )
+            .unwrap();
             println!("DONE {}", player_1.name);
             (player_1.deck, player_1.keys)
         });
         let t2 = thread::spawn(|| {
-            let startup = ConStartup::new(2, 1);
-            let opponent = OtherPlayer::new(startup.initialize(&ADDRESS.to_string()));
+            let opponent = OtherPlayer::new(second);
+            let mut rng = game_rng(None);
             let player_2 = DeckPreparationBasic::prepare(
                 "P2".to_string(),
+                SigningKey::generate(&mut rng),
                 vec![opponent],
-                false,
-                basic_deck().to_vec(),
-            );
+                1,
+                DeckSpec::standard(),
+                &mut rng,
+            )
+            .unwrap();
             println!("DONE {}", player_2.name);
             (player_2.deck, player_2.keys)
         });
@@ -306,8 +812,7 @@ mod test {
         println!("Done");
         assert_eq!(d1, d2);
         println!("Same deck");
-        use std::iter::zip;
-        let translator = Translator::new(&basic_deck());
+        let translator = DeckSpec::standard().translator();
         let deck = zip(d1, k1)
             .map(|(c, k)| decrypt(&c, &k))
             .zip(k2)
@@ -316,4 +821,129 @@ mod test {
             .collect::<Vec<_>>();
         println!("Deck: {:?}", deck);
     }
+
+    /// Long-term key of player `player_id` in tests, so other players can pin it.
+    pub(crate) fn signer(player_id: usize) -> SigningKey {
+        SigningKey::generate(&mut game_rng(Some(1000 + player_id as u64)))
+    }
+
+    pub(crate) fn prepare_players<P: DeckPreparation>(
+        num_players: usize,
+        deck: DeckSpec,
+    ) -> Vec<Player> {
+        prepare_seeded_players::<P>(num_players, deck, None)
+    }
+
+    // player `id` is seeded with `seed + id`
+    pub(crate) fn prepare_seeded_players<P: DeckPreparation>(
+        num_players: usize,
+        deck: DeckSpec,
+        seed: Option<u64>,
+    ) -> Vec<Player> {
+        let handles = ChannelConnection::mesh(num_players)
+            .into_iter()
+            .enumerate()
+            .map(|(player_id, connections)| {
+                let deck = deck.clone();
+                thread::spawn(move || {
+                    let others = connections
+                        .into_iter()
+                        .enumerate()
+                        .map(|(ind, connection)| {
+                            let key = signer(other_player_id(player_id, ind)).verifying_key();
+                            OtherPlayer::new(connection).with_peer_key(key)
+                        })
+                        .collect();
+                    let mut rng = game_rng(seed.map(|seed| seed + player_id as u64));
+                    let signer = signer(player_id);
+                    P::prepare(
+                        format!("P{}", player_id),
+                        signer,
+                        others,
+                        player_id,
+                        deck,
+                        &mut rng,
+                    )
+                    .unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    }
+
+    fn prepare_mesh<P: DeckPreparation>(num_players: usize, deck: DeckSpec) {
+        let (decks, keys): (Vec<_>, Vec<_>) = prepare_players::<P>(num_players, deck.clone())
+            .into_iter()
+            .map(|player| (player.deck, player.keys))
+            .unzip();
+        assert!(decks.windows(2).all(|w| w[0] == w[1]));
+        let mut shuffled = decks.first().unwrap().clone();
+        for player_keys in keys.iter() {
+            for (value, key) in zip(shuffled.iter_mut(), player_keys) {
+                *value = decrypt(value, key);
+            }
+        }
+        let translator = deck.translator();
+        let mut cards = shuffled
+            .into_iter()
+            .map(|v| translator.translate(v).unwrap())
+            .collect::<Vec<_>>();
+        let mut expected = deck.cards().to_vec();
+        cards.sort();
+        expected.sort();
+        assert_eq!(cards, expected);
+    }
+
+    fn verification_ring(num_players: usize) {
+        const DECK_SIZE: usize = 8;
+        prepare_mesh::<DeckPreparationVerification>(
+            num_players,
+            DeckSpec::new(DeckSpec::short().cards()[..DECK_SIZE].to_vec()),
+        );
+    }
+
+    #[test]
+    fn verification_ring_shuffle_argument() {
+        prepare_mesh::<DeckPreparationVerification<ShuffleWithArgument>>(3, DeckSpec::standard());
+    }
+
+    #[test]
+    fn basic_ring_3_players() {
+        prepare_mesh::<DeckPreparationBasic>(3, DeckSpec::standard());
+    }
+
+    #[test]
+    fn basic_ring_double_deck() {
+        prepare_mesh::<DeckPreparationBasic>(2, DeckSpec::double());
+    }
+
+    #[test]
+    fn seeded_preparation_is_reproducible() {
+        const SEED: u64 = 42;
+        let run = || {
+            prepare_seeded_players::<DeckPreparationVerification>(3, DeckSpec::short(), Some(SEED))
+                .into_iter()
+                .map(|player| (player.deck, player.keys, player.commitments))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn verification_ring_3_players() {
+        verification_ring(3);
+    }
+
+    #[test]
+    fn verification_ring_4_players() {
+        verification_ring(4);
+    }
+
+    #[test]
+    fn verification_ring_5_players() {
+        verification_ring(5);
+    }
 }
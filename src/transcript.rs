@@ -0,0 +1,639 @@
+use std::collections::{HashMap, VecDeque};
+use std::iter::zip;
+use std::sync::{Arc, Mutex};
+
+use common::{
+    cards::Card,
+    game::{CardFromDeck, CardSelector, GamePrinter, GameState, ShowHandDeck},
+};
+use crypto::{
+    deck::DeckSpec,
+    encryption::decrypt,
+    shuffle_v2::{key_commitment, DecryptionShare, EncryptWithProof, ShuffleProof},
+    signature::{Signature, SigningKey, VerifyingKey},
+    types::{EncryptedValue, KeyType},
+};
+use network::{
+    error::{Error, Result},
+    message::{ProtocolMessage, ShuffleChain},
+};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    moves::{draw_order, Moves},
+    player::{collect_commitments, verify_steps, Owner, Player},
+    trick_game::{GameRules, TrickGame},
+};
+
+/*
+ * Every player records all messages it sends and receives from the start of the preparation,
+ * at the end it signs the transcript together with the final scores.
+ * Transcript is verified offline from the view of the player who recorded it:
+ * 1. Proofs of the verified preparation are checked, they determine the deck and key commitments.
+ *    Basic preparation has no proofs, the last deck and the exchanged commitments are used.
+ * 2. Every decryption share is checked against the commitment of its author.
+ * 3. Keys revealed at the end have to match the commitments and decrypt the deck
+ *    to a permutation of the starting deck.
+ * 4. The game is replayed with the recorded moves and has to end with the reported scores.
+ */
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Entry {
+    Sent {
+        to: usize,
+        message: ProtocolMessage,
+    },
+    Received {
+        from: usize,
+        message: ProtocolMessage,
+    },
+}
+
+/// Log of messages of one player, shared by all of its connections.
+#[derive(Clone, Default)]
+pub struct Recorder(Arc<Mutex<Vec<Entry>>>);
+
+impl Recorder {
+    /// Continues the log of a player restored from a checkpoint.
+    pub(crate) fn with_entries(entries: Vec<Entry>) -> Self {
+        Recorder(Arc::new(Mutex::new(entries)))
+    }
+    pub(crate) fn record(&self, entry: Entry) {
+        self.0.lock().unwrap().push(entry);
+    }
+    pub fn entries(&self) -> Vec<Entry> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Transcript {
+    pub player_id: usize,
+    pub num_players: usize,
+    pub spec: DeckSpec,
+    pub entries: Vec<Entry>,
+    /// `scores[id]` is the final score of player `id`.
+    pub scores: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Signed by the long-term key of the player, the verifier has to know the key.
+pub struct SignedTranscript {
+    pub transcript: Transcript,
+    pub signature: Signature,
+}
+
+impl Player {
+    /// Transcript of everything the player has seen, `scores` are ordered by ids.
+    pub fn transcript(&self, scores: Vec<usize>) -> Transcript {
+        Transcript {
+            player_id: self.player_id,
+            num_players: self.players.len() + 1,
+            spec: self.spec.clone(),
+            entries: self.recorder.entries(),
+            scores,
+        }
+    }
+}
+
+impl Transcript {
+    pub fn sign<R: RngCore + CryptoRng>(self, key: &SigningKey, rng: &mut R) -> SignedTranscript {
+        let signature = key.sign(&serde_json::to_vec(&self).unwrap(), rng);
+        SignedTranscript {
+            transcript: self,
+            signature,
+        }
+    }
+}
+
+// sender, recipient and the message, broadcasts of the recording player are kept only once
+type Message<'a> = (usize, usize, &'a ProtocolMessage);
+
+fn messages(transcript: &Transcript) -> Result<Vec<Message<'_>>> {
+    let me = transcript.player_id;
+    let num_players = transcript.num_players;
+    if me >= num_players || transcript.scores.len() != num_players {
+        return Err(Error::protocol("Wrong number of players"));
+    }
+    let first_other = if me == 0 { 1 } else { 0 };
+    let mut messages = vec![];
+    for entry in transcript.entries.iter() {
+        let (from, to, message) = match entry {
+            Entry::Sent { to, message } => (me, *to, message),
+            Entry::Received { from, message } => (*from, me, message),
+        };
+        if from >= num_players || to >= num_players || from == to {
+            return Err(Error::protocol("Wrong player id"));
+        }
+        let broadcast = matches!(
+            message,
+            ProtocolMessage::KeyCommitments(_)
+                | ProtocolMessage::TableShare { .. }
+                | ProtocolMessage::PlayCard { .. }
+                | ProtocolMessage::Bet(_)
+                | ProtocolMessage::RevealKeys(_)
+        );
+        if from == me && broadcast && to != first_other {
+            continue;
+        }
+        messages.push((from, to, message));
+    }
+    Ok(messages)
+}
+
+fn chain_len(chain: &ShuffleChain) -> usize {
+    match chain {
+        ShuffleChain::Proofs(proofs) => proofs.len(),
+        ShuffleChain::Arguments(proofs) => proofs.len(),
+    }
+}
+
+// deck and commitments of all players after the verified preparation
+fn verified_deck<S: ShuffleProof>(
+    spec: &DeckSpec,
+    shuffles: &[S],
+    encryptions: &[EncryptWithProof],
+) -> Result<(Vec<EncryptedValue>, Vec<Vec<EncryptedValue>>)> {
+    verify_steps(&spec.deck(), shuffles, 0)?;
+    verify_steps(shuffles.last().unwrap().result(), encryptions, 0)?;
+    // id past the last player, so commitments of everybody are collected
+    let commitments = collect_commitments(shuffles, encryptions, shuffles.len())?;
+    Ok((encryptions.last().unwrap().values_aftr.clone(), commitments))
+}
+
+fn prepared_deck(
+    transcript: &Transcript,
+    messages: &[Message],
+) -> Result<(Vec<EncryptedValue>, Vec<Vec<EncryptedValue>>)> {
+    let num_players = transcript.num_players;
+    let mut shuffles = None;
+    let mut encryptions = None;
+    let mut deck = None;
+    let mut commitments = vec![None; num_players];
+    for &(from, _, message) in messages {
+        match message {
+            ProtocolMessage::Deck(values) => deck = Some(values),
+            ProtocolMessage::KeyCommitments(values) => {
+                *commitments.get_mut(from).unwrap() = Some(values.clone())
+            }
+            ProtocolMessage::ShufflesAndEncryptions(chain, proofs) => {
+                if chain_len(chain) == num_players {
+                    shuffles = Some(chain);
+                }
+                if proofs.len() == num_players {
+                    encryptions = Some(proofs);
+                }
+            }
+            ProtocolMessage::Encryptions(proofs) if proofs.len() == num_players => {
+                encryptions = Some(proofs)
+            }
+            _ => (),
+        }
+    }
+    match (shuffles, encryptions) {
+        (Some(ShuffleChain::Proofs(proofs)), Some(encryptions)) => {
+            verified_deck(&transcript.spec, proofs, encryptions)
+        }
+        (Some(ShuffleChain::Arguments(proofs)), Some(encryptions)) => {
+            verified_deck(&transcript.spec, proofs, encryptions)
+        }
+        _ => Ok((
+            deck.ok_or(Error::protocol("Transcript has no deck"))?
+                .clone(),
+            commitments
+                .into_iter()
+                .collect::<Option<Vec<_>>>()
+                .ok_or(Error::protocol("Transcript has no key commitments"))?,
+        )),
+    }
+}
+
+fn verify_shares(
+    num_players: usize,
+    deck: &[EncryptedValue],
+    commitments: &[Vec<EncryptedValue>],
+    messages: &[Message],
+) -> Result<()> {
+    let check =
+        |author: usize, ind: usize, card: &EncryptedValue, share: &DecryptionShare| -> Result<()> {
+            let commitment = commitments
+                .get(author)
+                .unwrap()
+                .get(ind)
+                .ok_or(Error::protocol("Card is not in the deck"))?;
+            if share.verify(card, commitment) {
+                Ok(())
+            } else {
+                Err(Error::cheating(author, "Wrong decryption share"))
+            }
+        };
+    let deck_card = |ind: usize| -> Result<EncryptedValue> {
+        deck.get(ind)
+            .copied()
+            .ok_or(Error::protocol("Card is not in the deck"))
+    };
+    // card `ind` from the deck decrypted by `shares` of `authors`, sent by `sender`
+    let apply = |ind: usize,
+                 authors: &[usize],
+                 shares: &[DecryptionShare],
+                 sender: usize|
+     -> Result<EncryptedValue> {
+        if shares.len() != authors.len() {
+            return Err(Error::cheating(sender, "Wrong number of shares"));
+        }
+        let mut card = deck_card(ind)?;
+        for (&author, share) in zip(authors, shares) {
+            check(author, ind, &card, share)?;
+            card = share.value;
+        }
+        Ok(card)
+    };
+    // requests by (requesting player, answering player)
+    let mut requests = HashMap::new();
+    // cards revealed to the table, decrypted by shares received so far
+    let mut table = HashMap::new();
+    for &(from, to, message) in messages {
+        match message {
+            ProtocolMessage::DrawRequest { ind, shares } => {
+                let authors = draw_order(num_players, from)
+                    .into_iter()
+                    .take_while(|&id| id != to)
+                    .collect::<Vec<_>>();
+                let card = apply(*ind, &authors, shares, from)?;
+                requests.insert((from, to), (*ind, card));
+            }
+            ProtocolMessage::KeyShare(share) => {
+                let (ind, card) = requests
+                    .remove(&(to, from))
+                    .ok_or(Error::cheating(from, "Share without request"))?;
+                check(from, ind, &card, share)?;
+            }
+            ProtocolMessage::TableShare { ind, share } => {
+                let value = match table.get(ind) {
+                    Some(value) => *value,
+                    None => deck_card(*ind)?,
+                };
+                check(from, *ind, &value, share)?;
+                table.insert(*ind, share.value);
+            }
+            ProtocolMessage::PlayCard { ind, shares } => {
+                let mut authors = draw_order(num_players, from);
+                authors.push(from);
+                apply(*ind, &authors, shares, from)?;
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+// cards of the whole deck decrypted with revealed keys
+fn reveal_deck(
+    transcript: &Transcript,
+    deck: &[EncryptedValue],
+    commitments: &[Vec<EncryptedValue>],
+    messages: &[Message],
+) -> Result<Vec<Card>> {
+    let mut keys = vec![None; transcript.num_players];
+    for &(from, _, message) in messages {
+        if let ProtocolMessage::RevealKeys(revealed) = message {
+            *keys.get_mut(from).unwrap() = Some(revealed);
+        }
+    }
+    let mut values = deck.to_vec();
+    for (id, (keys, commitments)) in zip(keys, commitments).enumerate() {
+        let keys = keys.ok_or(Error::protocol("Transcript has no revealed keys"))?;
+        if keys.len() != values.len()
+            || keys.contains(&KeyType::from(0))
+            || zip(keys, commitments).any(|(k, c)| key_commitment(k) != *c)
+        {
+            return Err(Error::cheating(
+                id,
+                "Revealed keys do not match commitments",
+            ));
+        }
+        for (value, key) in zip(values.iter_mut(), keys) {
+            *value = decrypt(value, key);
+        }
+    }
+    let translator = transcript.spec.translator();
+    let codes = values
+        .into_iter()
+        .map(|value| translator.translate(value))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(Error::protocol("Card is not in the deck"))?;
+    let mut sorted = codes.clone();
+    let mut expected = transcript.spec.cards().to_vec();
+    sorted.sort();
+    expected.sort();
+    if sorted != expected {
+        return Err(Error::protocol(
+            "Deck is not a permutation of the starting deck",
+        ));
+    }
+    codes
+        .into_iter()
+        .map(|code| Card::try_from(code).map_err(|_| Error::protocol("Card is not in the deck")))
+        .collect()
+}
+
+// whole deck known in advance, moves of other players come from the transcript
+struct ReplayTable {
+    player_id: usize,
+    cards: Vec<Card>,
+    owners: Vec<Option<Owner>>,
+    /// `plays[id]` are indices of cards played by player `id`, in order.
+    plays: Vec<VecDeque<usize>>,
+}
+
+impl ReplayTable {
+    fn top_of_deck(&self) -> Result<usize> {
+        self.owners
+            .iter()
+            .position(|o| o.is_none())
+            .ok_or(Error::protocol("Deck is empty"))
+    }
+
+    fn take_top(&mut self, owner: impl Fn(CardFromDeck) -> Owner) -> Result<Card> {
+        let ind = self.top_of_deck()?;
+        let card = *self.cards.get(ind).unwrap();
+        *self.owners.get_mut(ind).unwrap() = Some(owner(CardFromDeck { card, ind }));
+        Ok(card)
+    }
+}
+
+impl Moves for ReplayTable {
+    fn draw_from_deck(&mut self) -> Result<Card> {
+        self.take_top(Owner::Me)
+    }
+    fn let_draw_from_deck(&mut self, other: usize) -> Result<()> {
+        self.take_top(|_| Owner::Other(other))?;
+        Ok(())
+    }
+    fn play_card(&mut self, ind: usize) -> Result<()> {
+        let card = match self.owners.get(ind) {
+            Some(Some(Owner::Me(card))) => *card,
+            _ => return Err(Error::protocol("Player is not owner of this card")),
+        };
+        *self.owners.get_mut(ind).unwrap() = Some(Owner::Player(card));
+        Ok(())
+    }
+    fn let_play_card(&mut self, other: usize) -> Result<Card> {
+        let other_id = if other < self.player_id {
+            other
+        } else {
+            other + 1
+        };
+        let ind = self
+            .plays
+            .get_mut(other_id)
+            .unwrap()
+            .pop_front()
+            .ok_or(Error::cheating(other_id, "Missing move in the transcript"))?;
+        match self.owners.get(ind) {
+            Some(Some(Owner::Other(owner))) if *owner == other => (),
+            _ => {
+                return Err(Error::cheating(
+                    other_id,
+                    "Player is not owner of this card",
+                ))
+            }
+        };
+        let card = CardFromDeck {
+            card: *self.cards.get(ind).unwrap(),
+            ind,
+        };
+        *self.owners.get_mut(ind).unwrap() = Some(Owner::OtherPlayed(other, card));
+        Ok(card.card)
+    }
+    fn reveal_from_deck(&mut self) -> Result<Card> {
+        self.take_top(Owner::Table)
+    }
+}
+
+impl ShowHandDeck for ReplayTable {
+    fn show_hand(&self) -> Vec<CardFromDeck> {
+        self.owners
+            .iter()
+            .filter_map(|o| match o {
+                Some(Owner::Me(card)) => Some(*card),
+                _ => None,
+            })
+            .collect()
+    }
+    fn deck_size(&self) -> usize {
+        self.owners.iter().filter(|o| o.is_none()).count()
+    }
+}
+
+struct ReplayPrinter {}
+
+impl GamePrinter for ReplayPrinter {
+    fn print_game(&mut self, _game_state: &GameState) {}
+}
+
+// plays recorded moves of the player, unknown card is rejected by the game as illegal
+struct ReplaySelector {
+    plays: VecDeque<usize>,
+}
+
+impl CardSelector for ReplaySelector {
+    fn select_card(&mut self, hand: &[CardFromDeck]) -> CardFromDeck {
+        let ind = self.plays.pop_front().unwrap_or(usize::MAX);
+        hand.iter()
+            .find(|c| c.ind == ind)
+            .copied()
+            .unwrap_or(CardFromDeck {
+                ind: usize::MAX,
+                ..*hand.first().unwrap()
+            })
+    }
+}
+
+fn replay<Rules: GameRules>(
+    transcript: &Transcript,
+    rules: Rules,
+    cards: Vec<Card>,
+    messages: &[Message],
+) -> Result<Vec<usize>> {
+    let me = transcript.player_id;
+    let mut plays = vec![VecDeque::new(); transcript.num_players];
+    for &(from, _, message) in messages {
+        if let ProtocolMessage::PlayCard { ind, .. } = message {
+            plays.get_mut(from).unwrap().push_back(*ind);
+        }
+    }
+    let own_plays = std::mem::take(plays.get_mut(me).unwrap());
+    let num_own_plays = own_plays.len();
+    let table = ReplayTable {
+        player_id: me,
+        owners: vec![None; cards.len()],
+        cards,
+        plays,
+    };
+    let mut game = TrickGame::new(
+        rules,
+        me,
+        transcript.num_players,
+        table,
+        ReplayPrinter {},
+        ReplaySelector { plays: own_plays },
+    );
+    let (score, mut scores) = game.play()?;
+    scores.insert(me, score);
+    let table = game.into_player();
+    let replayed_own_plays = table
+        .owners
+        .iter()
+        .filter(|o| matches!(o, Some(Owner::Player(_))))
+        .count();
+    if replayed_own_plays != num_own_plays || table.plays.iter().any(|p| !p.is_empty()) {
+        return Err(Error::protocol(
+            "Transcript has moves after the end of the game",
+        ));
+    }
+    if scores != transcript.scores {
+        return Err(Error::protocol("Reported scores do not match the replay"));
+    }
+    Ok(scores)
+}
+
+const INVALID_SIGNATURE: &str = "Invalid signature of the transcript";
+
+/// Checks the signature by the pinned key `signer`, all proofs and replays the game,
+/// returns scores ordered by ids.
+pub fn verify_transcript<Rules: GameRules>(
+    signed: &SignedTranscript,
+    signer: &VerifyingKey,
+    rules: Rules,
+) -> Result<Vec<usize>> {
+    let SignedTranscript {
+        transcript,
+        signature,
+    } = signed;
+    if !signer.verify(&serde_json::to_vec(transcript)?, signature) {
+        return Err(Error::protocol(INVALID_SIGNATURE));
+    }
+    let messages = messages(transcript)?;
+    let (deck, commitments) = prepared_deck(transcript, &messages)?;
+    verify_shares(transcript.num_players, &deck, &commitments, &messages)?;
+    let cards = reveal_deck(transcript, &deck, &commitments, &messages)?;
+    replay(transcript, rules, cards, &messages)
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use common::game::{CardFromDeck, CardSelector, GamePrinter, GameState};
+    use crypto::{deck::DeckSpec, signature::SigningKey};
+    use network::error::Error;
+    use rand::thread_rng;
+
+    use super::{verify_transcript, SignedTranscript, INVALID_SIGNATURE};
+    use crate::{
+        audit::Audit,
+        player::{
+            test::{prepare_players, signer},
+            DeckPreparation, DeckPreparationBasic, DeckPreparationVerification,
+        },
+        simple_game::{SimpleGame, SimpleRules},
+    };
+
+    struct MockPrinter {}
+
+    impl GamePrinter for MockPrinter {
+        fn print_game(&mut self, _game_state: &GameState) {}
+    }
+
+    struct MockSelector {}
+
+    impl CardSelector for MockSelector {
+        fn select_card(&mut self, hand: &[CardFromDeck]) -> CardFromDeck {
+            *hand.last().unwrap()
+        }
+    }
+
+    fn play_and_record<P: DeckPreparation>(num_players: usize) -> Vec<SignedTranscript> {
+        let handles = prepare_players::<P>(num_players, DeckSpec::short())
+            .into_iter()
+            .enumerate()
+            .map(|(player_id, player)| {
+                thread::spawn(move || {
+                    let mut game = SimpleGame::new(
+                        SimpleRules {},
+                        player_id,
+                        num_players,
+                        player,
+                        MockPrinter {},
+                        MockSelector {},
+                    );
+                    let (score, mut scores) = game.play().unwrap();
+                    scores.insert(player_id, score);
+                    let mut player = game.into_player();
+                    assert!(player.audit().unwrap().is_valid());
+                    player
+                        .transcript(scores)
+                        .sign(&player.signer, &mut thread_rng())
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn verify_basic_game() {
+        let transcripts = play_and_record::<DeckPreparationBasic>(3);
+        for (player_id, signed) in transcripts.iter().enumerate() {
+            let key = signer(player_id).verifying_key();
+            let scores = verify_transcript(signed, &key, SimpleRules {}).unwrap();
+            assert_eq!(scores, signed.transcript.scores);
+        }
+    }
+
+    #[test]
+    fn verify_verified_game() {
+        let transcripts = play_and_record::<DeckPreparationVerification>(2);
+        for (player_id, signed) in transcripts.iter().enumerate() {
+            let key = signer(player_id).verifying_key();
+            assert!(verify_transcript(signed, &key, SimpleRules {}).is_ok());
+        }
+    }
+
+    #[test]
+    fn tampered_transcript() {
+        let signed = play_and_record::<DeckPreparationBasic>(2).pop().unwrap();
+        let key = signer(1).verifying_key();
+        let mut rng = thread_rng();
+
+        let mut tampered = signed.clone();
+        *tampered.transcript.scores.first_mut().unwrap() += 1;
+        assert!(matches!(
+            verify_transcript(&tampered, &key, SimpleRules {}),
+            Err(Error::ProtocolViolation(reason)) if reason == INVALID_SIGNATURE
+        ));
+
+        // signed by somebody else than the pinned player
+        let forged = signed
+            .transcript
+            .clone()
+            .sign(&SigningKey::generate(&mut rng), &mut rng);
+        assert!(matches!(
+            verify_transcript(&forged, &key, SimpleRules {}),
+            Err(Error::ProtocolViolation(reason)) if reason == INVALID_SIGNATURE
+        ));
+
+        // the player itself signs scores which do not match the game
+        let mut lying = signed.transcript;
+        *lying.scores.first_mut().unwrap() += 1;
+        let lying = lying.sign(&signer(1), &mut rng);
+        assert!(matches!(
+            verify_transcript(&lying, &key, SimpleRules {}),
+            Err(Error::ProtocolViolation(reason)) if reason != INVALID_SIGNATURE
+        ));
+    }
+}
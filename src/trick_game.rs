@@ -0,0 +1,640 @@
+use std::ops::AddAssign;
+
+use crate::async_moves::AsyncMoves;
+use crate::moves::Moves;
+use crate::player::run;
+use common::{
+    cards::Card,
+    game::{CardFromDeck, CardSelector, GameOutcome, GamePrinter, GameState, ShowHandDeck},
+};
+use network::{
+    error::{Error, Result},
+    message::{AbortNotice, Evidence},
+};
+use serde::{Deserialize, Serialize};
+
+/*
+ * Trick-taking game played by players with ids 0..num_players.
+ * Every player draws its starting hand in order of ids, player 0 leads the first trick.
+ * Players play in order of ids starting with the leader, winner of a trick
+ * scores its points and leads the next one.
+ * Steps of the game are async, blocking players are driven by `play`
+ * and async players by `play_async`, so one thread may run many async games.
+ */
+
+/// Outcome of a game of player `player_id` which failed with `err`,
+/// `check_evidence` tells whether evidence shows that a player cheated.
+/// Errors without anybody to blame are returned.
+pub(crate) fn failure_outcome(
+    err: Error,
+    player_id: usize,
+    num_players: usize,
+    check_evidence: impl Fn(usize, &Evidence) -> bool,
+) -> Result<GameOutcome> {
+    match err {
+        Error::Cheating { player, reason, .. } => {
+            Ok(GameOutcome::OpponentForfeited { player, reason })
+        }
+        // with two players the silent one is known, it may have just lost connection
+        Error::Timeout if num_players == 2 => Ok(GameOutcome::Aborted {
+            player: 1 - player_id,
+            reason: err.to_string(),
+        }),
+        Error::Aborted(notice) => Ok(blame(*notice, player_id, check_evidence)),
+        err => Err(err),
+    }
+}
+
+// accusation of another player counts only with evidence the player can check
+fn blame(
+    notice: AbortNotice,
+    player_id: usize,
+    check_evidence: impl Fn(usize, &Evidence) -> bool,
+) -> GameOutcome {
+    let AbortNotice {
+        player_id: sender,
+        reason,
+        cheater,
+        evidence,
+    } = notice;
+    match (cheater, evidence) {
+        (Some(cheater), Some(evidence)) if check_evidence(cheater, &evidence) => {
+            if cheater == player_id {
+                GameOutcome::Forfeited { reason }
+            } else {
+                GameOutcome::OpponentForfeited {
+                    player: cheater,
+                    reason,
+                }
+            }
+        }
+        _ => GameOutcome::Aborted {
+            player: sender,
+            reason,
+        },
+    }
+}
+
+pub trait GameRules {
+    /// Number of cards every player draws at the start, `deck_size` cards are available.
+    fn hand_size(&self, num_players: usize, deck_size: usize) -> usize;
+    /// Cards from `hand` which can be played, `trick` holds cards played so far starting with the leader.
+    fn legal_moves(&self, hand: &[CardFromDeck], trick: &[Card]) -> Vec<CardFromDeck>;
+    /// Position in complete `trick` of the winning card.
+    fn trick_winner(&self, trick: &[Card]) -> usize;
+    /// Points scored by the winner of complete `trick`.
+    fn trick_points(&self, _trick: &[Card]) -> usize {
+        1
+    }
+    /// Whether everybody draws a card after each trick, starting with the winner.
+    fn draw_after_trick(&self) -> bool;
+    /// Checked after every trick with the state of the player's hand and the deck.
+    fn is_over(&self, hand_size: usize, _deck_size: usize) -> bool {
+        hand_size == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum Phase {
+    Deal,
+    Trick { leader: usize },
+    Done,
+}
+
+/// State of the game between two steps, stored in a checkpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrickState {
+    phase: Phase,
+    scores: Vec<usize>,
+}
+
+pub struct TrickGame<Rules, Printer, Selector, PlayerType>
+where
+    Rules: GameRules,
+    Printer: GamePrinter,
+    Selector: CardSelector,
+    PlayerType: AsyncMoves + ShowHandDeck,
+{
+    rules: Rules,
+    player: PlayerType,
+    player_id: usize,
+    num_players: usize,
+    phase: Phase,
+    /// `scores[id]` is the score of player `id`.
+    scores: Vec<usize>,
+    printer: Printer,
+    selector: Selector,
+}
+
+impl<Rules, Printer, Selector, PlayerType> TrickGame<Rules, Printer, Selector, PlayerType>
+where
+    Rules: GameRules,
+    Printer: GamePrinter,
+    Selector: CardSelector,
+    PlayerType: AsyncMoves + ShowHandDeck,
+{
+    pub fn new(
+        rules: Rules,
+        player_id: usize,
+        num_players: usize,
+        player: PlayerType,
+        printer: Printer,
+        selector: Selector,
+    ) -> Self {
+        assert!(player_id < num_players);
+        TrickGame {
+            rules,
+            player,
+            player_id,
+            num_players,
+            phase: Phase::Deal,
+            scores: vec![0; num_players],
+            printer,
+            selector,
+        }
+    }
+
+    /// Continues the game from `state` stored in a checkpoint.
+    pub fn with_state(mut self, state: TrickState) -> Self {
+        self.phase = state.phase;
+        self.scores = state.scores;
+        self
+    }
+
+    pub fn state(&self) -> TrickState {
+        TrickState {
+            phase: self.phase,
+            scores: self.scores.clone(),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.phase, Phase::Done)
+    }
+
+    // index of player `id` among other players, as used by `Moves`
+    fn other_index(&self, id: usize) -> usize {
+        if id < self.player_id {
+            id
+        } else {
+            id - 1
+        }
+    }
+
+    fn print_game(&mut self, table_cards: &[Card]) {
+        self.printer.print_game(&GameState {
+            hand: self.player.show_hand().iter().map(|f| f.card).collect(),
+            table_cards: table_cards.to_vec(),
+            deck_cards: self.player.deck_size(),
+        });
+    }
+
+    async fn draw(&mut self, id: usize) -> Result<()> {
+        if id == self.player_id {
+            self.player.draw_from_deck().await?;
+        } else {
+            let other = self.other_index(id);
+            self.player.let_draw_from_deck(other).await?;
+        }
+        Ok(())
+    }
+
+    async fn deal(&mut self) -> Result<Phase> {
+        let hand_size = self
+            .rules
+            .hand_size(self.num_players, self.player.deck_size());
+        for id in 0..self.num_players {
+            for _ in 0..hand_size {
+                self.draw(id).await?;
+            }
+        }
+        Ok(Phase::Trick { leader: 0 })
+    }
+
+    async fn play_card(&mut self, trick: &[Card]) -> Result<Card> {
+        let legal = self.rules.legal_moves(&self.player.show_hand(), trick);
+        let CardFromDeck { card, ind } = self.selector.select_card(&legal);
+        if !legal.iter().any(|c| c.ind == ind) {
+            return Err(Error::protocol("Illegal move"));
+        }
+        self.player.play_card(ind).await?;
+        Ok(card)
+    }
+
+    async fn play_trick(&mut self, leader: usize) -> Result<Phase> {
+        let mut trick = vec![];
+        for offset in 0..self.num_players {
+            self.print_game(&trick);
+            let id = (leader + offset) % self.num_players;
+            let card = if id == self.player_id {
+                self.play_card(&trick).await?
+            } else {
+                let other = self.other_index(id);
+                self.player.let_play_card(other).await?
+            };
+            trick.push(card);
+        }
+        self.print_game(&trick);
+        let winner = (leader + self.rules.trick_winner(&trick)) % self.num_players;
+        self.scores
+            .get_mut(winner)
+            .unwrap()
+            .add_assign(self.rules.trick_points(&trick));
+        if self.rules.draw_after_trick() && self.player.deck_size() >= self.num_players {
+            for offset in 0..self.num_players {
+                self.draw((winner + offset) % self.num_players).await?;
+            }
+        }
+        let hand_size = self.player.show_hand().len();
+        if self.rules.is_over(hand_size, self.player.deck_size()) {
+            Ok(Phase::Done)
+        } else {
+            Ok(Phase::Trick { leader: winner })
+        }
+    }
+
+    async fn make_turn(&mut self) -> Result<()> {
+        self.phase = match self.phase {
+            Phase::Deal => self.deal().await?,
+            Phase::Trick { leader } => self.play_trick(leader).await?,
+            Phase::Done => unreachable!("Game is done"),
+        };
+        Ok(())
+    }
+
+    fn scores(&self) -> (usize, Vec<usize>) {
+        let mut scores = self.scores.clone();
+        let score = scores.remove(self.player_id);
+        (score, scores)
+    }
+
+    /// Same as `play`, for async players.
+    pub async fn play_async(&mut self) -> Result<(usize, Vec<usize>)> {
+        while !self.is_done() {
+            self.make_turn().await?;
+        }
+        Ok(self.scores())
+    }
+
+    /// Same as `outcome`, for async players.
+    pub async fn outcome_async(&mut self, result: Result<()>) -> Result<GameOutcome> {
+        let outcome = match result {
+            Ok(()) => {
+                let (score, scores) = self.scores();
+                GameOutcome::Finished { score, scores }
+            }
+            Err(err) => {
+                self.player.abort(&err).await;
+                failure_outcome(
+                    err,
+                    self.player_id,
+                    self.num_players,
+                    |cheater, evidence| self.player.check_evidence(cheater, evidence),
+                )?
+            }
+        };
+        self.printer.print_outcome(&outcome);
+        Ok(outcome)
+    }
+
+    /// Same as `play_to_end`, for async players.
+    pub async fn play_to_end_async(&mut self) -> Result<GameOutcome> {
+        let result = self.play_async().await.map(|_| ());
+        self.outcome_async(result).await
+    }
+
+    pub fn player_mut(&mut self) -> &mut PlayerType {
+        &mut self.player
+    }
+
+    pub fn into_player(self) -> PlayerType {
+        self.player
+    }
+}
+
+impl<Rules, Printer, Selector, PlayerType> TrickGame<Rules, Printer, Selector, PlayerType>
+where
+    Rules: GameRules,
+    Printer: GamePrinter,
+    Selector: CardSelector,
+    PlayerType: Moves + ShowHandDeck,
+{
+    pub fn play_one_step(&mut self) -> Result<()> {
+        if !self.is_done() {
+            run(self.make_turn())?;
+        }
+        Ok(())
+    }
+
+    /// Score of the player and scores of other players ordered by ids.
+    pub fn play(&mut self) -> Result<(usize, Vec<usize>)> {
+        run(self.play_async())
+    }
+
+    /// Outcome of the game which ended with `result`, shown by the printer.
+    /// Other players are told why the game failed. Cheating ends it with a forfeit,
+    /// leaving and silence of the other player with a no-fault abort,
+    /// errors without anybody to blame are returned.
+    pub fn outcome(&mut self, result: Result<()>) -> Result<GameOutcome> {
+        run(self.outcome_async(result))
+    }
+
+    /// Plays the game to the end, see `outcome`.
+    pub fn play_to_end(&mut self) -> Result<GameOutcome> {
+        run(self.play_to_end_async())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use common::{
+        cards::Card,
+        game::{CardFromDeck, CardSelector, GameOutcome, GamePrinter, GameState, ShowHandDeck},
+    };
+    use network::{
+        error::{Error, Result},
+        message::{AbortNotice, Evidence},
+    };
+
+    use crypto::deck::DeckSpec;
+
+    use super::{GameRules, TrickGame};
+    use crate::{
+        async_moves::test::{async_connections, prepare_async_player},
+        async_player::AsyncDeckPreparationBasic,
+        follow_suit::FollowSuitRules,
+        moves::Moves,
+        simple_game::SimpleRules,
+    };
+
+    struct MockPrinter {}
+
+    impl GamePrinter for MockPrinter {
+        fn print_game(&mut self, _game_state: &GameState) {}
+    }
+
+    struct MockSelector {}
+
+    impl CardSelector for MockSelector {
+        fn select_card(&mut self, hand: &[CardFromDeck]) -> CardFromDeck {
+            *hand.first().unwrap()
+        }
+    }
+
+    // selects card which is not in the hand
+    struct CheatingSelector {}
+
+    impl CardSelector for CheatingSelector {
+        fn select_card(&mut self, hand: &[CardFromDeck]) -> CardFromDeck {
+            CardFromDeck {
+                ind: usize::MAX,
+                ..*hand.first().unwrap()
+            }
+        }
+    }
+
+    // sees the whole table, other players play their first card
+    struct MockTable {
+        deck: Vec<Card>,
+        hand: Vec<CardFromDeck>,
+        others: Vec<Vec<Card>>,
+        drawn: usize,
+        /// Returned instead of the next card played by another player.
+        failure: Option<Error>,
+        aborted: bool,
+    }
+
+    impl MockTable {
+        fn new(num_players: usize) -> Self {
+            MockTable {
+                deck: (0..52).map(|i| Card::try_from(i).unwrap()).rev().collect(),
+                hand: vec![],
+                others: vec![vec![]; num_players - 1],
+                drawn: 0,
+                failure: None,
+                aborted: false,
+            }
+        }
+    }
+
+    impl Moves for MockTable {
+        fn draw_from_deck(&mut self) -> Result<Card> {
+            let card = self.deck.pop().unwrap();
+            self.hand.push(CardFromDeck {
+                card,
+                ind: self.drawn,
+            });
+            self.drawn += 1;
+            Ok(card)
+        }
+        fn let_draw_from_deck(&mut self, other: usize) -> Result<()> {
+            let card = self.deck.pop().unwrap();
+            self.others.get_mut(other).unwrap().push(card);
+            self.drawn += 1;
+            Ok(())
+        }
+        fn play_card(&mut self, ind: usize) -> Result<()> {
+            self.hand.retain(|c| c.ind != ind);
+            Ok(())
+        }
+        fn let_play_card(&mut self, other: usize) -> Result<Card> {
+            if let Some(err) = self.failure.take() {
+                return Err(err);
+            }
+            Ok(self.others.get_mut(other).unwrap().remove(0))
+        }
+        fn reveal_from_deck(&mut self) -> Result<Card> {
+            self.drawn += 1;
+            Ok(self.deck.pop().unwrap())
+        }
+        fn abort(&mut self, _err: &Error) {
+            self.aborted = true;
+        }
+    }
+
+    impl ShowHandDeck for MockTable {
+        fn show_hand(&self) -> Vec<CardFromDeck> {
+            self.hand.clone()
+        }
+        fn deck_size(&self) -> usize {
+            self.deck.len()
+        }
+    }
+
+    fn run_rules<R: GameRules + Copy>(rules: R) {
+        for num_players in 2..=4 {
+            for player_id in 0..num_players {
+                let mut game = TrickGame::new(
+                    rules,
+                    player_id,
+                    num_players,
+                    MockTable::new(num_players),
+                    MockPrinter {},
+                    MockSelector {},
+                );
+                let (score, scores) = game.play().unwrap();
+                assert_eq!(scores.len(), num_players - 1);
+                assert_eq!(score + scores.iter().sum::<usize>(), 52 / num_players);
+                assert!(game.into_player().hand.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn simple_rules() {
+        run_rules(SimpleRules {});
+    }
+
+    #[test]
+    fn follow_suit_rules() {
+        run_rules(FollowSuitRules {});
+    }
+
+    #[test]
+    fn identical_cards_in_trick() {
+        // copies from a multi-copy deck are equal cards at different positions
+        let mut table = MockTable::new(2);
+        table.deck = vec![Card::try_from(0).unwrap(); 52];
+        let mut game = TrickGame::new(SimpleRules {}, 0, 2, table, MockPrinter {}, MockSelector {});
+        let (score, scores) = game.play().unwrap();
+        assert_eq!(score + scores.iter().sum::<usize>(), 26);
+    }
+
+    #[test]
+    fn illegal_move() {
+        let mut game = TrickGame::new(
+            SimpleRules {},
+            0,
+            2,
+            MockTable::new(2),
+            MockPrinter {},
+            CheatingSelector {},
+        );
+        game.play_one_step().unwrap();
+        assert!(matches!(
+            game.play_one_step(),
+            Err(Error::ProtocolViolation(_))
+        ));
+    }
+
+    fn outcome_after(failure: Error) -> (GameOutcome, bool) {
+        let mut table = MockTable::new(2);
+        table.failure = Some(failure);
+        let mut game = TrickGame::new(SimpleRules {}, 0, 2, table, MockPrinter {}, MockSelector {});
+        let outcome = game.play_to_end().unwrap();
+        (outcome, game.into_player().aborted)
+    }
+
+    #[test]
+    fn cheater_forfeits() {
+        assert_eq!(
+            outcome_after(Error::cheating(1, "Wrong share")),
+            (
+                GameOutcome::OpponentForfeited {
+                    player: 1,
+                    reason: "Wrong share".to_string()
+                },
+                true
+            )
+        );
+    }
+
+    #[test]
+    fn silent_opponent_is_not_blamed() {
+        assert_eq!(
+            outcome_after(Error::Timeout),
+            (
+                GameOutcome::Aborted {
+                    player: 1,
+                    reason: Error::Timeout.to_string()
+                },
+                true
+            )
+        );
+    }
+
+    #[test]
+    fn accusation_needs_evidence() {
+        let notice = AbortNotice {
+            player_id: 1,
+            reason: "Wrong share".to_string(),
+            cheater: Some(0),
+            evidence: Some(Evidence::Proof {
+                step: 0,
+                proof: serde_json::Value::Null,
+            }),
+        };
+        assert_eq!(
+            outcome_after(Error::Aborted(Box::new(notice))),
+            (
+                GameOutcome::Aborted {
+                    player: 1,
+                    reason: "Wrong share".to_string()
+                },
+                true
+            )
+        );
+    }
+
+    #[test]
+    fn follow_suit() {
+        let hand = (0..52)
+            .step_by(5)
+            .map(|ind| CardFromDeck {
+                card: Card::try_from(ind).unwrap(),
+                ind,
+            })
+            .collect::<Vec<_>>();
+        let led = Card::try_from(14).unwrap();
+        let legal = FollowSuitRules {}.legal_moves(&hand, &[led]);
+        assert!(!legal.is_empty());
+        assert!(legal.iter().all(|c| c.card.suit == led.suit));
+        assert_eq!(FollowSuitRules {}.legal_moves(&hand, &[]).len(), hand.len());
+    }
+
+    #[test]
+    fn async_games_on_one_thread() {
+        let deck = DeckSpec::short();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let outcomes = runtime.block_on(async {
+            let mut connections = vec![];
+            for _ in 0..2 {
+                connections.extend(async_connections().await.into_iter().enumerate());
+            }
+            let players = connections
+                .into_iter()
+                .map(|(player_id, connection)| {
+                    tokio::spawn(async move {
+                        let player = prepare_async_player::<AsyncDeckPreparationBasic>(
+                            connection, player_id,
+                        )
+                        .await;
+                        let mut game = TrickGame::new(
+                            SimpleRules {},
+                            player_id,
+                            2,
+                            player,
+                            MockPrinter {},
+                            MockSelector {},
+                        );
+                        game.play_to_end_async().await.unwrap()
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut outcomes = vec![];
+            for player in players {
+                outcomes.push(player.await.unwrap());
+            }
+            outcomes
+        });
+        for outcome in outcomes {
+            let GameOutcome::Finished { score, scores } = outcome else {
+                panic!("Game did not finish");
+            };
+            assert_eq!(score + scores.iter().sum::<usize>(), deck.len() / 2);
+        }
+    }
+}
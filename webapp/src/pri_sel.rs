@@ -4,7 +4,7 @@ use actix::Addr;
 use actix_web::dev::ServerHandle;
 use common::{
     cards::{Card, Suit},
-    game::{CardFromDeck, CardSelector, GamePrinter, GameState},
+    game::{CardFromDeck, CardSelector, GameOutcome, GamePrinter, GameState},
 };
 use serde::Serialize;
 
@@ -42,6 +42,16 @@ impl GamePrinter for WebInterface {
         }
         drop(lock);
     }
+
+    fn print_outcome(&mut self, outcome: &GameOutcome) {
+        println!("{}", outcome);
+        self.ws.do_send(TextMessage {
+            msg: serde_json::to_string(&SerializableOutcome {
+                outcome: outcome.to_string(),
+            })
+            .unwrap(),
+        });
+    }
 }
 
 impl CardSelector for WebInterface {
@@ -62,6 +72,11 @@ impl CardSelector for WebInterface {
     }
 }
 
+#[derive(Serialize)]
+struct SerializableOutcome {
+    outcome: String,
+}
+
 #[derive(Serialize)]
 struct SerializableGameState {
     hand: Vec<String>,
@@ -72,6 +87,13 @@ fn card_to_string(cards: &[Card]) -> Vec<String> {
     cards
         .iter()
         .map(|card| {
+            if card.is_joker() {
+                return if card.suit.is_red() {
+                    "joker_red".to_string()
+                } else {
+                    "joker_black".to_string()
+                };
+            }
             format!(
                 "{}_{}",
                 card.rank,
@@ -80,6 +102,7 @@ fn card_to_string(cards: &[Card]) -> Vec<String> {
                     Suit::Diamonds => "diamonds",
                     Suit::Hearts => "hearts",
                     Suit::Spades => "spades",
+                    Suit::Stars => "stars",
                 }
             )
         })
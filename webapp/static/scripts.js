@@ -1,6 +1,7 @@
 const $table_cards = document.getElementById("table").children
 const $cards = document.getElementById("handcards").children
 const $debug = document.getElementById("debug_div");
+const $outcome = document.getElementById("outcome");
 /** @type {WebSocket | null} */
 const socket = new WebSocket(`ws://${window.location.host}/ws`)
 
@@ -31,7 +32,10 @@ socket.onmessage = (ev) => {
         return
     }
     const msg = JSON.parse(ev.data)
-    if (typeof msg === "object") {
+    if (typeof msg === "object" && msg.outcome !== undefined) {
+        $outcome.innerText = msg.outcome
+        $outcome.style.visibility = "visible"
+    } else if (typeof msg === "object") {
         render_cards($cards, msg.hand)
         render_cards($table_cards, msg.table)
     }